@@ -0,0 +1,89 @@
+//! Allocations and time spent parsing an SGR-heavy stream, with and without recycling the
+//! dispatched parameter vectors.
+//!
+//! Run with `cargo bench -p ansi`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use ansi::{ansi::AnsiParser, vt::VTParser};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Colored `ls`-like output: every word changes colors and is reset afterwards.
+fn sgr_stream() -> Vec<u8> {
+    let mut stream = Vec::new();
+    for i in 0..10_000 {
+        stream.extend_from_slice(
+            format!(
+                "\x1B[1;38;5;{}mword\x1B[0m \x1B[38;2;1;2;{}mtext\x1B[m\r\n",
+                i % 256,
+                i % 256
+            )
+            .as_bytes(),
+        );
+    }
+    stream
+}
+
+fn measure(name: &str, stream: &[u8], mut parse: impl FnMut(&[u8])) {
+    // Warm up so that buffers reaching their final size aren't counted.
+    parse(stream);
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    parse(stream);
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+
+    println!(
+        "{name:>10}: {allocations:>7} allocations, {:>8.2} MiB/s",
+        stream.len() as f64 / elapsed.as_secs_f64() / (1024.0 * 1024.0)
+    );
+}
+
+fn main() {
+    let stream = sgr_stream();
+
+    let mut parser = VTParser::default();
+    measure("fresh", &stream, |stream| {
+        for &byte in stream {
+            black_box(parser.parse_byte(byte));
+        }
+    });
+
+    let mut parser = VTParser::default();
+    measure("recycled", &stream, |stream| {
+        for &byte in stream {
+            let action = parser.parse_byte(byte);
+            parser.recycle(black_box(action));
+        }
+    });
+
+    let mut parser = AnsiParser::new();
+    measure("AnsiParser", &stream, |stream| {
+        parser.parse(stream, |command| {
+            black_box(command);
+        })
+    });
+}
@@ -1,22 +1,12 @@
-use crate::vt::{Action, VTParser};
-
-macro_rules! parse_color {
-    ($iter:expr, $color_type:ident) => {
-        match $iter.next() {
-            Some(5) => match $iter.next() {
-                Some(color) => Some($color_type(Color::Indexed(color as u8))),
-                None => None,
-            },
-            Some(2) => match ($iter.next(), $iter.next(), $iter.next()) {
-                (Some(r), Some(g), Some(b)) => {
-                    Some($color_type(Color::RGB(r as u8, g as u8, b as u8)))
-                }
-                _ => None,
-            },
-            _ => None,
-        }
-    };
-}
+use alloc::{boxed::Box, collections::VecDeque, string::String, vec::Vec};
+use core::mem;
+
+use enum_repr_convert::ConvertRepr;
+
+use crate::{
+    control::{CsiFinal, C0},
+    vt::{Action, VTParser},
+};
 
 #[derive(Debug, PartialEq)]
 pub enum Color {
@@ -25,9 +15,26 @@ pub enum Color {
     RGB(u8, u8, u8),
 }
 
+/// RGB values of the 256 indexed colors.
+pub type Palette = [(u8, u8, u8); 256];
+
 #[derive(Debug, PartialEq)]
-pub enum Csi {
+pub enum AnsiCommand {
     Print(char),
+    /// BEL: asks for the user's attention.
+    Bell,
+    /// Moves the cursor one column to the left without erasing.
+    Backspace,
+    /// HT: moves the cursor to the next tab stop.
+    HorizontalTab,
+    /// Moves the cursor down one line, scrolling at the bottom. Also produced by VT and FF.
+    LineFeed,
+    /// Moves the cursor to the first column.
+    CarriageReturn,
+    /// XOFF (DC3): software flow control asking the terminal to stop transmitting.
+    TransmitOff,
+    /// XON (DC1): resumes transmission after [`AnsiCommand::TransmitOff`].
+    TransmitOn,
     CursorUp(u16),
     CursorDown(u16),
     CursorForward(u16),
@@ -35,7 +42,186 @@ pub enum Csi {
     CursorPosition(u16, u16),
     EraseInDisplay(u8),
     EraseInLine(u8),
+    /// IL (`CSI Ps L`): insert blank lines at the cursor row, inside the scroll region.
+    InsertLines(u16),
+    /// DL (`CSI Ps M`): delete lines at the cursor row, inside the scroll region.
+    DeleteLines(u16),
+    /// DECSED (`CSI ? Ps J`): like [`AnsiCommand::EraseInDisplay`] but skips protected cells.
+    SelectiveEraseInDisplay(u8),
+    /// DECSEL (`CSI ? Ps K`): like [`AnsiCommand::EraseInLine`] but skips protected cells.
+    SelectiveEraseInLine(u8),
+    /// DECSCA (`CSI Ps " q`): whether characters printed from now on are protected from
+    /// selective erases.
+    SetCharacterProtection(bool),
+    /// SPA (`ESC V`): start of a protected area, characters printed until
+    /// [`AnsiCommand::EndProtectedArea`] are protected from selective erases.
+    StartProtectedArea,
+    /// EPA (`ESC W`)
+    EndProtectedArea,
+    /// SCS (`ESC ( F` through `ESC + F`): designates the character set identified by the final
+    /// byte `F` into G0 to G3, e.g. `B` for ASCII and `0` for DEC Special Graphics.
+    DesignateCharset(u8, u8),
+    /// SO: G1 becomes the active character set.
+    ShiftOut,
+    /// SI: G0 becomes the active character set again.
+    ShiftIn,
+    /// SS2 (`ESC N`): the next printed character is taken from the G2 character set.
+    SingleShift2,
+    /// SS3 (`ESC O`): the next printed character is taken from the G3 character set.
+    SingleShift3,
+    /// RIS (`ESC c`): resets the terminal to its initial state.
+    ResetToInitialState,
+    /// DECSC (`ESC 7`): saves the cursor position, rendition and character set state.
+    SaveCursor,
+    /// DECRC (`ESC 8`): restores the state saved by [`AnsiCommand::SaveCursor`].
+    RestoreCursor,
+    /// IND (`ESC D`): moves the cursor down one line, scrolling at the bottom margin. Unlike
+    /// [`AnsiCommand::LineFeed`] it is not affected by LNM.
+    Index,
+    /// RI (`ESC M`): moves the cursor up one line, scrolling down at the top margin.
+    ReverseIndex,
+    /// NEL (`ESC E`): moves the cursor to the first column of the next line, scrolling at the
+    /// bottom margin.
+    NextLine,
+    /// DECKPAM (`ESC =`): the keypad sends application sequences.
+    KeypadApplicationMode,
+    /// DECKPNM (`ESC >`): the keypad sends the characters printed on its keys.
+    KeypadNumericMode,
+    /// DECSTBM (`CSI Pt ; Pb r`): top and bottom margins of the scroll region, 0 if omitted.
+    SetScrollRegion(u16, u16),
+    /// DECFRA (`CSI Pch ; Pt ; Pl ; Pb ; Pr $ x`): fills the rectangle with the character `Pch`.
+    FillRectangularArea(char, Rectangle),
+    /// DECERA (`CSI Pt ; Pl ; Pb ; Pr $ z`): erases the rectangle.
+    EraseRectangularArea(Rectangle),
+    /// DSR (`CSI Ps n`): the host asks for a report, 5 for the terminal status and 6 for the
+    /// cursor position.
+    DeviceStatusReport(u16),
+    /// XTWINOPS (`CSI Ps ; ... t`): window manipulation or report selected by `Ps`, e.g. 14 for
+    /// the size of the text area in pixels and 18 for the size in cells. Further parameters are
+    /// dropped.
+    WindowOperation(u16),
+    /// OSC 0 or 2 (`OSC 2 ; Pt ST`): sets the window title to `Pt`.
+    SetTitle(String),
+    /// OSC 7 (`OSC 7 ; file://host/path ST`): the shell's working directory changed to `path`.
+    #[cfg(feature = "std")]
+    SetWorkingDirectory(std::path::PathBuf),
+    /// XTWINOPS 22 (`CSI 22 ; Ps t`): saves the window title on a stack.
+    PushTitle,
+    /// XTWINOPS 23 (`CSI 23 ; Ps t`): restores the title saved last by
+    /// [`AnsiCommand::PushTitle`].
+    PopTitle,
+    /// DA2 (`CSI > c`): the host asks for the terminal type and version.
+    SecondaryDeviceAttributes,
+    /// XTGETTCAP (`DCS + q Pt ST`): the host asks for the values of the termcap or terminfo
+    /// capabilities named in `Pt`, hex-encoded and separated by `;`. Names that aren't valid hex
+    /// or UTF-8 are passed on empty.
+    RequestCapabilities(Vec<String>),
+    /// XTMODKEYS (`CSI > Pp ; Pv m`): sets the modifier key option `Pp`, e.g. 4 for
+    /// modifyOtherKeys, to `Pv`. Without a value the option is reset.
+    SetModifyKeys(u16, Option<u16>),
+    /// One per attribute in the sequence, or a single `Sgr(None)` if none of them is supported.
     Sgr(Option<Sgr>),
+    /// SM (`CSI Pm h`): sets an ANSI mode such as LNM (20), emitted once per mode in the
+    /// sequence.
+    SetMode(u16),
+    /// RM (`CSI Pm l`), emitted once per mode in the sequence.
+    ResetMode(u16),
+    /// DECRQM (`CSI Ps $ p`): the host asks whether the ANSI mode `Ps` is set.
+    RequestMode(u16),
+    /// DECRQM (`CSI ? Ps $ p`): the host asks whether the DEC private mode `Ps` is set.
+    DecRequestMode(PrivateMode),
+    /// DECSET (`CSI ? Pm h`), emitted once per mode in the sequence.
+    DecSetMode(PrivateMode),
+    /// DECRST (`CSI ? Pm l`), emitted once per mode in the sequence.
+    DecResetMode(PrivateMode),
+}
+
+/// Area of the rectangular operations such as [`AnsiCommand::EraseRectangularArea`]. The 1-based,
+/// inclusive bounds are passed on as received, 0 if omitted.
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+pub struct Rectangle {
+    pub top: u16,
+    pub left: u16,
+    pub bottom: u16,
+    pub right: u16,
+}
+
+impl Rectangle {
+    fn from_params(params: &[Option<i32>]) -> Self {
+        let param = |i: usize| params.get(i).copied().flatten().unwrap_or(0) as u16;
+        Self {
+            top: param(0),
+            left: param(1),
+            bottom: param(2),
+            right: param(3),
+        }
+    }
+}
+
+/// DEC private modes, set and reset with `CSI ? Pm h` and `CSI ? Pm l`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum PrivateMode {
+    /// DECCKM: cursor keys send application sequences (`ESC O A`) instead of `ESC [ A`.
+    CursorKeys,
+    /// DECOM: cursor addressing is relative to the scroll region.
+    Origin,
+    /// DECAWM: printing past the last column wraps onto the next line.
+    Autowrap,
+    /// DECTCEM: cursor is visible.
+    ShowCursor,
+    /// Report mouse button presses and releases.
+    MouseTracking,
+    /// Send mouse reports in the SGR format (`CSI < b ; x ; y M`) instead of as raw bytes.
+    SgrMouse,
+    /// Mouse wheel events are sent as cursor keys while mouse tracking is off.
+    AlternateScroll,
+    /// Mode 1047: show the alternate screen, which has no scrollback, instead of the normal one.
+    AlternateScreen,
+    /// Mode 1048: save the cursor as DECSC does when set, restore it as DECRC does when reset.
+    SaveCursor,
+    /// Mode 1049: save the cursor and switch to a cleared alternate screen, i.e. 1048 and 1047.
+    AlternateScreenSaveCursor,
+    /// Mode 2026: the program is writing a frame, which shouldn't be drawn until it's done.
+    SynchronizedOutput,
+    Other(u16),
+}
+
+impl From<PrivateMode> for u16 {
+    fn from(mode: PrivateMode) -> Self {
+        match mode {
+            PrivateMode::CursorKeys => 1,
+            PrivateMode::Origin => 6,
+            PrivateMode::Autowrap => 7,
+            PrivateMode::ShowCursor => 25,
+            PrivateMode::MouseTracking => 1000,
+            PrivateMode::SgrMouse => 1006,
+            PrivateMode::AlternateScroll => 1007,
+            PrivateMode::AlternateScreen => 1047,
+            PrivateMode::SaveCursor => 1048,
+            PrivateMode::AlternateScreenSaveCursor => 1049,
+            PrivateMode::SynchronizedOutput => 2026,
+            PrivateMode::Other(value) => value,
+        }
+    }
+}
+
+impl From<u16> for PrivateMode {
+    fn from(value: u16) -> Self {
+        match value {
+            1 => PrivateMode::CursorKeys,
+            6 => PrivateMode::Origin,
+            7 => PrivateMode::Autowrap,
+            25 => PrivateMode::ShowCursor,
+            1000 => PrivateMode::MouseTracking,
+            1006 => PrivateMode::SgrMouse,
+            1007 => PrivateMode::AlternateScroll,
+            1047 => PrivateMode::AlternateScreen,
+            1048 => PrivateMode::SaveCursor,
+            1049 => PrivateMode::AlternateScreenSaveCursor,
+            2026 => PrivateMode::SynchronizedOutput,
+            value => PrivateMode::Other(value),
+        }
+    }
 }
 
 /// this shit is not exhaustive
@@ -45,7 +231,7 @@ pub enum Sgr {
     Bold,
     Faint,
     Italic,
-    Underlined(bool),
+    Underlined(UnderlineStyle),
     Blink(BlinkInterval),
     Inverted(bool),
     Conceal(bool),
@@ -53,7 +239,6 @@ pub enum Sgr {
     PrimaryFont,
     AlternativeFont(u8),
     Fraktur,
-    DoublyUnderlined,
     Regular,
     NeitherItalicNorBlackletter,
     ProportionalSpacing(bool),
@@ -66,6 +251,19 @@ pub enum Sgr {
     UnderlineColor(Color),
 }
 
+/// Underline styles, selected with `CSI 4 : Ps m`. The discriminants are the `Ps` values.
+#[derive(Debug, Default, PartialEq, Copy, Clone, ConvertRepr)]
+#[repr(u8)]
+pub enum UnderlineStyle {
+    #[default]
+    None = 0,
+    Straight = 1,
+    Double = 2,
+    Curly = 3,
+    Dotted = 4,
+    Dashed = 5,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum BlinkInterval {
     Slow,
@@ -73,9 +271,89 @@ pub enum BlinkInterval {
     Static,
 }
 
+/// Counters collected by [`AnsiParser`] when enabled through [`AnsiParserBuilder::stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ParseStats {
+    /// Bytes fed to the parser.
+    pub bytes: usize,
+    /// Printable characters.
+    pub printed: usize,
+    /// C0 controls, whether or not they map to a command.
+    pub executed: usize,
+    /// Complete CSI and ESC sequences that didn't map to a command.
+    pub unknown_sequences: usize,
+}
+
+impl ParseStats {
+    fn record(&mut self, action: &Action) {
+        self.bytes += 1;
+        match action {
+            Action::Print(_) => self.printed += 1,
+            Action::Execute(_) => self.executed += 1,
+            _ => {}
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AnsiParserBuilder {
+    stats: bool,
+    max_string_len: Option<usize>,
+    palette: Option<Box<Palette>>,
+}
+
+impl AnsiParserBuilder {
+    /// Collect [`ParseStats`]. Off by default so the common path stays free of bookkeeping.
+    pub fn stats(mut self, enabled: bool) -> Self {
+        self.stats = enabled;
+        self
+    }
+
+    /// Longest OSC string accepted, see [`VTParser::set_max_string_len`]. Defaults to
+    /// [`DEFAULT_MAX_STRING_LEN`](crate::vt::DEFAULT_MAX_STRING_LEN).
+    pub fn max_string_len(mut self, len: usize) -> Self {
+        self.max_string_len = Some(len);
+        self
+    }
+
+    /// Resolve indexed SGR colors to their RGB value in `palette`, for consumers not keeping a
+    /// palette of their own. By default they are passed on as [`Color::Indexed`].
+    pub fn palette(mut self, palette: Palette) -> Self {
+        self.palette = Some(Box::new(palette));
+        self
+    }
+
+    pub fn build(self) -> AnsiParser {
+        let mut vt_parser = VTParser::default();
+        if let Some(len) = self.max_string_len {
+            vt_parser.set_max_string_len(len);
+        }
+        AnsiParser {
+            vt_parser,
+            stats: self.stats.then(ParseStats::default),
+            palette: self.palette,
+            ..AnsiParser::default()
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct AnsiParser {
     vt_parser: VTParser,
+    stats: Option<ParseStats>,
+    /// Commands completed by [`AnsiParser::next_command`] but not returned yet.
+    pending: VecDeque<AnsiCommand>,
+    /// Content of the OSC string being parsed.
+    osc: Vec<u8>,
+    /// Data of the XTGETTCAP request being parsed, `None` outside of one. Other device control
+    /// strings are dropped.
+    capability_request: Option<Vec<u8>>,
+    /// Indexed colors are resolved through this if set, see [`AnsiParserBuilder::palette`].
+    palette: Option<Box<Palette>>,
+    /// Bytes of the sequence being parsed by [`AnsiParser::parse_raw`], up to the
+    /// [max string length](AnsiParserBuilder::max_string_len), and the commands it produced so far.
+    raw: Vec<u8>,
+    raw_commands: Vec<AnsiCommand>,
 }
 
 impl AnsiParser {
@@ -83,104 +361,498 @@ impl AnsiParser {
         Self::default()
     }
 
+    pub fn builder() -> AnsiParserBuilder {
+        AnsiParserBuilder::default()
+    }
+
+    /// Counters collected so far, or `None` if stats weren't enabled on the builder.
+    pub fn stats(&self) -> Option<&ParseStats> {
+        self.stats.as_ref()
+    }
+
     pub fn parse<F>(&mut self, data: &[u8], mut callback: F)
     where
-        F: FnMut(Csi),
+        F: FnMut(AnsiCommand),
+    {
+        self.pending.drain(..).for_each(&mut callback);
+        for &byte in data {
+            self.advance(byte, &mut callback);
+        }
+    }
+
+    /// Like [`AnsiParser::parse`], passing each command along with the bytes it was parsed from,
+    /// e.g. for a tool showing what a program sends. Commands of the same sequence, such as the
+    /// attributes of an SGR, share its bytes, while C0 controls executed in the middle of a
+    /// sequence get just their own. Bytes not producing any command are dropped, and only the
+    /// first [max string length](AnsiParserBuilder::max_string_len) bytes of a sequence are kept.
+    pub fn parse_raw<F>(&mut self, data: &[u8], mut callback: F)
+    where
+        F: FnMut(AnsiCommand, &[u8]),
     {
+        let mut commands = mem::take(&mut self.raw_commands);
         for &byte in data {
-            let action = self.vt_parser.parse_byte(byte);
-            if let Some(command) = self.interpret_action(action) {
-                callback(command);
+            let kept = self.raw.len() < self.vt_parser.max_string_len();
+            if kept {
+                self.raw.push(byte);
+            }
+            let queued = commands.len();
+            self.advance(byte, &mut |command| commands.push(command));
+
+            if self.vt_parser.is_ground() {
+                for command in commands.drain(..) {
+                    callback(command, &self.raw);
+                }
+                self.raw.clear();
+            } else if commands.len() > queued && byte < 0x20 && byte != C0::ESCAPE {
+                if kept {
+                    self.raw.pop();
+                }
+                for command in commands.drain(queued..) {
+                    callback(command, &[byte]);
+                }
+            }
+        }
+        self.raw_commands = commands;
+    }
+
+    /// Pull-based alternative to [`AnsiParser::parse`]: consumes bytes of `data` starting at `pos`
+    /// until a command is complete and returns it, leaving `pos` after the last byte consumed.
+    ///
+    /// Returns `None` once all of `data` is consumed without completing a command; a sequence
+    /// left unfinished is continued by the next call. Sequences producing several commands, such
+    /// as SGR with multiple attributes, return them one per call without consuming more bytes.
+    pub fn next_command(&mut self, data: &[u8], pos: &mut usize) -> Option<AnsiCommand> {
+        while self.pending.is_empty() && *pos < data.len() {
+            let byte = data[*pos];
+            *pos += 1;
+            let mut pending = mem::take(&mut self.pending);
+            self.advance(byte, &mut |command| pending.push_back(command));
+            self.pending = pending;
+        }
+        self.pending.pop_front()
+    }
+
+    fn advance<F>(&mut self, byte: u8, callback: &mut F)
+    where
+        F: FnMut(AnsiCommand),
+    {
+        let action = self.vt_parser.parse_byte(byte);
+        match &mut self.stats {
+            None => self.interpret_action(&action, callback),
+            Some(stats) => {
+                stats.record(&action);
+                let dispatch = matches!(action, Action::CsiDispatch(..) | Action::EscDispatch(..));
+                let mut emitted = false;
+                self.interpret_action(&action, &mut |command| {
+                    emitted = true;
+                    callback(command);
+                });
+                if let Some(stats) = self.stats.as_mut().filter(|_| dispatch && !emitted) {
+                    stats.unknown_sequences += 1;
+                }
             }
         }
+        // Commands don't borrow from the action, so its parameters can be reused right away.
+        self.vt_parser.recycle(action);
     }
 
-    fn interpret_action(&self, action: Action) -> Option<Csi> {
-        use Csi::*;
+    /// Turns a parser action into commands:
+    ///
+    /// | Action                       | Commands                                                |
+    /// |------------------------------|---------------------------------------------------------|
+    /// | `Print`                      | [`AnsiCommand::Print`]                                  |
+    /// | `Execute` of BEL             | [`AnsiCommand::Bell`]                                   |
+    /// | `Execute` of BS              | [`AnsiCommand::Backspace`]                              |
+    /// | `Execute` of HT              | [`AnsiCommand::HorizontalTab`]                          |
+    /// | `Execute` of LF, VT and FF   | [`AnsiCommand::LineFeed`]                               |
+    /// | `Execute` of CR              | [`AnsiCommand::CarriageReturn`]                         |
+    /// | `Execute` of SO and SI       | [`AnsiCommand::ShiftOut`], [`AnsiCommand::ShiftIn`]     |
+    /// | `Execute` of DC3 and DC1     | [`AnsiCommand::TransmitOff`], [`AnsiCommand::TransmitOn`] |
+    /// | `CsiDispatch`                | see [`CsiFinal`], unknown sequences produce nothing     |
+    /// | `EscDispatch`                | SCS and the sequences listed at `interpret_esc`         |
+    /// | `OscStart`, `OscPut`         | nothing, the string is collected                        |
+    /// | `OscEnd`                     | title and working directory                             |
+    /// | `OscAbort`                   | nothing, the collected string is dropped                |
+    /// | `Hook`, `Put`                | nothing, the data of XTGETTCAP is collected             |
+    /// | `Unhook`                     | [`AnsiCommand::RequestCapabilities`] for XTGETTCAP      |
+    ///
+    /// Everything else has no visible effect and produces nothing: the other C0 controls
+    /// (NUL, ENQ, CAN, SUB and the transmission controls), `Clear` and `CollectParam`, which only
+    /// update the parser's state, and other device control strings, which aren't supported.
+    fn interpret_action<F>(&mut self, action: &Action, callback: &mut F)
+    where
+        F: FnMut(AnsiCommand),
+    {
+        use AnsiCommand::*;
 
-        match action {
-            Action::Print(c) => Some(Print(c)),
-            Action::CsiDispatch(byte, params) => {
-                let p1 = params.first().copied().flatten().unwrap_or(1) as u16;
-
-                match byte {
-                    b'A' => Some(CursorUp(p1)),
-                    b'B' => Some(CursorDown(p1)),
-                    b'C' => Some(CursorForward(p1)),
-                    b'D' => Some(CursorBackward(p1)),
-                    b'H' | b'f' => {
-                        let row = p1;
-                        let col = params.get(1).copied().flatten().unwrap_or(1) as u16;
-
-                        Some(CursorPosition(row, col))
+        match *action {
+            Action::Print(c) => callback(Print(c)),
+            Action::Execute(C0::ALERT) => callback(Bell),
+            Action::Execute(C0::BACKSPACE) => callback(Backspace),
+            Action::Execute(C0::CHARACTER_TABULATION) => callback(HorizontalTab),
+            Action::OscStart => self.osc.clear(),
+            Action::OscPut(byte) => self.osc.push(byte),
+            Action::OscEnd => {
+                if let Some(command) = self.interpret_osc() {
+                    callback(command);
+                }
+            }
+            // Give back the memory taken by the oversized string.
+            Action::OscAbort => self.osc = Vec::new(),
+            Action::Hook(b'q', _, ref intermediates) if *intermediates == [b'+'] => {
+                self.capability_request = Some(Vec::new());
+            }
+            Action::Put(byte) => {
+                if let Some(request) = &mut self.capability_request {
+                    request.push(byte);
+                }
+            }
+            Action::Unhook => {
+                if let Some(request) = self.capability_request.take() {
+                    let names = request
+                        .split(|&byte| byte == b';')
+                        .map(|name| {
+                            crate::dcs::decode_hex(name)
+                                .ok()
+                                .and_then(|name| String::from_utf8(name).ok())
+                                .unwrap_or_default()
+                        })
+                        .collect();
+                    callback(RequestCapabilities(names));
+                }
+            }
+            Action::Execute(C0::LINE_FEED | C0::LINE_TABULATION | C0::FORM_FEED) => {
+                callback(LineFeed)
+            }
+            Action::Execute(C0::CARRIAGE_RETURN) => callback(CarriageReturn),
+            Action::Execute(C0::SHIFT_OUT) => callback(ShiftOut),
+            Action::Execute(C0::SHIFT_IN) => callback(ShiftIn),
+            Action::Execute(C0::DEVICE_CONTROL_THREE) => callback(TransmitOff),
+            Action::Execute(C0::DEVICE_CONTROL_ONE) => callback(TransmitOn),
+            Action::CsiDispatch(byte, ref params, ref intermediates) => {
+                if let Ok(final_byte) = CsiFinal::try_from(byte) {
+                    self.interpret_csi(final_byte, params, intermediates, callback);
+                }
+            }
+            // SCS: the intermediate selects G0 to G3, the final byte the character set.
+            Action::EscDispatch(charset, ref intermediates)
+                if matches!(intermediates[..], [b'('..=b'+']) =>
+            {
+                callback(DesignateCharset(intermediates[0] - b'(', charset))
+            }
+            Action::EscDispatch(byte, ref intermediates) if intermediates.is_empty() => {
+                if let Some(command) = self.interpret_esc(byte) {
+                    callback(command);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn interpret_osc(&self) -> Option<AnsiCommand> {
+        use AnsiCommand::*;
+
+        let (command, text) = match self.osc.iter().position(|&byte| byte == b';') {
+            Some(i) => (&self.osc[..i], &self.osc[i + 1..]),
+            None => (&self.osc[..], &[][..]),
+        };
+        match command {
+            // 1 sets the icon name, which has no use without icons.
+            b"0" | b"2" => Some(SetTitle(String::from_utf8_lossy(text).into_owned())),
+            // Malformed URLs are dropped rather than reported with a wrong directory.
+            #[cfg(feature = "std")]
+            b"7" => {
+                let path = crate::osc::decode_file_url(text).ok()?;
+                #[cfg(unix)]
+                let path: std::ffi::OsString = std::os::unix::ffi::OsStringExt::from_vec(path);
+                #[cfg(not(unix))]
+                let path = String::from_utf8_lossy(&path).into_owned();
+                Some(SetWorkingDirectory(path.into()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Commands of escape sequences without intermediates: DECSC, DECRC, IND, RI, NEL, SS2, SS3,
+    /// SPA, EPA, RIS, DECKPAM and DECKPNM.
+    fn interpret_esc(&self, byte: u8) -> Option<AnsiCommand> {
+        use AnsiCommand::*;
+
+        match byte {
+            b'7' => Some(SaveCursor),
+            b'8' => Some(RestoreCursor),
+            b'D' => Some(Index),
+            b'M' => Some(ReverseIndex),
+            b'E' => Some(NextLine),
+            b'N' => Some(SingleShift2),
+            b'O' => Some(SingleShift3),
+            b'V' => Some(StartProtectedArea),
+            b'W' => Some(EndProtectedArea),
+            b'c' => Some(ResetToInitialState),
+            b'=' => Some(KeypadApplicationMode),
+            b'>' => Some(KeypadNumericMode),
+            _ => None,
+        }
+    }
+
+    fn interpret_csi<F>(
+        &self,
+        final_byte: CsiFinal,
+        params: &[Option<i32>],
+        intermediates: &[u8],
+        callback: &mut F,
+    ) where
+        F: FnMut(AnsiCommand),
+    {
+        use AnsiCommand::*;
+
+        let p1 = params.first().copied().flatten().unwrap_or(1) as u16;
+        let p0 = params.first().copied().flatten().unwrap_or(0) as u16;
+
+        let command = match (final_byte, intermediates) {
+            (CsiFinal::SetMode | CsiFinal::ResetMode, [b'?']) => {
+                for mode in params.iter().flatten() {
+                    let mode = PrivateMode::from(*mode as u16);
+                    callback(if final_byte == CsiFinal::SetMode {
+                        DecSetMode(mode)
+                    } else {
+                        DecResetMode(mode)
+                    });
+                }
+                return;
+            }
+            (CsiFinal::SetMode | CsiFinal::ResetMode, []) => {
+                for mode in params.iter().flatten() {
+                    let mode = *mode as u16;
+                    callback(if final_byte == CsiFinal::SetMode {
+                        SetMode(mode)
+                    } else {
+                        ResetMode(mode)
+                    });
+                }
+                return;
+            }
+            (CsiFinal::RequestMode, [b'$'] | [b'?', b'$']) => {
+                params.first().copied().flatten().map(|mode| {
+                    let mode = mode as u16;
+                    if intermediates[0] == b'?' {
+                        DecRequestMode(PrivateMode::from(mode))
+                    } else {
+                        RequestMode(mode)
+                    }
+                })
+            }
+            // Pch must be a printable character of ISO Latin-1.
+            (CsiFinal::FillRectangularArea, [b'$']) => {
+                let ch = params.first().copied().flatten().unwrap_or(0);
+                match char::from_u32(ch as u32) {
+                    Some(ch @ (' '..='~' | '\u{A0}'..='\u{FF}')) => {
+                        let area = Rectangle::from_params(params.get(1..).unwrap_or_default());
+                        Some(FillRectangularArea(ch, area))
                     }
-                    b'J' => Some(EraseInDisplay(p1 as u8)),
-                    b'K' => Some(EraseInLine(p1 as u8)),
-                    b'm' => Some(Csi::Sgr(self.interpret_sgr(&params))),
                     _ => None,
                 }
             }
-            _ => None,
+            (CsiFinal::EraseRectangularArea, [b'$']) => {
+                Some(EraseRectangularArea(Rectangle::from_params(params)))
+            }
+            (CsiFinal::EraseInDisplay, [b'?']) => Some(SelectiveEraseInDisplay(p0 as u8)),
+            (CsiFinal::EraseInLine, [b'?']) => Some(SelectiveEraseInLine(p0 as u8)),
+            // Ps of 1 protects, 0 and 2 don't.
+            (CsiFinal::SelectCharacterProtection, [b'"']) => Some(SetCharacterProtection(
+                params.first().copied().flatten() == Some(1),
+            )),
+            (CsiFinal::DeviceAttributes, [b'>']) => Some(SecondaryDeviceAttributes),
+            (CsiFinal::SelectGraphicRendition, [b'>']) => {
+                params.first().copied().flatten().map(|resource| {
+                    let value = params.get(1).copied().flatten().map(|value| value as u16);
+                    SetModifyKeys(resource as u16, value)
+                })
+            }
+            (_, [_, ..]) => None,
+            (CsiFinal::SelectGraphicRendition, []) => return self.interpret_sgr(params, callback),
+            (CsiFinal::CursorUp, []) => Some(CursorUp(p1)),
+            (CsiFinal::CursorDown, []) => Some(CursorDown(p1)),
+            (CsiFinal::CursorForward, []) => Some(CursorForward(p1)),
+            (CsiFinal::CursorBackward, []) => Some(CursorBackward(p1)),
+            // HVP is a format effector but behaves identically to CUP. Zero and out-of-range
+            // values are passed through as-is; clamping is up to the consumer since only it
+            // knows the screen dimensions.
+            (CsiFinal::CursorPosition | CsiFinal::HorizontalVerticalPosition, []) => {
+                let row = p1;
+                let col = params.get(1).copied().flatten().unwrap_or(1) as u16;
+
+                Some(CursorPosition(row, col))
+            }
+            // Unlike the cursor movements, the erase modes default to 0.
+            (CsiFinal::EraseInDisplay, []) => Some(EraseInDisplay(p0 as u8)),
+            (CsiFinal::EraseInLine, []) => Some(EraseInLine(p0 as u8)),
+            (CsiFinal::InsertLine, []) => Some(InsertLines(p1)),
+            (CsiFinal::DeleteLine, []) => Some(DeleteLines(p1)),
+            (CsiFinal::DeviceStatusReport, []) => Some(DeviceStatusReport(p0)),
+            // The second parameter of 22 and 23 selects the icon name (1), the title (2) or
+            // both (0).
+            (CsiFinal::WindowOperation, []) => match params.first().copied().flatten() {
+                Some(22 | 23) if params.get(1).copied().flatten() == Some(1) => None,
+                Some(22) => Some(PushTitle),
+                Some(23) => Some(PopTitle),
+                Some(n) => Some(WindowOperation(n as u16)),
+                Option::None => None,
+            },
+            (CsiFinal::SetTopBottomMargins, []) => {
+                let bottom = params.get(1).copied().flatten().unwrap_or(0) as u16;
+                Some(SetScrollRegion(p0, bottom))
+            }
+            (
+                CsiFinal::DeviceAttributes
+                | CsiFinal::RequestMode
+                | CsiFinal::SelectCharacterProtection
+                | CsiFinal::FillRectangularArea
+                | CsiFinal::EraseRectangularArea,
+                [],
+            ) => None,
+        };
+        if let Some(command) = command {
+            callback(command);
         }
     }
 
-    fn interpret_sgr(&self, params: &[Option<i32>]) -> Option<Sgr> {
+    fn interpret_sgr<F>(&self, params: &[Option<i32>], callback: &mut F)
+    where
+        F: FnMut(AnsiCommand),
+    {
         use Sgr::*;
 
         if params.is_empty() {
-            return Some(Reset);
+            return callback(AnsiCommand::Sgr(Some(Reset)));
+        }
+
+        let mut emitted = false;
+        let mut i = 0;
+        while i < params.len() {
+            let code = params[i].unwrap_or(0);
+            let mut end = i + 1;
+            while end < params.len() && self.vt_parser.is_subparam(end) {
+                end += 1;
+            }
+            let subparams = &params[i + 1..end];
+
+            let sgr = match code {
+                0 => Some(Reset),
+                1 => Some(Bold),
+                2 => Some(Faint),
+                3 => Some(Italic),
+                4 => match subparams.first() {
+                    Option::None => Some(Underlined(UnderlineStyle::Straight)),
+                    Some(style) => u8::try_from(style.unwrap_or(0))
+                        .ok()
+                        .and_then(|style| UnderlineStyle::try_from(style).ok())
+                        .map(Underlined),
+                },
+                5 => Some(Blink(BlinkInterval::Slow)),
+                6 => Some(Blink(BlinkInterval::Rapid)),
+                7 => Some(Inverted(true)),
+                8 => Some(Conceal(true)),
+                9 => Some(CrossedOut(true)),
+                10 => Some(PrimaryFont),
+                11..=19 => Some(AlternativeFont((code - 10) as u8)),
+                20 => Some(Fraktur),
+                21 => Some(Underlined(UnderlineStyle::Double)),
+                22 => Some(Regular),
+                23 => Some(NeitherItalicNorBlackletter),
+                24 => Some(Underlined(UnderlineStyle::None)),
+                25 => Some(Blink(BlinkInterval::Static)),
+                26 => Some(ProportionalSpacing(true)),
+                27 => Some(Inverted(false)),
+                28 => Some(Conceal(false)),
+                29 => Some(CrossedOut(false)),
+                30..=37 => Some(ForegroundColor(Color::Indexed((code - 30) as u8))),
+                39 => Some(ForegroundColor(Color::Default)),
+                40..=47 => Some(BackgroundColor(Color::Indexed((code - 40) as u8))),
+                49 => Some(BackgroundColor(Color::Default)),
+                50 => Some(ProportionalSpacing(false)),
+                51 => Some(Framed),
+                52 => Some(Encircled),
+                53 => Some(Overlined(true)),
+                54 => Some(NeitherFramedNorEncircled),
+                55 => Some(Overlined(false)),
+                38 | 48 | 58 => {
+                    // Without sub-parameters the color is given by the parameters that follow.
+                    let color = if subparams.is_empty() {
+                        let (color, len) = parse_color(&params[end..], false);
+                        end += len;
+                        color
+                    } else {
+                        parse_color(subparams, true).0
+                    };
+                    color.map(match code {
+                        38 => ForegroundColor,
+                        48 => BackgroundColor,
+                        _ => UnderlineColor,
+                    })
+                }
+                59 => Some(UnderlineColor(Color::Default)),
+                _ => Option::None,
+            };
+
+            if let Some(sgr) = sgr {
+                emitted = true;
+                callback(AnsiCommand::Sgr(Some(self.resolve_color(sgr))));
+            }
+            i = end;
         }
 
-        let mut iter = params.iter().flatten().copied();
-        while let Some(code) = iter.next() {
-            match code {
-                0 => return Some(Reset),
-                1 => return Some(Bold),
-                2 => return Some(Faint),
-                3 => return Some(Italic),
-                4 => return Some(Underlined(true)),
-                5 => return Some(Blink(BlinkInterval::Slow)),
-                6 => return Some(Blink(BlinkInterval::Rapid)),
-                7 => return Some(Inverted(true)),
-                8 => return Some(Conceal(true)),
-                9 => return Some(CrossedOut(true)),
-                10 => return Some(PrimaryFont),
-                11..=19 => return Some(AlternativeFont((code - 10) as u8)),
-                20 => return Some(Fraktur),
-                21 => return Some(DoublyUnderlined),
-                22 => return Some(Regular),
-                23 => return Some(NeitherItalicNorBlackletter),
-                24 => return Some(Underlined(false)),
-                25 => return Some(Blink(BlinkInterval::Static)),
-                26 => return Some(ProportionalSpacing(true)),
-                27 => return Some(Inverted(false)),
-                28 => return Some(Conceal(false)),
-                29 => return Some(CrossedOut(false)),
-                30..=37 => return Some(ForegroundColor(Color::Indexed((code - 30) as u8))),
-                38 => return parse_color!(iter, ForegroundColor),
-                39 => return Some(ForegroundColor(Color::Default)),
-                40..=47 => return Some(BackgroundColor(Color::Indexed((code - 40) as u8))),
-                48 => return parse_color!(iter, BackgroundColor),
-                49 => return Some(BackgroundColor(Color::Default)),
-                50 => return Some(ProportionalSpacing(false)),
-                51 => return Some(Framed),
-                52 => return Some(Encircled),
-                53 => return Some(Overlined(true)),
-                54 => return Some(NeitherFramedNorEncircled),
-                55 => return Some(Overlined(false)),
-                58 => return parse_color!(iter, UnderlineColor),
-                59 => return Some(UnderlineColor(Color::Default)),
-                _ => {}
+        if !emitted {
+            callback(AnsiCommand::Sgr(None));
+        }
+    }
+
+    /// Replaces an indexed color set by `sgr` with its RGB value if a palette is configured.
+    fn resolve_color(&self, sgr: Sgr) -> Sgr {
+        let Some(palette) = &self.palette else {
+            return sgr;
+        };
+        let resolve = |color| match color {
+            Color::Indexed(index) => {
+                let (r, g, b) = palette[index as usize];
+                Color::RGB(r, g, b)
+            }
+            color => color,
+        };
+        match sgr {
+            Sgr::ForegroundColor(color) => Sgr::ForegroundColor(resolve(color)),
+            Sgr::BackgroundColor(color) => Sgr::BackgroundColor(resolve(color)),
+            Sgr::UnderlineColor(color) => Sgr::UnderlineColor(resolve(color)),
+            sgr => sgr,
+        }
+    }
+}
+
+/// Parses the color selected by SGR 38, 48 and 58: `5;n` for an indexed color or `2;r;g;b` for
+/// RGB. The colon form may put a color space id before the components, as in `2::r:g:b`.
+///
+/// Returns the color and the number of values it took up.
+fn parse_color(values: &[Option<i32>], colon: bool) -> (Option<Color>, usize) {
+    let value = |i: usize| values.get(i).copied().flatten().unwrap_or(0) as u8;
+
+    match values.first().copied().flatten() {
+        Some(5) if values.len() >= 2 => (Some(Color::Indexed(value(1))), 2),
+        Some(2) => {
+            let offset = if colon && values.len() >= 5 { 2 } else { 1 };
+            if values.len() < offset + 3 {
+                return (None, values.len());
             }
+            let color = Color::RGB(value(offset), value(offset + 1), value(offset + 2));
+            (Some(color), offset + 3)
         }
-        None
+        _ => (None, values.len().min(1)),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use Csi::*;
+    use AnsiCommand::*;
 
     #[test]
     fn simple_text() {
@@ -228,39 +900,730 @@ mod tests {
     }
 
     #[test]
-    fn colors() {
+    fn insert_and_delete_lines() {
         let mut parser = AnsiParser::new();
         let mut output = vec![];
 
-        parser.parse(
-            b"\
-            \x1B[31m\
-            \x1B[38;5;123m\
-            \x1B[38;2;1;12;123m\
-            \x1B[39m\
-            \x1B[41m\
-            \x1B[48;5;123m\
-            \x1B[48;2;1;12;123m\
-            \x1B[49m\
-            ",
-            |cmd| output.push(cmd),
+        parser.parse(b"\x1B[L\x1B[3L\x1B[M\x1B[2M", |cmd| output.push(cmd));
+
+        assert_eq!(
+            output,
+            vec![
+                InsertLines(1),
+                InsertLines(3),
+                DeleteLines(1),
+                DeleteLines(2)
+            ]
+        );
+    }
+
+    #[test]
+    fn backspace() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"a\x08", |cmd| output.push(cmd));
+
+        assert_eq!(output, vec![Print('a'), Backspace]);
+    }
+
+    #[test]
+    fn horizontal_tab() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        // Executed right away when it appears inside a sequence.
+        parser.parse(b"a\t\x1B[2\tA", |cmd| output.push(cmd));
+
+        assert_eq!(
+            output,
+            vec![Print('a'), HorizontalTab, HorizontalTab, CursorUp(2)]
+        );
+    }
+
+    #[test]
+    fn controls_without_visible_effect() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x00\x05\x1B[1\x18\x1A\x7F", |cmd| output.push(cmd));
+
+        assert_eq!(output, vec![]);
+    }
+
+    #[test]
+    fn line_breaks() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\r\n\x0B\x0C", |cmd| output.push(cmd));
+
+        assert_eq!(output, vec![CarriageReturn, LineFeed, LineFeed, LineFeed]);
+    }
+
+    #[test]
+    fn flow_control() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x13a\x11", |cmd| output.push(cmd));
+
+        assert_eq!(output, vec![TransmitOff, Print('a'), TransmitOn]);
+    }
+
+    #[test]
+    fn erase() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x1B[J\x1B[1K\x1B[?J\x1B[?2K", |cmd| output.push(cmd));
+
+        assert_eq!(
+            output,
+            vec![
+                EraseInDisplay(0),
+                EraseInLine(1),
+                SelectiveEraseInDisplay(0),
+                SelectiveEraseInLine(2),
+            ]
         );
+    }
+
+    #[test]
+    fn character_protection() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x1B[1\"q\x1B[\"q\x1B[2\"q\x1BV\x1BW", |cmd| {
+            output.push(cmd)
+        });
 
         assert_eq!(
             output,
             vec![
-                Sgr(Some(crate::ansi::Sgr::ForegroundColor(Color::Indexed(1)))),
-                Sgr(Some(crate::ansi::Sgr::ForegroundColor(Color::Indexed(123)))),
-                Sgr(Some(crate::ansi::Sgr::ForegroundColor(Color::RGB(
-                    1, 12, 123
-                )))),
-                Sgr(Some(crate::ansi::Sgr::ForegroundColor(Color::Default))),
-                Sgr(Some(crate::ansi::Sgr::BackgroundColor(Color::Indexed(1)))),
-                Sgr(Some(crate::ansi::Sgr::BackgroundColor(Color::Indexed(123)))),
-                Sgr(Some(crate::ansi::Sgr::BackgroundColor(Color::RGB(
-                    1, 12, 123
-                )))),
-                Sgr(Some(crate::ansi::Sgr::BackgroundColor(Color::Default))),
+                SetCharacterProtection(true),
+                SetCharacterProtection(false),
+                SetCharacterProtection(false),
+                StartProtectedArea,
+                EndProtectedArea,
+            ]
+        );
+    }
+
+    #[test]
+    fn scroll_region() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x1B[2;10r\x1B[r", |cmd| output.push(cmd));
+
+        assert_eq!(output, vec![SetScrollRegion(2, 10), SetScrollRegion(0, 0)]);
+    }
+
+    #[test]
+    fn device_status_report() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x1B[5n\x1B[6n", |cmd| output.push(cmd));
+
+        assert_eq!(output, vec![DeviceStatusReport(5), DeviceStatusReport(6)]);
+    }
+
+    #[test]
+    fn next_command() {
+        let mut parser = AnsiParser::new();
+        let data = b"a\x1B[1;3mb\x1B[2";
+        let mut pos = 0;
+
+        assert_eq!(parser.next_command(data, &mut pos), Some(Print('a')));
+        assert_eq!(pos, 1);
+        assert_eq!(
+            parser.next_command(data, &mut pos),
+            Some(Sgr(Some(crate::ansi::Sgr::Bold)))
+        );
+        assert_eq!(pos, 7);
+        assert_eq!(
+            parser.next_command(data, &mut pos),
+            Some(Sgr(Some(crate::ansi::Sgr::Italic)))
+        );
+        assert_eq!(pos, 7);
+        assert_eq!(parser.next_command(data, &mut pos), Some(Print('b')));
+        assert_eq!(parser.next_command(data, &mut pos), None);
+        assert_eq!(pos, data.len());
+
+        let mut pos = 0;
+        assert_eq!(parser.next_command(b"J", &mut pos), Some(EraseInDisplay(2)));
+        assert_eq!(parser.next_command(b"J", &mut pos), None);
+    }
+
+    #[test]
+    fn designate_charset() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x1B(0\x1B)B\x1B+0", |cmd| output.push(cmd));
+
+        assert_eq!(
+            output,
+            vec![
+                DesignateCharset(0, b'0'),
+                DesignateCharset(1, b'B'),
+                DesignateCharset(3, b'0'),
+            ]
+        );
+    }
+
+    #[test]
+    fn bell() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"a\x07\x1B]2;t\x07", |cmd| output.push(cmd));
+
+        assert_eq!(output, vec![Print('a'), Bell, SetTitle("t".into())]);
+    }
+
+    #[test]
+    fn locking_shifts() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x0Ea\x0F", |cmd| output.push(cmd));
+
+        assert_eq!(output, vec![ShiftOut, Print('a'), ShiftIn]);
+    }
+
+    #[test]
+    fn single_shifts() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x1BNa\x1BOb", |cmd| output.push(cmd));
+
+        assert_eq!(
+            output,
+            vec![SingleShift2, Print('a'), SingleShift3, Print('b')]
+        );
+    }
+
+    #[test]
+    fn ansi_modes() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x1B[20h\x1B[20;4l", |cmd| output.push(cmd));
+
+        assert_eq!(output, vec![SetMode(20), ResetMode(20), ResetMode(4)]);
+
+        output.clear();
+        parser.parse(b"\x1B[4h\x1B[?4h", |cmd| output.push(cmd));
+
+        assert_eq!(output, vec![SetMode(4), DecSetMode(PrivateMode::Other(4))]);
+    }
+
+    #[test]
+    fn request_capabilities() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x1BP+q436F;6B63757531\x1B\\\x1BP+q4;zz\x9C", |cmd| {
+            output.push(cmd)
+        });
+        // Other device control strings are dropped.
+        parser.parse(b"\x1BP$qm\x1B\\a", |cmd| output.push(cmd));
+
+        assert_eq!(
+            output,
+            vec![
+                RequestCapabilities(vec!["Co".into(), "kcuu1".into()]),
+                RequestCapabilities(vec!["".into(), "".into()]),
+                Print('a'),
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_osc_is_bounded() {
+        let mut parser = AnsiParser::builder().max_string_len(64).build();
+        let mut output = vec![];
+        let mut data = b"\x1B]2;".to_vec();
+        data.resize(100_000, b'a');
+
+        parser.parse(&data, |cmd| output.push(cmd));
+        assert_eq!(parser.osc.capacity(), 0);
+        // The rest of the string is dropped rather than printed.
+        assert_eq!(output, vec![]);
+
+        parser.parse(b"\x07\x1B[1mb\x1B]2;t\x07", |cmd| output.push(cmd));
+        assert_eq!(
+            output,
+            vec![
+                Sgr(Some(crate::ansi::Sgr::Bold)),
+                Print('b'),
+                SetTitle("t".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_indexed_colors() {
+        let mut palette = [(0, 0, 0); 256];
+        palette[196] = (255, 0, 0);
+        palette[1] = (205, 0, 0);
+        let data = b"\x1B[38;5;196;41;58:5:196;39m";
+
+        let mut deferred = vec![];
+        AnsiParser::new().parse(data, |cmd| deferred.push(cmd));
+        assert_eq!(
+            deferred,
+            vec![
+                Sgr(Some(crate::ansi::Sgr::ForegroundColor(Color::Indexed(196)))),
+                Sgr(Some(crate::ansi::Sgr::BackgroundColor(Color::Indexed(1)))),
+                Sgr(Some(crate::ansi::Sgr::UnderlineColor(Color::Indexed(196)))),
+                Sgr(Some(crate::ansi::Sgr::ForegroundColor(Color::Default))),
+            ]
+        );
+
+        let mut resolved = vec![];
+        let mut parser = AnsiParser::builder().palette(palette).build();
+        parser.parse(data, |cmd| resolved.push(cmd));
+        assert_eq!(
+            resolved,
+            vec![
+                Sgr(Some(crate::ansi::Sgr::ForegroundColor(Color::RGB(
+                    255, 0, 0
+                )))),
+                Sgr(Some(crate::ansi::Sgr::BackgroundColor(Color::RGB(
+                    205, 0, 0
+                )))),
+                Sgr(Some(crate::ansi::Sgr::UnderlineColor(Color::RGB(
+                    255, 0, 0
+                )))),
+                Sgr(Some(crate::ansi::Sgr::ForegroundColor(Color::Default))),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignored_strings() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x1B^private [1m\x1B\\a\x1B_app\x1B\\\x1B[2Jb", |cmd| {
+            output.push(cmd)
+        });
+
+        assert_eq!(output, vec![Print('a'), EraseInDisplay(2), Print('b')]);
+    }
+
+    #[test]
+    fn set_title() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(
+            b"\x1B]0;vim\x07\x1B]2;\xC3\xA9t\xC3\xA9\x1B\\\x1B]1;icon\x07\x1B]2\x07",
+            |cmd| output.push(cmd),
+        );
+
+        assert_eq!(
+            output,
+            vec![
+                SetTitle("vim".into()),
+                SetTitle("été".into()),
+                SetTitle("".into()),
+            ]
+        );
+
+        // The second byte of `ќ` is 0x9C, which only ends the string outside a character.
+        output.clear();
+        parser.parse("\x1B]2;ќ\x07a\x1B]2;b\u{9C}".as_bytes(), |cmd| {
+            output.push(cmd)
+        });
+        parser.parse(b"c\x9C", |cmd| output.push(cmd));
+        assert_eq!(
+            output,
+            vec![
+                SetTitle("ќ".into()),
+                Print('a'),
+                SetTitle("b\u{9C}c".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn working_directory() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(
+            b"\x1B]7;file://host/home/a%20b\x07\x1B]7;/home\x07\x1B]7;file://host\x1B\\",
+            |cmd| output.push(cmd),
+        );
+
+        assert_eq!(output, vec![SetWorkingDirectory("/home/a b".into())]);
+    }
+
+    #[test]
+    fn raw_bytes_are_bounded() {
+        let mut parser = AnsiParser::builder().max_string_len(64).build();
+        let mut output = vec![];
+        let mut data = b"\x1B]2;".to_vec();
+        data.resize(100_000, b'a');
+
+        parser.parse_raw(&data, |cmd, raw| output.push((cmd, raw.to_vec())));
+        assert_eq!(parser.raw.len(), 64);
+        assert!(parser.raw.capacity() <= 128);
+
+        parser.parse_raw(b"\x07\x1B[1m", |cmd, raw| output.push((cmd, raw.to_vec())));
+        assert_eq!(
+            output,
+            vec![(Sgr(Some(crate::ansi::Sgr::Bold)), b"\x1B[1m".to_vec())]
+        );
+    }
+
+    #[test]
+    fn raw_bytes_of_commands() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+        let input = b"a\x1B[1;31mb\x1B[2\x08J\x1B]2;t\x1B\\\x1B[99y\xC3\xA9\x1B[";
+
+        parser.parse_raw(input, |cmd, raw| output.push((cmd, raw.to_vec())));
+        parser.parse_raw(b"A", |cmd, raw| output.push((cmd, raw.to_vec())));
+
+        let raw: Vec<&[u8]> = output.iter().map(|(_, raw)| &raw[..]).collect();
+        assert_eq!(
+            raw,
+            vec![
+                &b"a"[..],
+                b"\x1B[1;31m",
+                b"\x1B[1;31m",
+                b"b",
+                b"\x08",
+                b"\x1B[2J",
+                b"\x1B]2;t\x1B\\",
+                "é".as_bytes(),
+                b"\x1B[A",
+            ]
+        );
+        assert_eq!(output[1].0, Sgr(Some(crate::ansi::Sgr::Bold)));
+        assert_eq!(output[5].0, EraseInDisplay(2));
+        assert_eq!(output[7].0, Print('é'));
+        assert_eq!(output[8].0, CursorUp(1));
+    }
+
+    #[test]
+    fn title_stack() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x1B[22;0t\x1B[22;2t\x1B[22;1t\x1B[23t", |cmd| {
+            output.push(cmd)
+        });
+
+        assert_eq!(output, vec![PushTitle, PushTitle, PopTitle]);
+    }
+
+    #[test]
+    fn request_mode() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x1B[?25$p\x1B[20$p\x1B[?$p", |cmd| output.push(cmd));
+
+        assert_eq!(
+            output,
+            vec![DecRequestMode(PrivateMode::ShowCursor), RequestMode(20)]
+        );
+    }
+
+    #[test]
+    fn rectangular_areas() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x1B[35;2;3;4;5$x\x1B[7$x\x1B[$z\x1B[1;2;3;4$z", |cmd| {
+            output.push(cmd)
+        });
+
+        assert_eq!(
+            output,
+            vec![
+                FillRectangularArea(
+                    '#',
+                    Rectangle {
+                        top: 2,
+                        left: 3,
+                        bottom: 4,
+                        right: 5
+                    }
+                ),
+                EraseRectangularArea(Rectangle::default()),
+                EraseRectangularArea(Rectangle {
+                    top: 1,
+                    left: 2,
+                    bottom: 3,
+                    right: 4
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn window_operation() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x1B[18t\x1B[14;2t\x1B[t", |cmd| output.push(cmd));
+
+        assert_eq!(output, vec![WindowOperation(18), WindowOperation(14)]);
+    }
+
+    #[test]
+    fn greater_than_marker() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x1B[>4;1m\x1B[>4m\x1B[>c\x1B[=c", |cmd| output.push(cmd));
+
+        assert_eq!(
+            output,
+            vec![
+                SetModifyKeys(4, Some(1)),
+                SetModifyKeys(4, None),
+                SecondaryDeviceAttributes,
+            ]
+        );
+    }
+
+    #[test]
+    fn escape_sequences() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x1B7\x1B8\x1BD\x1BM\x1BE\x1B=\x1B>\x1Bc", |cmd| {
+            output.push(cmd)
+        });
+
+        assert_eq!(
+            output,
+            vec![
+                SaveCursor,
+                RestoreCursor,
+                Index,
+                ReverseIndex,
+                NextLine,
+                KeypadApplicationMode,
+                KeypadNumericMode,
+                ResetToInitialState,
+            ]
+        );
+    }
+
+    #[test]
+    fn escape_sequences_with_intermediates() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        // SCS for G0 and G1, then DECALN and others that aren't supported.
+        parser.parse(b"\x1B(0\x1B)B\x1B#8\x1B 7\x1B(7", |cmd| output.push(cmd));
+
+        assert_eq!(
+            output,
+            vec![
+                DesignateCharset(0, b'0'),
+                DesignateCharset(1, b'B'),
+                DesignateCharset(0, b'7'),
+            ]
+        );
+    }
+
+    #[test]
+    fn reset_to_initial_state() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x1Bc", |cmd| output.push(cmd));
+
+        assert_eq!(output, vec![ResetToInitialState]);
+    }
+
+    #[test]
+    fn cursor_position() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(
+            b"\
+            \x1B[H\
+            \x1B[0;0H\
+            \x1B[2;3f",
+            |cmd| output.push(cmd),
+        );
+
+        assert_eq!(
+            output,
+            vec![
+                CursorPosition(1, 1),
+                CursorPosition(0, 0),
+                CursorPosition(2, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn dec_private_modes() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(
+            b"\
+            \x1B[?25h\
+            \x1B[?7l\
+            \x1B[?1;1049h",
+            |cmd| output.push(cmd),
+        );
+
+        assert_eq!(
+            output,
+            vec![
+                DecSetMode(PrivateMode::ShowCursor),
+                DecResetMode(PrivateMode::Autowrap),
+                DecSetMode(PrivateMode::CursorKeys),
+                DecSetMode(PrivateMode::AlternateScreenSaveCursor),
+            ]
+        );
+    }
+
+    #[test]
+    fn stats_disabled_by_default() {
+        let mut parser = AnsiParser::new();
+        parser.parse(b"abc", |_| {});
+
+        assert_eq!(parser.stats(), None);
+    }
+
+    #[test]
+    fn stats() {
+        let mut parser = AnsiParser::builder().stats(true).build();
+        let mut output = vec![];
+
+        parser.parse(b"Hi\x07\r\x1B[31m\x1B[5y\x1BZ!", |cmd| output.push(cmd));
+
+        assert_eq!(
+            parser.stats(),
+            Some(&ParseStats {
+                bytes: 16,
+                printed: 3,
+                executed: 2,
+                unknown_sequences: 2,
+            })
+        );
+        assert_eq!(output.len(), 6);
+    }
+
+    #[test]
+    fn colors() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(
+            b"\
+            \x1B[31m\
+            \x1B[38;5;123m\
+            \x1B[38;2;1;12;123m\
+            \x1B[39m\
+            \x1B[41m\
+            \x1B[48;5;123m\
+            \x1B[48;2;1;12;123m\
+            \x1B[49m\
+            ",
+            |cmd| output.push(cmd),
+        );
+
+        assert_eq!(
+            output,
+            vec![
+                Sgr(Some(crate::ansi::Sgr::ForegroundColor(Color::Indexed(1)))),
+                Sgr(Some(crate::ansi::Sgr::ForegroundColor(Color::Indexed(123)))),
+                Sgr(Some(crate::ansi::Sgr::ForegroundColor(Color::RGB(
+                    1, 12, 123
+                )))),
+                Sgr(Some(crate::ansi::Sgr::ForegroundColor(Color::Default))),
+                Sgr(Some(crate::ansi::Sgr::BackgroundColor(Color::Indexed(1)))),
+                Sgr(Some(crate::ansi::Sgr::BackgroundColor(Color::Indexed(123)))),
+                Sgr(Some(crate::ansi::Sgr::BackgroundColor(Color::RGB(
+                    1, 12, 123
+                )))),
+                Sgr(Some(crate::ansi::Sgr::BackgroundColor(Color::Default))),
+            ]
+        );
+    }
+
+    #[test]
+    fn multiple_attributes() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(b"\x1B[1;4;38;5;9;44m\x1B[;3m\x1B[99m", |cmd| {
+            output.push(cmd)
+        });
+
+        assert_eq!(
+            output,
+            vec![
+                Sgr(Some(crate::ansi::Sgr::Bold)),
+                Sgr(Some(crate::ansi::Sgr::Underlined(UnderlineStyle::Straight))),
+                Sgr(Some(crate::ansi::Sgr::ForegroundColor(Color::Indexed(9)))),
+                Sgr(Some(crate::ansi::Sgr::BackgroundColor(Color::Indexed(4)))),
+                Sgr(Some(crate::ansi::Sgr::Reset)),
+                Sgr(Some(crate::ansi::Sgr::Italic)),
+                Sgr(None),
+            ]
+        );
+    }
+
+    #[test]
+    fn underline_styles() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(
+            b"\x1B[4:3m\x1B[4:0m\x1B[4:5m\x1B[21m\x1B[24m\x1B[4:9m",
+            |cmd| output.push(cmd),
+        );
+
+        assert_eq!(
+            output,
+            vec![
+                Sgr(Some(crate::ansi::Sgr::Underlined(UnderlineStyle::Curly))),
+                Sgr(Some(crate::ansi::Sgr::Underlined(UnderlineStyle::None))),
+                Sgr(Some(crate::ansi::Sgr::Underlined(UnderlineStyle::Dashed))),
+                Sgr(Some(crate::ansi::Sgr::Underlined(UnderlineStyle::Double))),
+                Sgr(Some(crate::ansi::Sgr::Underlined(UnderlineStyle::None))),
+                Sgr(None),
+            ]
+        );
+    }
+
+    #[test]
+    fn colon_colors() {
+        let mut parser = AnsiParser::new();
+        let mut output = vec![];
+
+        parser.parse(
+            b"\x1B[38:2::1:2:3m\x1B[48:2:4:5:6;1m\x1B[58:5:200m",
+            |cmd| output.push(cmd),
+        );
+
+        assert_eq!(
+            output,
+            vec![
+                Sgr(Some(crate::ansi::Sgr::ForegroundColor(Color::RGB(1, 2, 3)))),
+                Sgr(Some(crate::ansi::Sgr::BackgroundColor(Color::RGB(4, 5, 6)))),
+                Sgr(Some(crate::ansi::Sgr::Bold)),
+                Sgr(Some(crate::ansi::Sgr::UnderlineColor(Color::Indexed(200)))),
             ]
         );
     }
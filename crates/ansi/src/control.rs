@@ -1,4 +1,4 @@
-use std::fmt::{self, Display, LowerHex, UpperHex};
+use core::fmt::{LowerHex, UpperHex};
 
 use enum_meta::EnumMeta;
 use enum_repr_convert::ConvertRepr;
@@ -8,6 +8,7 @@ use enum_repr_convert::ConvertRepr;
 #[derive(Debug, PartialEq, Copy, Clone, EnumMeta, ConvertRepr)]
 #[repr(u8)]
 #[meta_attrs(caret_notation, abbreviation)]
+#[meta_display(caret_notation)]
 pub enum C0 {
     /// Does nothing. The code of blank paper tape, and also used for padding to slow transmission.
     #[meta(caret_notation = "^@", abbreviation = "NUL")]
@@ -128,21 +129,15 @@ pub enum C0 {
     Delete = 0x7F,
 }
 
-impl Display for C0 {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.caret_notation())
-    }
-}
-
 impl LowerHex for C0 {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let value: u8 = (*self).into();
         write!(f, "{:x}", value)
     }
 }
 
 impl UpperHex for C0 {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let value: u8 = (*self).into();
         write!(f, "{:X}", value)
     }
@@ -151,6 +146,7 @@ impl UpperHex for C0 {
 #[derive(Debug, PartialEq, Copy, Clone, EnumMeta, ConvertRepr)]
 #[repr(u8)]
 #[meta_attrs(notation, abbreviation)]
+#[meta_display(abbreviation)]
 pub enum C1 {
     /// Proposed as a "padding" or "high byte" for single-byte characters to make them two bytes long for easier interoperability with [multiple byte characters](https://www.wikiwand.com/en/articles/Variable-width_encoding).
     /// [Extended Unix Code](https://www.wikiwand.com/en/articles/Extended_Unix_Code) (EUC) occasionally uses this.
@@ -215,13 +211,13 @@ pub enum C1 {
     /// Reserved for private function agreed on between the sender and the recipient of the data.
     #[meta(notation = "R", abbreviation = "PU2")]
     PrivateUser2 = 0x92,
-    ///
+    /// Set a flag allowing the terminal to transmit data.
     #[meta(notation = "S", abbreviation = "STS")]
     SetTransmitState = 0x93,
     /// Destructive backspace, to eliminate ambiguity about meaning of [`BS`](C0::Backspace).
     #[meta(notation = "T", abbreviation = "CCH")]
     CancelCharacter = 0x94,
-    ///
+    /// Set a message waiting indicator in the receiving device.
     #[meta(notation = "U", abbreviation = "MW")]
     MessageWaiting = 0x95,
     /// Used by [block-oriented terminals](https://www.wikiwand.com/en/articles/Block-oriented_terminal).
@@ -259,6 +255,141 @@ pub enum C1 {
     ApplicationProgramCommand = 0x9F,
 }
 
+/// Any code of [`C0`] or [`C1`], e.g. for listing the whole control code space.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ControlCode {
+    C0(C0),
+    C1(C1),
+}
+
+impl ControlCode {
+    /// All C0 codes followed by all C1 codes, each in order of their value.
+    pub fn all() -> impl Iterator<Item = Self> {
+        let c0 = C0::all().iter().copied().map(Self::C0);
+        c0.chain(C1::all().iter().copied().map(Self::C1))
+    }
+
+    pub fn abbreviation(&self) -> &'static str {
+        match self {
+            Self::C0(c0) => c0.abbreviation(),
+            Self::C1(c1) => c1.abbreviation(),
+        }
+    }
+
+    /// Caret notation of a C0 code such as `^[`, or the byte following ESC in the 7-bit form of
+    /// a C1 code such as `[` for CSI.
+    pub fn notation(&self) -> &'static str {
+        match self {
+            Self::C0(c0) => c0.caret_notation(),
+            Self::C1(c1) => c1.notation(),
+        }
+    }
+}
+
+impl From<C0> for ControlCode {
+    fn from(c0: C0) -> Self {
+        Self::C0(c0)
+    }
+}
+
+impl From<C1> for ControlCode {
+    fn from(c1: C1) -> Self {
+        Self::C1(c1)
+    }
+}
+
+impl From<ControlCode> for u8 {
+    fn from(code: ControlCode) -> Self {
+        match code {
+            ControlCode::C0(c0) => c0.into(),
+            ControlCode::C1(c1) => c1.into(),
+        }
+    }
+}
+
+impl TryFrom<u8> for ControlCode {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        C0::try_from(value)
+            .map(Self::C0)
+            .or_else(|_| C1::try_from(value).map(Self::C1))
+    }
+}
+
+/// Final bytes of the control sequences interpreted by [`AnsiParser`](crate::ansi::AnsiParser),
+/// named after the sequence without intermediates. Some are shared with other sequences told
+/// apart by their intermediates, e.g. `CSI ? Ps h` (DECSET) and `CSI Ps $ p` (DECRQM).
+#[derive(Debug, PartialEq, Copy, Clone, EnumMeta, ConvertRepr)]
+#[repr(u8)]
+#[meta_attrs(abbreviation)]
+#[meta_display(abbreviation)]
+pub enum CsiFinal {
+    /// Move the cursor up `Ps` rows.
+    #[meta(abbreviation = "CUU")]
+    CursorUp = 0x41,
+    /// Move the cursor down `Ps` rows.
+    #[meta(abbreviation = "CUD")]
+    CursorDown = 0x42,
+    /// Move the cursor right `Ps` columns.
+    #[meta(abbreviation = "CUF")]
+    CursorForward = 0x43,
+    /// Move the cursor left `Ps` columns.
+    #[meta(abbreviation = "CUB")]
+    CursorBackward = 0x44,
+    /// Move the cursor to row and column.
+    #[meta(abbreviation = "CUP")]
+    CursorPosition = 0x48,
+    /// Erase parts of the screen, selectively with `?` (DECSED).
+    #[meta(abbreviation = "ED")]
+    EraseInDisplay = 0x4A,
+    /// Erase parts of the cursor row, selectively with `?` (DECSEL).
+    #[meta(abbreviation = "EL")]
+    EraseInLine = 0x4B,
+    /// Insert blank rows at the cursor.
+    #[meta(abbreviation = "IL")]
+    InsertLine = 0x4C,
+    /// Delete rows at the cursor.
+    #[meta(abbreviation = "DL")]
+    DeleteLine = 0x4D,
+    /// Ask for the terminal's identity, the secondary attributes with `>`.
+    #[meta(abbreviation = "DA")]
+    DeviceAttributes = 0x63,
+    /// Same as [`CUP`](CsiFinal::CursorPosition), though a format effector.
+    #[meta(abbreviation = "HVP")]
+    HorizontalVerticalPosition = 0x66,
+    /// Set ANSI modes, DEC private modes with `?` (DECSET).
+    #[meta(abbreviation = "SM")]
+    SetMode = 0x68,
+    /// Reset ANSI modes, DEC private modes with `?` (DECRST).
+    #[meta(abbreviation = "RM")]
+    ResetMode = 0x6C,
+    /// Set text attributes and colors, xterm's key modifier options with `>` (XTMODKEYS).
+    #[meta(abbreviation = "SGR")]
+    SelectGraphicRendition = 0x6D,
+    /// Ask for a status report, e.g. the cursor position.
+    #[meta(abbreviation = "DSR")]
+    DeviceStatusReport = 0x6E,
+    /// Ask for the state of a mode, with `$` (DECRQM).
+    #[meta(abbreviation = "DECRQM")]
+    RequestMode = 0x70,
+    /// Protect characters from selective erases, with `"` (DECSCA).
+    #[meta(abbreviation = "DECSCA")]
+    SelectCharacterProtection = 0x71,
+    /// Set the top and bottom margins of the scroll region.
+    #[meta(abbreviation = "DECSTBM")]
+    SetTopBottomMargins = 0x72,
+    /// Manipulate or report on the window.
+    #[meta(abbreviation = "XTWINOPS")]
+    WindowOperation = 0x74,
+    /// Fill a rectangle with a character, with `$` (DECFRA).
+    #[meta(abbreviation = "DECFRA")]
+    FillRectangularArea = 0x78,
+    /// Erase a rectangle, with `$` (DECERA).
+    #[meta(abbreviation = "DECERA")]
+    EraseRectangularArea = 0x7A,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -279,6 +410,12 @@ mod tests {
         assert_eq!(abbreviation, "NUL");
     }
 
+    #[test]
+    fn display() {
+        assert_eq!(C0::Escape.to_string(), "^[");
+        assert_eq!(C1::ControlSequenceIntroducer.to_string(), "CSI");
+    }
+
     #[test]
     fn into_u8() {
         let c0 = C0::Null;
@@ -300,4 +437,38 @@ mod tests {
 
         assert_eq!(c0_res, Err(0xFF));
     }
+
+    #[test]
+    fn all_control_codes() {
+        assert_eq!(
+            ControlCode::all().count(),
+            C0::all().len() + C1::all().len()
+        );
+
+        for code in ControlCode::all() {
+            assert_eq!(ControlCode::try_from(u8::from(code)), Ok(code));
+        }
+        let escape = ControlCode::from(C0::Escape);
+        assert_eq!((escape.abbreviation(), escape.notation()), ("ESC", "^["));
+        let csi = ControlCode::from(C1::ControlSequenceIntroducer);
+        assert_eq!((csi.abbreviation(), csi.notation()), ("CSI", "["));
+        assert_eq!(ControlCode::try_from(b'a'), Err(b'a'));
+    }
+
+    #[test]
+    fn csi_final_round_trip() {
+        for byte in 0x40..=0x7E {
+            if let Ok(final_byte) = CsiFinal::try_from(byte) {
+                assert_eq!(u8::from(final_byte), byte);
+            }
+        }
+        assert_eq!(CsiFinal::try_from(b'H'), Ok(CsiFinal::CursorPosition));
+        assert_eq!(
+            CsiFinal::try_from(b'm'),
+            Ok(CsiFinal::SelectGraphicRendition)
+        );
+        assert_eq!(CsiFinal::try_from(b'Z'), Err(b'Z'));
+        assert_eq!(CsiFinal::SET_TOP_BOTTOM_MARGINS, b'r');
+        assert_eq!(CsiFinal::EraseInLine.to_string(), "EL");
+    }
 }
@@ -0,0 +1,56 @@
+//! Decoding and encoding of values carried by device control strings, e.g. the capability names
+//! of XTGETTCAP.
+
+use alloc::{string::String, vec::Vec};
+
+use crate::error::AnsiError;
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+/// Decodes bytes written as two hex digits each, the form XTGETTCAP sends names and values in.
+pub fn decode_hex(data: &[u8]) -> Result<Vec<u8>, AnsiError> {
+    if !data.len().is_multiple_of(2) {
+        return Err(AnsiError::InvalidHexLength);
+    }
+    data.chunks(2)
+        .map(|pair| {
+            let digit = |byte: u8| {
+                (byte as char)
+                    .to_digit(16)
+                    .ok_or(AnsiError::InvalidHexDigit(byte))
+            };
+            Ok((digit(pair[0])? << 4 | digit(pair[1])?) as u8)
+        })
+        .collect()
+}
+
+/// Writes each of `bytes` as two uppercase hex digits, see [`decode_hex`].
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .flat_map(|&byte| [byte >> 4, byte & 0xF])
+        .map(|digit| HEX_DIGITS[digit as usize] as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trip() {
+        assert_eq!(encode_hex(b"Co"), "436F");
+        assert_eq!(decode_hex(b"436f").unwrap(), b"Co");
+        assert_eq!(decode_hex(b"").unwrap(), b"");
+        assert_eq!(
+            decode_hex(&encode_hex(b"\x1BOA").into_bytes()).unwrap(),
+            b"\x1BOA"
+        );
+    }
+
+    #[test]
+    fn invalid_hex() {
+        assert_eq!(decode_hex(b"436"), Err(AnsiError::InvalidHexLength));
+        assert_eq!(decode_hex(b"4x"), Err(AnsiError::InvalidHexDigit(b'x')));
+    }
+}
@@ -0,0 +1,313 @@
+//! Serializes commands back into the byte sequences [`AnsiParser`](crate::ansi::AnsiParser)
+//! understands.
+
+use std::io::Write;
+
+use crate::{
+    ansi::{AnsiCommand, BlinkInterval, Color, Sgr, UnderlineStyle},
+    control::C0,
+};
+
+impl AnsiCommand {
+    /// Appends the bytes for this command to `out`. `Sgr(None)` produces nothing.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        use AnsiCommand::*;
+
+        let _ = match self {
+            Print(ch) => write!(out, "{ch}"),
+            Bell => out.write_all(&[C0::ALERT]),
+            Backspace => out.write_all(&[C0::BACKSPACE]),
+            HorizontalTab => out.write_all(&[C0::CHARACTER_TABULATION]),
+            LineFeed => out.write_all(&[C0::LINE_FEED]),
+            CarriageReturn => out.write_all(&[C0::CARRIAGE_RETURN]),
+            TransmitOff => out.write_all(&[C0::DEVICE_CONTROL_THREE]),
+            TransmitOn => out.write_all(&[C0::DEVICE_CONTROL_ONE]),
+            CursorUp(n) => write!(out, "\x1B[{n}A"),
+            CursorDown(n) => write!(out, "\x1B[{n}B"),
+            CursorForward(n) => write!(out, "\x1B[{n}C"),
+            CursorBackward(n) => write!(out, "\x1B[{n}D"),
+            CursorPosition(row, col) => write!(out, "\x1B[{row};{col}H"),
+            EraseInDisplay(n) => write!(out, "\x1B[{n}J"),
+            EraseInLine(n) => write!(out, "\x1B[{n}K"),
+            InsertLines(n) => write!(out, "\x1B[{n}L"),
+            DeleteLines(n) => write!(out, "\x1B[{n}M"),
+            SelectiveEraseInDisplay(n) => write!(out, "\x1B[?{n}J"),
+            SelectiveEraseInLine(n) => write!(out, "\x1B[?{n}K"),
+            SetCharacterProtection(protect) => write!(out, "\x1B[{}\"q", u8::from(*protect)),
+            StartProtectedArea => out.write_all(b"\x1BV"),
+            EndProtectedArea => out.write_all(b"\x1BW"),
+            DesignateCharset(set, charset) => out.write_all(&[0x1B, b'(' + set, *charset]),
+            ShiftOut => out.write_all(&[C0::SHIFT_OUT]),
+            ShiftIn => out.write_all(&[C0::SHIFT_IN]),
+            SingleShift2 => out.write_all(b"\x1BN"),
+            SingleShift3 => out.write_all(b"\x1BO"),
+            ResetToInitialState => out.write_all(b"\x1Bc"),
+            SaveCursor => out.write_all(b"\x1B7"),
+            RestoreCursor => out.write_all(b"\x1B8"),
+            Index => out.write_all(b"\x1BD"),
+            ReverseIndex => out.write_all(b"\x1BM"),
+            NextLine => out.write_all(b"\x1BE"),
+            KeypadApplicationMode => out.write_all(b"\x1B="),
+            KeypadNumericMode => out.write_all(b"\x1B>"),
+            SetScrollRegion(top, bottom) => write!(out, "\x1B[{top};{bottom}r"),
+            FillRectangularArea(ch, area) => write!(
+                out,
+                "\x1B[{};{};{};{};{}$x",
+                *ch as u32, area.top, area.left, area.bottom, area.right
+            ),
+            EraseRectangularArea(area) => write!(
+                out,
+                "\x1B[{};{};{};{}$z",
+                area.top, area.left, area.bottom, area.right
+            ),
+            DeviceStatusReport(n) => write!(out, "\x1B[{n}n"),
+            WindowOperation(n) => write!(out, "\x1B[{n}t"),
+            SetTitle(title) => write!(out, "\x1B]2;{title}\x07"),
+            SetWorkingDirectory(path) => {
+                out.extend_from_slice(b"\x1B]7;file://");
+                encode_path(out, path);
+                out.write_all(&[C0::ALERT])
+            }
+            PushTitle => out.write_all(b"\x1B[22t"),
+            PopTitle => out.write_all(b"\x1B[23t"),
+            SecondaryDeviceAttributes => out.write_all(b"\x1B[>c"),
+            RequestCapabilities(names) => {
+                out.extend_from_slice(b"\x1BP+q");
+                for (i, name) in names.iter().enumerate() {
+                    if i > 0 {
+                        out.push(b';');
+                    }
+                    out.extend_from_slice(crate::dcs::encode_hex(name.as_bytes()).as_bytes());
+                }
+                out.write_all(b"\x1B\\")
+            }
+            SetModifyKeys(resource, Some(value)) => write!(out, "\x1B[>{resource};{value}m"),
+            SetModifyKeys(resource, None) => write!(out, "\x1B[>{resource}m"),
+            Sgr(Some(sgr)) => {
+                encode_sgr(std::slice::from_ref(sgr), out);
+                Ok(())
+            }
+            Sgr(None) => Ok(()),
+            SetMode(mode) => write!(out, "\x1B[{mode}h"),
+            ResetMode(mode) => write!(out, "\x1B[{mode}l"),
+            RequestMode(mode) => write!(out, "\x1B[{mode}$p"),
+            DecRequestMode(mode) => write!(out, "\x1B[?{}$p", u16::from(*mode)),
+            DecSetMode(mode) => write!(out, "\x1B[?{}h", u16::from(*mode)),
+            DecResetMode(mode) => write!(out, "\x1B[?{}l", u16::from(*mode)),
+        };
+    }
+}
+
+/// Appends a single SGR sequence setting all of `attributes` to `out`, or nothing if there are no
+/// attributes.
+pub fn encode_sgr(attributes: &[Sgr], out: &mut Vec<u8>) {
+    if attributes.is_empty() {
+        return;
+    }
+
+    out.extend_from_slice(b"\x1B[");
+    for (i, sgr) in attributes.iter().enumerate() {
+        if i > 0 {
+            out.push(b';');
+        }
+        sgr.encode(out);
+    }
+    out.push(b'm');
+}
+
+impl Sgr {
+    /// Appends the parameters selecting this attribute, without introducer or final byte.
+    fn encode(&self, out: &mut Vec<u8>) {
+        use Sgr::*;
+
+        let _ = match self {
+            Reset => write!(out, "0"),
+            Bold => write!(out, "1"),
+            Faint => write!(out, "2"),
+            Italic => write!(out, "3"),
+            Underlined(UnderlineStyle::None) => write!(out, "24"),
+            Underlined(UnderlineStyle::Straight) => write!(out, "4"),
+            Underlined(style) => write!(out, "4:{}", u8::from(*style)),
+            Blink(BlinkInterval::Slow) => write!(out, "5"),
+            Blink(BlinkInterval::Rapid) => write!(out, "6"),
+            Blink(BlinkInterval::Static) => write!(out, "25"),
+            Inverted(enabled) => write!(out, "{}", if *enabled { 7 } else { 27 }),
+            Conceal(enabled) => write!(out, "{}", if *enabled { 8 } else { 28 }),
+            CrossedOut(enabled) => write!(out, "{}", if *enabled { 9 } else { 29 }),
+            PrimaryFont => write!(out, "10"),
+            AlternativeFont(n) => write!(out, "{}", 10 + *n as u16),
+            Fraktur => write!(out, "20"),
+            Regular => write!(out, "22"),
+            NeitherItalicNorBlackletter => write!(out, "23"),
+            ProportionalSpacing(enabled) => write!(out, "{}", if *enabled { 26 } else { 50 }),
+            ForegroundColor(color) => encode_color(out, 30, color),
+            BackgroundColor(color) => encode_color(out, 40, color),
+            Framed => write!(out, "51"),
+            Encircled => write!(out, "52"),
+            Overlined(enabled) => write!(out, "{}", if *enabled { 53 } else { 55 }),
+            NeitherFramedNorEncircled => write!(out, "54"),
+            UnderlineColor(color) => encode_color(out, 50, color),
+        };
+    }
+}
+
+/// Writes `color` relative to `base`, the code of the first of the eight basic colors (30 for
+/// the foreground). Underline colors have no basic color codes, `base + 8` and `base + 9` select
+/// an extended and the default color for all three.
+/// Appends `path` as the path of a `file://` URL, percent-encoding everything but unreserved
+/// characters and `/`.
+fn encode_path(out: &mut Vec<u8>, path: &std::path::Path) {
+    #[cfg(unix)]
+    let bytes = std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str());
+    #[cfg(not(unix))]
+    let bytes = path.to_string_lossy();
+    #[cfg(not(unix))]
+    let bytes = bytes.as_bytes();
+
+    for &byte in bytes {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                out.push(byte)
+            }
+            _ => {
+                let _ = write!(out, "%{byte:02X}");
+            }
+        }
+    }
+}
+
+fn encode_color(out: &mut Vec<u8>, base: u16, color: &Color) -> std::io::Result<()> {
+    match *color {
+        Color::Default => write!(out, "{}", base + 9),
+        Color::Indexed(index) if index < 8 && base != 50 => write!(out, "{}", base + index as u16),
+        Color::Indexed(index) => write!(out, "{};5;{index}", base + 8),
+        Color::RGB(r, g, b) => write!(out, "{};2;{r};{g};{b}", base + 8),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ansi::{AnsiParser, PrivateMode, Rectangle};
+
+    fn round_trip(commands: Vec<AnsiCommand>) {
+        let mut bytes = Vec::new();
+        for command in &commands {
+            command.encode(&mut bytes);
+        }
+
+        let mut parsed = Vec::new();
+        AnsiParser::new().parse(&bytes, |command| parsed.push(command));
+
+        assert_eq!(parsed, commands, "{:?}", String::from_utf8_lossy(&bytes));
+    }
+
+    #[test]
+    fn commands_round_trip() {
+        use AnsiCommand::*;
+
+        round_trip(vec![
+            Print('a'),
+            Bell,
+            Backspace,
+            HorizontalTab,
+            CarriageReturn,
+            LineFeed,
+            TransmitOff,
+            TransmitOn,
+            CursorUp(2),
+            CursorDown(3),
+            CursorForward(4),
+            CursorBackward(5),
+            CursorPosition(6, 7),
+            EraseInDisplay(2),
+            EraseInLine(1),
+            InsertLines(3),
+            DeleteLines(1),
+            SelectiveEraseInDisplay(0),
+            SelectiveEraseInLine(2),
+            SetCharacterProtection(true),
+            SetCharacterProtection(false),
+            StartProtectedArea,
+            EndProtectedArea,
+            DesignateCharset(0, b'0'),
+            DesignateCharset(2, b'B'),
+            ShiftOut,
+            ShiftIn,
+            SingleShift2,
+            SingleShift3,
+            ResetToInitialState,
+            SaveCursor,
+            RestoreCursor,
+            Index,
+            ReverseIndex,
+            NextLine,
+            KeypadApplicationMode,
+            KeypadNumericMode,
+            SetScrollRegion(2, 10),
+            FillRectangularArea(
+                'é',
+                Rectangle {
+                    top: 1,
+                    left: 2,
+                    bottom: 3,
+                    right: 4,
+                },
+            ),
+            EraseRectangularArea(Rectangle::default()),
+            DeviceStatusReport(6),
+            WindowOperation(18),
+            SetTitle("été".into()),
+            SetWorkingDirectory("/home/a b/%é".into()),
+            PushTitle,
+            PopTitle,
+            SecondaryDeviceAttributes,
+            RequestCapabilities(vec!["Co".into(), "kcuu1".into()]),
+            SetModifyKeys(4, Some(2)),
+            SetModifyKeys(4, None),
+            SetMode(20),
+            ResetMode(4),
+            RequestMode(20),
+            DecRequestMode(PrivateMode::Origin),
+            DecSetMode(PrivateMode::ShowCursor),
+            DecResetMode(PrivateMode::AlternateScreenSaveCursor),
+        ]);
+    }
+
+    #[test]
+    fn sgr_round_trip() {
+        round_trip(
+            [
+                Sgr::Reset,
+                Sgr::Bold,
+                Sgr::Underlined(UnderlineStyle::Curly),
+                Sgr::Underlined(UnderlineStyle::Straight),
+                Sgr::Underlined(UnderlineStyle::None),
+                Sgr::Blink(BlinkInterval::Static),
+                Sgr::Inverted(false),
+                Sgr::AlternativeFont(3),
+                Sgr::ForegroundColor(Color::Indexed(3)),
+                Sgr::ForegroundColor(Color::Indexed(200)),
+                Sgr::BackgroundColor(Color::RGB(1, 2, 3)),
+                Sgr::BackgroundColor(Color::Default),
+                Sgr::UnderlineColor(Color::Indexed(1)),
+                Sgr::UnderlineColor(Color::Default),
+            ]
+            .into_iter()
+            .map(|sgr| AnsiCommand::Sgr(Some(sgr)))
+            .collect(),
+        );
+    }
+
+    #[test]
+    fn combined_sgr() {
+        let mut bytes = Vec::new();
+        encode_sgr(
+            &[Sgr::Bold, Sgr::ForegroundColor(Color::Indexed(1))],
+            &mut bytes,
+        );
+        encode_sgr(&[], &mut bytes);
+
+        assert_eq!(bytes, b"\x1B[1;31m");
+    }
+}
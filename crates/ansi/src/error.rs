@@ -0,0 +1,66 @@
+//! Errors of the fallible parts of the crate, such as [`osc`](crate::osc).
+
+use core::fmt;
+
+/// Malformed content of a control sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnsiError {
+    /// A color spec in none of the forms of [`parse_color`](crate::osc::parse_color).
+    InvalidColorSpec,
+    /// A byte that isn't a hexadecimal digit where one was expected.
+    InvalidHexDigit(u8),
+    /// Hex-encoded bytes with an odd number of digits.
+    InvalidHexLength,
+    /// A byte outside of the base64 alphabet, or padding before the end.
+    InvalidBase64(u8),
+    /// Base64 data that doesn't end on a whole byte.
+    InvalidBase64Length,
+    /// A URL that isn't `file://host/path`, or a truncated percent escape in its path.
+    InvalidFileUrl,
+}
+
+impl fmt::Display for AnsiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidColorSpec => write!(f, "invalid color spec"),
+            Self::InvalidHexDigit(byte) => write!(f, "invalid hex digit {byte:#04x}"),
+            Self::InvalidHexLength => write!(f, "odd number of hex digits"),
+            Self::InvalidBase64(byte) => write!(f, "invalid base64 byte {byte:#04x}"),
+            Self::InvalidBase64Length => write!(f, "truncated base64 data"),
+            Self::InvalidFileUrl => write!(f, "invalid file URL"),
+        }
+    }
+}
+
+impl core::error::Error for AnsiError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            AnsiError::InvalidColorSpec.to_string(),
+            "invalid color spec"
+        );
+        assert_eq!(
+            AnsiError::InvalidHexDigit(b'g').to_string(),
+            "invalid hex digit 0x67"
+        );
+        assert_eq!(
+            AnsiError::InvalidHexLength.to_string(),
+            "odd number of hex digits"
+        );
+        assert_eq!(
+            AnsiError::InvalidBase64(b'%').to_string(),
+            "invalid base64 byte 0x25"
+        );
+        assert_eq!(
+            AnsiError::InvalidBase64Length.to_string(),
+            "truncated base64 data"
+        );
+        assert_eq!(AnsiError::InvalidFileUrl.to_string(), "invalid file URL");
+    }
+}
@@ -1,3 +1,12 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
 pub mod ansi;
 pub mod control;
+pub mod dcs;
+#[cfg(feature = "std")]
+pub mod encode;
+pub mod error;
+pub mod osc;
 pub mod vt;
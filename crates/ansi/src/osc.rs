@@ -0,0 +1,203 @@
+//! Decoding of values carried by OSC strings, e.g. colors set with OSC 4, 10 and 11, clipboard
+//! content sent with OSC 52 and the working directory reported with OSC 7.
+
+use alloc::vec::Vec;
+
+use crate::error::AnsiError;
+
+/// Parses a color spec as understood by `XParseColor`: `rgb:r/g/b` with one to four hex digits
+/// per component, or `#rgb` with the same number of digits for each component. Components are
+/// returned scaled to 8 bits.
+pub fn parse_color(spec: &[u8]) -> Result<(u8, u8, u8), AnsiError> {
+    if let Some(components) = spec.strip_prefix(b"rgb:") {
+        let mut rgb = [0; 3];
+        let mut parts = components.split(|&byte| byte == b'/');
+        for value in &mut rgb {
+            let part = parts.next().ok_or(AnsiError::InvalidColorSpec)?;
+            if part.is_empty() || part.len() > 4 {
+                return Err(AnsiError::InvalidColorSpec);
+            }
+            // Scale so that all digits set is the maximum, e.g. `f` and `ffff` are both 255.
+            let max = (1u32 << (4 * part.len())) - 1;
+            *value = (parse_hex(part)? * 255 / max) as u8;
+        }
+        if parts.next().is_some() {
+            return Err(AnsiError::InvalidColorSpec);
+        }
+        return Ok((rgb[0], rgb[1], rgb[2]));
+    }
+
+    if let Some(digits) = spec.strip_prefix(b"#") {
+        if digits.is_empty() || digits.len() % 3 != 0 || digits.len() > 12 {
+            return Err(AnsiError::InvalidColorSpec);
+        }
+        let len = digits.len() / 3;
+        let mut rgb = [0; 3];
+        for (value, part) in rgb.iter_mut().zip(digits.chunks(len)) {
+            // Only the most significant bits are used, `#fff` is 240 for each component.
+            let value32 = parse_hex(part)? << (4 * (4 - len));
+            *value = (value32 >> 8) as u8;
+        }
+        return Ok((rgb[0], rgb[1], rgb[2]));
+    }
+
+    Err(AnsiError::InvalidColorSpec)
+}
+
+fn parse_hex(digits: &[u8]) -> Result<u32, AnsiError> {
+    digits.iter().try_fold(0, |value, &byte| {
+        let digit = (byte as char)
+            .to_digit(16)
+            .ok_or(AnsiError::InvalidHexDigit(byte))?;
+        Ok(value << 4 | digit)
+    })
+}
+
+/// Decodes standard base64 as used by OSC 52. Padding is optional.
+pub fn decode_base64(data: &[u8]) -> Result<Vec<u8>, AnsiError> {
+    let data = data
+        .strip_suffix(b"==")
+        .or_else(|| data.strip_suffix(b"="))
+        .unwrap_or(data);
+    if data.len() % 4 == 1 {
+        return Err(AnsiError::InvalidBase64Length);
+    }
+
+    let mut out = Vec::with_capacity(data.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for &byte in data {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(AnsiError::InvalidBase64(byte)),
+        };
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    Ok(out)
+}
+
+/// Decodes the path of a `file://host/path` URL as sent with OSC 7. The host is ignored and
+/// percent escapes are decoded, so the path may be any bytes.
+pub fn decode_file_url(url: &[u8]) -> Result<Vec<u8>, AnsiError> {
+    let rest = url
+        .strip_prefix(b"file://")
+        .ok_or(AnsiError::InvalidFileUrl)?;
+    let start = rest
+        .iter()
+        .position(|&byte| byte == b'/')
+        .ok_or(AnsiError::InvalidFileUrl)?;
+
+    let mut path = Vec::with_capacity(rest.len() - start);
+    let mut bytes = rest[start..].iter();
+    while let Some(&byte) = bytes.next() {
+        if byte == b'%' {
+            let digits = bytes.as_slice().get(..2).ok_or(AnsiError::InvalidFileUrl)?;
+            path.push(parse_hex(digits)? as u8);
+            bytes.nth(1);
+        } else {
+            path.push(byte);
+        }
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgb_color_spec() {
+        assert_eq!(parse_color(b"rgb:ff/80/00"), Ok((255, 128, 0)));
+        assert_eq!(parse_color(b"rgb:f/8/0"), Ok((255, 136, 0)));
+        assert_eq!(parse_color(b"rgb:ffff/0000/8080"), Ok((255, 0, 128)));
+    }
+
+    #[test]
+    fn hash_color_spec() {
+        assert_eq!(parse_color(b"#ff8000"), Ok((255, 128, 0)));
+        assert_eq!(parse_color(b"#f80"), Ok((240, 128, 0)));
+        assert_eq!(parse_color(b"#ffff80000000"), Ok((255, 128, 0)));
+    }
+
+    #[test]
+    fn invalid_color_spec() {
+        for spec in [
+            &b"red"[..],
+            b"rgb:ff/ff",
+            b"rgb:ff/ff/ff/ff",
+            b"rgb:/ff/ff",
+            b"rgb:fffff/0/0",
+            b"#ffff",
+            b"#",
+        ] {
+            assert_eq!(
+                parse_color(spec),
+                Err(AnsiError::InvalidColorSpec),
+                "{spec:?}"
+            );
+        }
+        assert_eq!(
+            parse_color(b"rgb:fg/00/00"),
+            Err(AnsiError::InvalidHexDigit(b'g'))
+        );
+        assert_eq!(parse_color(b"#00z"), Err(AnsiError::InvalidHexDigit(b'z')));
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(decode_base64(b"aGVsbG8="), Ok(b"hello".to_vec()));
+        assert_eq!(decode_base64(b"aGVsbG8"), Ok(b"hello".to_vec()));
+        assert_eq!(decode_base64(b"aGk/+w=="), Ok(b"hi?\xFB".to_vec()));
+        assert_eq!(decode_base64(b""), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn invalid_base64() {
+        assert_eq!(decode_base64(b"aGV%"), Err(AnsiError::InvalidBase64(b'%')));
+        assert_eq!(
+            decode_base64(b"aG=sbG8="),
+            Err(AnsiError::InvalidBase64(b'='))
+        );
+        assert_eq!(decode_base64(b"aGVsb"), Err(AnsiError::InvalidBase64Length));
+    }
+
+    #[test]
+    fn file_url() {
+        assert_eq!(
+            decode_file_url(b"file://host/home/user"),
+            Ok(b"/home/user".to_vec())
+        );
+        assert_eq!(
+            decode_file_url(b"file:///tmp/a%20b%2525%ff"),
+            Ok(b"/tmp/a b%25\xff".to_vec())
+        );
+    }
+
+    #[test]
+    fn invalid_file_url() {
+        for url in [
+            &b"/home/user"[..],
+            b"http://host/path",
+            b"file://host",
+            b"file:///a%2",
+        ] {
+            assert_eq!(
+                decode_file_url(url),
+                Err(AnsiError::InvalidFileUrl),
+                "{url:?}"
+            );
+        }
+        assert_eq!(
+            decode_file_url(b"file:///a%zz"),
+            Err(AnsiError::InvalidHexDigit(b'z'))
+        );
+    }
+}
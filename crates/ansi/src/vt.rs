@@ -1,4 +1,5 @@
-use std::mem::MaybeUninit;
+use alloc::vec::Vec;
+use core::mem::{self, MaybeUninit};
 
 #[derive(Clone, Copy)]
 pub enum State {
@@ -10,6 +11,18 @@ pub enum State {
     CsiIntermediate,
     CsiIgnore,
     OscString,
+    /// OSC string past [`VTParser::set_max_string_len`], consumed up to BEL or ST without being
+    /// passed on.
+    OscIgnore,
+    /// SOS, PM or APC string, consumed up to ST without being passed on.
+    SosPmApcString,
+    DcsEntry,
+    DcsParam,
+    DcsIntermediate,
+    /// Data of a device control string, passed on up to ST.
+    DcsPassthrough,
+    /// Malformed device control string, consumed up to ST without being passed on.
+    DcsIgnore,
 }
 
 #[derive(Debug, PartialEq)]
@@ -18,98 +31,586 @@ pub enum Action {
     Execute(u8),
     Clear,
     CollectParam(u8),
-    Hook(Vec<i32>, Vec<u8>),
+    /// Start of a device control string: final byte, parameters and intermediates like
+    /// [`Action::CsiDispatch`]. Its data follows as [`Action::Put`], up to [`Action::Unhook`].
+    Hook(u8, Vec<Option<i32>>, Vec<u8>),
     Put(u8),
     Unhook,
     OscStart,
     OscPut(u8),
     OscEnd,
-    CsiDispatch(u8, Vec<Option<i32>>),
-    EscDispatch(u8),
+    /// The OSC string grew past [`VTParser::set_max_string_len`] and was abandoned. Anything
+    /// collected from it should be dropped.
+    OscAbort,
+    /// Final byte, parameters and collected intermediates, including a leading private marker
+    /// such as `?`.
+    CsiDispatch(u8, Vec<Option<i32>>, Vec<u8>),
+    /// Final byte and collected intermediates, e.g. `(` for `ESC ( 0`.
+    EscDispatch(u8, Vec<u8>),
     None,
 }
 
-pub struct Params {
-    data: [MaybeUninit<Option<i32>>; 16],
+/// Number of CSI parameters kept by [`VTParser::default`].
+pub const DEFAULT_PARAMS_CAPACITY: usize = 16;
+
+/// Most intermediate bytes a CSI sequence may have, not counting a private marker. Sequences with
+/// more are ignored, like in xterm.
+pub const MAX_INTERMEDIATES: usize = 2;
+
+/// Longest OSC string accepted by default, see [`VTParser::set_max_string_len`].
+pub const DEFAULT_MAX_STRING_LEN: usize = 1024 * 1024;
+
+/// Printed in place of bytes that aren't valid UTF-8.
+pub const REPLACEMENT_CHARACTER: char = '\u{FFFD}';
+
+/// A UTF-8 encoded character being read byte by byte.
+#[derive(Debug, Default, Clone, Copy)]
+struct Utf8 {
+    /// Bits of the code point read so far.
+    code: u32,
+    /// Continuation bytes still expected.
+    remaining: u8,
+    /// Range the next continuation byte has to be in. Narrower than 0x80..=0xBF after some lead
+    /// bytes, ruling out overlong encodings, surrogates and code points past U+10FFFF.
+    lower: u8,
+    upper: u8,
+}
+
+enum Decoded {
+    Pending,
+    Char(char),
+    /// The byte doesn't continue the character, which is abandoned.
+    Invalid,
+}
+
+impl Utf8 {
+    /// Starts a character with lead byte `byte`. Returns false if it isn't one.
+    fn start(&mut self, byte: u8) -> bool {
+        let (remaining, lower, upper, mask) = match byte {
+            0xC2..=0xDF => (1, 0x80, 0xBF, 0x1F),
+            0xE0 => (2, 0xA0, 0xBF, 0x0F),
+            0xED => (2, 0x80, 0x9F, 0x0F),
+            0xE1..=0xEF => (2, 0x80, 0xBF, 0x0F),
+            0xF0 => (3, 0x90, 0xBF, 0x07),
+            0xF4 => (3, 0x80, 0x8F, 0x07),
+            0xF1..=0xF3 => (3, 0x80, 0xBF, 0x07),
+            _ => return false,
+        };
+        *self = Self {
+            code: (byte & mask) as u32,
+            remaining,
+            lower,
+            upper,
+        };
+        true
+    }
+
+    fn is_pending(&self) -> bool {
+        self.remaining > 0
+    }
+
+    /// Adds the next byte of the character started last.
+    fn push(&mut self, byte: u8) -> Decoded {
+        if !(self.lower..=self.upper).contains(&byte) {
+            self.remaining = 0;
+            return Decoded::Invalid;
+        }
+        self.code = (self.code << 6) | (byte & 0x3F) as u32;
+        self.remaining -= 1;
+        (self.lower, self.upper) = (0x80, 0xBF);
+        if self.is_pending() {
+            return Decoded::Pending;
+        }
+        Decoded::Char(char::from_u32(self.code).unwrap_or(REPLACEMENT_CHARACTER))
+    }
+
+    /// Follows UTF-8 text without decoding it. Returns whether `byte` continues a character,
+    /// in which case it isn't a C1 control even if it has the value of one.
+    fn skip(&mut self, byte: u8) -> bool {
+        if self.is_pending() && (0x80..=0xBF).contains(&byte) {
+            self.remaining -= 1;
+            return true;
+        }
+        if !self.start(byte) {
+            self.remaining = 0;
+        }
+        false
+    }
+}
+
+/// Parameters of the CSI sequence being parsed, holding at most `N` values.
+///
+/// Parameters past the capacity are dropped, the sequence is still dispatched with the first `N`.
+pub struct Params<const N: usize> {
+    data: [MaybeUninit<Option<i32>>; N],
     len: usize,
     current: Option<i32>,
     has_current: bool,
+    /// Bit `i` is set if parameter `i` was preceded by a colon. Only the first 64 are tracked.
+    subparams: u64,
+    /// The parameter being collected follows a colon.
+    in_subparam: bool,
 }
 
-impl Params {
+impl<const N: usize> Params<N> {
+    /// Values too large for an `i32` saturate rather than overflow.
     fn push_digit(&mut self, digit: u8) {
         let digit = (digit - b'0') as i32;
-        self.current = Some(self.current.unwrap_or(0) * 10 + digit);
+        let value = self.current.unwrap_or(0).saturating_mul(10);
+        self.current = Some(value.saturating_add(digit));
         self.has_current = true;
     }
 
     fn finish_param(&mut self) {
         if self.len < self.data.len() {
             self.data[self.len].write(if self.has_current { self.current } else { None });
+            if self.in_subparam && self.len < 64 {
+                self.subparams |= 1 << self.len;
+            }
             self.len += 1;
         }
         self.current = None;
         self.has_current = false;
+        self.in_subparam = false;
+    }
+
+    /// Ends the current parameter at a colon, making the next one a sub-parameter of it.
+    fn finish_subparam(&mut self) {
+        self.finish_param();
+        self.in_subparam = true;
+    }
+
+    fn is_subparam(&self, index: usize) -> bool {
+        index < self.len.min(64) && self.subparams & (1 << index) != 0
     }
 
     fn as_slice(&self) -> &[Option<i32>] {
-        unsafe { std::slice::from_raw_parts(self.data.as_ptr() as *const Option<i32>, self.len) }
+        unsafe { core::slice::from_raw_parts(self.data.as_ptr() as *const Option<i32>, self.len) }
     }
 }
 
-impl Default for Params {
+impl<const N: usize> Default for Params<N> {
     fn default() -> Self {
         Self {
             data: unsafe { MaybeUninit::uninit().assume_init() },
             len: 0,
             current: None,
             has_current: false,
+            subparams: 0,
+            in_subparam: false,
         }
     }
 }
 
-pub struct VTParser {
+/// Receives what [`VTParser::advance`] parses, one method per kind of [`Action`]. All methods do
+/// nothing by default, so handlers only implement what they are interested in.
+pub trait AnsiHandler {
+    /// A printable character.
+    fn print(&mut self, _ch: char) {}
+
+    /// A C0 control code, executed immediately even in the middle of a sequence.
+    fn execute(&mut self, _byte: u8) {}
+
+    /// A complete CSI sequence, see [`Action::CsiDispatch`].
+    fn csi_dispatch(&mut self, _params: &[Option<i32>], _intermediates: &[u8], _byte: u8) {}
+
+    /// A complete escape sequence, see [`Action::EscDispatch`].
+    fn esc_dispatch(&mut self, _intermediates: &[u8], _byte: u8) {}
+
+    /// A complete OSC string, split into its `;`-separated parameters.
+    fn osc_dispatch(&mut self, _params: &[&[u8]]) {}
+
+    /// Start of a device control string, followed by its data through [`AnsiHandler::put`]
+    /// and ended by [`AnsiHandler::unhook`].
+    fn hook(&mut self, _params: &[Option<i32>], _intermediates: &[u8], _byte: u8) {}
+
+    fn put(&mut self, _byte: u8) {}
+
+    fn unhook(&mut self) {}
+}
+
+/// VT500-style escape sequence state machine.
+///
+/// `PARAMS` bounds the number of CSI parameters collected per sequence, see [`Params`]. Use
+/// [`VTParser::default`] for the default capacity or [`VTParser::new`] to pick another one, e.g.
+/// `VTParser::<4>::new()`.
+pub struct VTParser<const PARAMS: usize = DEFAULT_PARAMS_CAPACITY> {
     state: State,
-    params: Params,
+    params: Params<PARAMS>,
     intermediates: Vec<u8>,
+    /// Vectors handed back through [`VTParser::recycle`], reused for the next dispatch.
+    spare_params: Vec<Option<i32>>,
+    spare_intermediates: Vec<u8>,
+    /// Content of the OSC string being collected by [`VTParser::advance`].
+    osc: Vec<u8>,
+    /// Bytes passed on from the current OSC or device control string so far.
+    string_len: usize,
+    max_string_len: usize,
+    /// Character being printed in Ground.
+    utf8: Utf8,
+    /// Character being passed through in a control string, tracked so that its bytes aren't
+    /// taken for ST.
+    string_utf8: Utf8,
 }
 
 impl Default for VTParser {
     fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const PARAMS: usize> VTParser<PARAMS> {
+    pub fn new() -> Self {
+        Self::with_intermediates_capacity(4)
+    }
+
+    /// Like [`VTParser::new`], reserving room for `capacity` intermediate bytes up front. Zero
+    /// avoids allocating until a sequence with intermediates is parsed.
+    pub fn with_intermediates_capacity(capacity: usize) -> Self {
         Self {
             state: State::Ground,
             params: Params::default(),
-            intermediates: Vec::with_capacity(4),
+            intermediates: Vec::with_capacity(capacity),
+            spare_params: Vec::new(),
+            spare_intermediates: Vec::new(),
+            osc: Vec::new(),
+            string_len: 0,
+            max_string_len: DEFAULT_MAX_STRING_LEN,
+            utf8: Utf8::default(),
+            string_utf8: Utf8::default(),
         }
     }
-}
 
-impl VTParser {
+    /// Limits the length of OSC strings, so that output never terminating one can't make
+    /// consumers collect it without bound. A string reaching `len` bytes is aborted with
+    /// [`Action::OscAbort`] and the rest of it, up to BEL or ST, is dropped. Data of device control
+    /// strings past `len` bytes is dropped as well.
+    pub fn set_max_string_len(&mut self, len: usize) {
+        self.max_string_len = len;
+    }
+
+    /// Limit set by [`VTParser::set_max_string_len`].
+    pub fn max_string_len(&self) -> usize {
+        self.max_string_len
+    }
+
+    /// Parses `bytes`, passing everything recognized to `handler`. An alternative to calling
+    /// [`VTParser::parse_byte`] and matching on the actions; OSC strings are collected and
+    /// passed on whole. Sequences may be split across calls.
+    pub fn advance<H: AnsiHandler>(&mut self, handler: &mut H, bytes: &[u8]) {
+        for &byte in bytes {
+            let action = self.parse_byte(byte);
+            match action {
+                Action::Print(ch) => handler.print(ch),
+                Action::Execute(byte) => handler.execute(byte),
+                Action::CsiDispatch(byte, ref params, ref intermediates) => {
+                    handler.csi_dispatch(params, intermediates, byte)
+                }
+                Action::EscDispatch(byte, ref intermediates) => {
+                    handler.esc_dispatch(intermediates, byte)
+                }
+                Action::OscStart => self.osc.clear(),
+                Action::OscPut(byte) => self.osc.push(byte),
+                Action::OscEnd => {
+                    let params: Vec<&[u8]> = self.osc.split(|&byte| byte == b';').collect();
+                    handler.osc_dispatch(&params);
+                }
+                Action::OscAbort => self.osc = Vec::new(),
+                Action::Hook(byte, ref params, ref intermediates) => {
+                    handler.hook(params, intermediates, byte)
+                }
+                Action::Put(byte) => handler.put(byte),
+                Action::Unhook => handler.unhook(),
+                Action::Clear | Action::CollectParam(_) | Action::None => {}
+            }
+            self.recycle(action);
+        }
+    }
+
+    /// Hands the vectors of a dispatched `action` back to the parser, which refills them for the
+    /// next [`Action::CsiDispatch`] or [`Action::EscDispatch`] instead of allocating new ones.
+    /// Other actions are dropped.
+    ///
+    /// The vectors of an action are owned by the caller until it is recycled, so actions can be
+    /// kept around as long as needed; only actions that aren't recycled cost an allocation.
+    pub fn recycle(&mut self, action: Action) {
+        let (params, intermediates) = match action {
+            Action::CsiDispatch(_, params, intermediates)
+            | Action::Hook(_, params, intermediates) => (params, intermediates),
+            Action::EscDispatch(_, intermediates) => (Vec::new(), intermediates),
+            _ => return,
+        };
+        if params.capacity() > self.spare_params.capacity() {
+            self.spare_params = params;
+        }
+        if intermediates.capacity() > self.spare_intermediates.capacity() {
+            self.spare_intermediates = intermediates;
+        }
+    }
+
+    /// Whether the parser is between sequences, i.e. the last byte completed or aborted one or
+    /// wasn't part of one. Not the case in the middle of a UTF-8 character.
+    pub fn is_ground(&self) -> bool {
+        matches!(self.state, State::Ground) && !self.utf8.is_pending()
+    }
+
+    /// Whether parameter `index` of the last dispatched CSI sequence is a sub-parameter, i.e. it
+    /// was separated from the one before it by a colon as in `CSI 4:3 m`.
+    pub fn is_subparam(&self, index: usize) -> bool {
+        self.params.is_subparam(index)
+    }
+
+    fn dispatch_csi(&mut self, byte: u8) -> Action {
+        self.state = State::Ground;
+        let (params, intermediates) = self.take_sequence();
+        Action::CsiDispatch(byte, params, intermediates)
+    }
+
+    fn hook(&mut self, byte: u8) -> Action {
+        self.state = State::DcsPassthrough;
+        self.string_len = 0;
+        let (params, intermediates) = self.take_sequence();
+        Action::Hook(byte, params, intermediates)
+    }
+
+    /// Copies the parameters and intermediates collected into spare vectors.
+    fn take_sequence(&mut self) -> (Vec<Option<i32>>, Vec<u8>) {
+        let mut params = mem::take(&mut self.spare_params);
+        params.clear();
+        params.extend_from_slice(self.params.as_slice());
+        let mut intermediates = mem::take(&mut self.spare_intermediates);
+        intermediates.clear();
+        intermediates.extend_from_slice(&self.intermediates);
+        (params, intermediates)
+    }
+
+    /// Collects an intermediate byte of a CSI sequence, or ignores the sequence if it already has
+    /// [`MAX_INTERMEDIATES`].
+    fn collect_csi_intermediate(&mut self, byte: u8) -> Action {
+        let collected = self
+            .intermediates
+            .iter()
+            .filter(|byte| (0x20..=0x2F).contains(*byte))
+            .count();
+        if collected < MAX_INTERMEDIATES {
+            self.state = State::CsiIntermediate;
+            self.intermediates.push(byte);
+        } else {
+            self.state = State::CsiIgnore;
+        }
+        Action::None
+    }
+
+    fn dispatch_esc(&mut self, byte: u8) -> Action {
+        self.state = State::Ground;
+
+        let mut intermediates = mem::take(&mut self.spare_intermediates);
+        intermediates.clear();
+        intermediates.extend_from_slice(&self.intermediates);
+
+        Action::EscDispatch(byte, intermediates)
+    }
+
     pub fn parse_byte(&mut self, byte: u8) -> Action {
         use Action::*;
         use State::*;
 
+        // Text is UTF-8, characters past ASCII are printed once all of their bytes are read.
+        if let Ground = self.state {
+            if self.utf8.is_pending() {
+                match self.utf8.push(byte) {
+                    Decoded::Pending => return None,
+                    Decoded::Char(ch) => return Print(ch),
+                    // Only one action can be returned, so an incomplete character cut short by
+                    // ASCII or a control is dropped rather than replaced.
+                    Decoded::Invalid if byte < 0x80 => {}
+                    Decoded::Invalid => {
+                        self.utf8.start(byte);
+                        return Print(REPLACEMENT_CHARACTER);
+                    }
+                }
+            } else if byte >= 0x80 {
+                return if self.utf8.start(byte) {
+                    None
+                } else {
+                    Print(REPLACEMENT_CHARACTER)
+                };
+            }
+        }
+
+        // Control strings may hold UTF-8 text, in which 0x9C can be a continuation byte rather
+        // than the 8-bit ST.
+        let in_string = matches!(
+            self.state,
+            OscString | OscIgnore | SosPmApcString | DcsPassthrough | DcsIgnore
+        );
+        let st = byte == 0x9C && !(in_string && self.string_utf8.skip(byte));
+        if in_string && byte != 0x9C {
+            self.string_utf8.skip(byte);
+        }
+
         match (self.state, byte) {
-            (Ground, 0x1B) => {
+            // A device control string ends on CAN and SUB like any sequence, and on ESC of ST.
+            (DcsPassthrough, 0x18 | 0x1A) => {
+                self.state = Ground;
+                Unhook
+            }
+            (DcsPassthrough, _) if st => {
+                self.state = Ground;
+                Unhook
+            }
+            (DcsPassthrough, 0x1B) => {
                 self.state = Escape;
+                self.intermediates.clear();
+                Unhook
+            }
+            // CAN and SUB abort any sequence in progress, ESC restarts it.
+            (_, 0x18 | 0x1A) => {
+                self.state = Ground;
+                Execute(byte)
+            }
+            // ESC ends an OSC string, the `\` completing ST is then dispatched on its own.
+            (OscString, 0x1B) => {
+                self.state = Escape;
+                self.intermediates.clear();
+                OscEnd
+            }
+            (_, 0x1B) => {
+                self.state = Escape;
+                self.intermediates.clear();
                 None
             }
-            (Ground, 0x20..=0x7F) => Print(byte as char),
+
+            (Ground, 0x20..=0x7E) => Print(byte as char),
             (Ground, 0x00..=0x1F) => Execute(byte),
+            // DEL is a padding character and has no visible effect.
+            (Ground, 0x7F) => None,
+
+            // Other C0 controls are executed immediately without interrupting the sequence.
+            (
+                Escape | EscapeIntermediate | CsiEntry | CsiParam | CsiIntermediate | CsiIgnore,
+                0x00..=0x17 | 0x19 | 0x1C..=0x1F,
+            ) => Execute(byte),
 
             (Escape, 0x5B) => {
                 self.state = CsiEntry;
                 self.params = Params::default();
+                self.intermediates.clear();
+                None
+            }
+            (Escape, 0x5D) => {
+                self.state = OscString;
+                self.string_len = 0;
+                self.string_utf8 = Utf8::default();
+                OscStart
+            }
+            (OscString, 0x07) => {
+                self.state = Ground;
+                OscEnd
+            }
+            (OscString, _) if st => {
+                self.state = Ground;
+                OscEnd
+            }
+            // Bytes past ASCII are passed on as well, as titles and the like are UTF-8.
+            (OscString, 0x20..=0x7E | 0x80..=0xFF) if self.string_len == self.max_string_len => {
+                self.state = OscIgnore;
+                OscAbort
+            }
+            (OscString, 0x20..=0x7E | 0x80..=0xFF) => {
+                self.string_len += 1;
+                OscPut(byte)
+            }
+            (OscString, _) => None,
+            (OscIgnore, 0x07) => {
+                self.state = Ground;
+                None
+            }
+            (OscIgnore, _) if st => {
+                self.state = Ground;
+                None
+            }
+            (OscIgnore, _) => None,
+
+            // ESC X, ESC ^ and ESC _ introduce SOS, PM and APC strings, which are ignored. ESC
+            // returns to Escape as usual, so the `\` of ST is dispatched and dropped later on.
+            (Escape, 0x58 | 0x5E | 0x5F) => {
+                self.state = SosPmApcString;
+                self.string_utf8 = Utf8::default();
+                None
+            }
+            (SosPmApcString, _) if st => {
+                self.state = Ground;
+                None
+            }
+            (SosPmApcString, _) => None,
+
+            // Device control strings are parsed like CSI sequences up to the final byte, but
+            // C0 controls are ignored rather than executed.
+            (Escape, 0x50) => {
+                self.state = DcsEntry;
+                self.string_utf8 = Utf8::default();
+                self.params = Params::default();
+                self.intermediates.clear();
+                None
+            }
+            (DcsEntry | DcsParam, 0x30..=0x39) => {
+                self.state = DcsParam;
+                self.params.push_digit(byte);
                 None
             }
+            (DcsEntry | DcsParam, 0x3B) => {
+                self.state = DcsParam;
+                self.params.finish_param();
+                None
+            }
+            (DcsEntry, 0x3C..=0x3F) => {
+                self.state = DcsParam;
+                self.intermediates.push(byte);
+                None
+            }
+            (DcsEntry | DcsIntermediate, 0x20..=0x2F) => {
+                self.state = DcsIntermediate;
+                self.intermediates.push(byte);
+                None
+            }
+            (DcsParam, 0x20..=0x2F) => {
+                self.state = DcsIntermediate;
+                self.params.finish_param();
+                self.intermediates.push(byte);
+                None
+            }
+            (DcsEntry | DcsIntermediate, 0x40..=0x7E) => self.hook(byte),
+            (DcsParam, 0x40..=0x7E) => {
+                self.params.finish_param();
+                self.hook(byte)
+            }
+            (DcsEntry | DcsParam | DcsIntermediate, 0x00..=0x1F | 0x7F) => None,
+            (DcsEntry | DcsParam | DcsIntermediate, _) => {
+                self.state = DcsIgnore;
+                None
+            }
+            (DcsPassthrough, 0x7F) => None,
+            (DcsPassthrough, _) if self.string_len == self.max_string_len => None,
+            (DcsPassthrough, _) => {
+                self.string_len += 1;
+                Put(byte)
+            }
+            (DcsIgnore, _) if st => {
+                self.state = Ground;
+                None
+            }
+            (DcsIgnore, _) => None,
+
             (Escape, 0x20..=0x2F) => {
                 self.state = EscapeIntermediate;
                 self.intermediates.push(byte);
                 None
             }
-            (Escape, 0x30..=0x7E) => {
-                self.state = Ground;
-                EscDispatch(byte)
+            (Escape | EscapeIntermediate, 0x30..=0x7E) => self.dispatch_esc(byte),
+            (EscapeIntermediate, 0x20..=0x2F) => {
+                self.intermediates.push(byte);
+                None
             }
 
             (CsiEntry, 0x30..=0x39) => {
@@ -122,11 +623,19 @@ impl VTParser {
                 self.params.finish_param();
                 None
             }
-            (CsiEntry, 0x40..=0x7E) => {
-                let params = self.params.as_slice().to_vec();
-                self.state = Ground;
-                CsiDispatch(byte, params)
+            (CsiEntry, 0x3A) => {
+                self.state = CsiParam;
+                self.params.finish_subparam();
+                None
+            }
+            // Private markers (`<`, `=`, `>` and the DEC `?`), only valid right after the
+            // introducer.
+            (CsiEntry, 0x3C..=0x3F) => {
+                self.state = CsiParam;
+                self.intermediates.push(byte);
+                None
             }
+            (CsiEntry, 0x40..=0x7E) => self.dispatch_csi(byte),
 
             (CsiParam, 0x30..=0x39) => {
                 self.params.push_digit(byte);
@@ -136,11 +645,36 @@ impl VTParser {
                 self.params.finish_param();
                 None
             }
+            (CsiParam, 0x3A) => {
+                self.params.finish_subparam();
+                None
+            }
             (CsiParam, 0x40..=0x7E) => {
                 self.params.finish_param();
-                let params = self.params.as_slice().to_vec();
+                self.dispatch_csi(byte)
+            }
+            (CsiEntry | CsiIntermediate, 0x20..=0x2F) => self.collect_csi_intermediate(byte),
+            (CsiParam, 0x20..=0x2F) => {
+                self.params.finish_param();
+                self.collect_csi_intermediate(byte)
+            }
+            (CsiIntermediate, 0x40..=0x7E) => self.dispatch_csi(byte),
+            // Parameters can't follow intermediates.
+            (CsiIntermediate, 0x30..=0x3F) => {
+                self.state = CsiIgnore;
+                None
+            }
+
+            (CsiParam, 0x3C..=0x3F) => {
+                self.state = CsiIgnore;
+                None
+            }
+
+            // Malformed sequences are consumed up to their final byte without dispatching.
+            (CsiIgnore, 0x20..=0x3F | 0x7F) => None,
+            (CsiIgnore, 0x40..=0x7E) => {
                 self.state = Ground;
-                CsiDispatch(byte, params)
+                None
             }
 
             _ => {
@@ -201,28 +735,295 @@ mod tests {
         );
     }
 
+    #[test]
+    fn delete_is_ignored() {
+        assert_eq!(parse_bytes(b"\x7F"), vec![]);
+        assert_eq!(parse_bytes(b"a\x7Fb"), vec![Print('a'), Print('b')]);
+    }
+
+    #[test]
+    fn osc_string() {
+        assert_eq!(
+            parse_bytes(b"\x1B]0;hi\x07a"),
+            vec![
+                OscStart,
+                OscPut(b'0'),
+                OscPut(b';'),
+                OscPut(b'h'),
+                OscPut(b'i'),
+                OscEnd,
+                Print('a'),
+            ]
+        );
+        assert_eq!(
+            parse_bytes(b"\x1B]2\x1B\\"),
+            vec![OscStart, OscPut(b'2'), OscEnd, EscDispatch(b'\\', vec![])]
+        );
+        assert_eq!(
+            parse_bytes(b"\x1B]2\x9Ca"),
+            vec![OscStart, OscPut(b'2'), OscEnd, Print('a')]
+        );
+    }
+
+    #[test]
+    fn utf8_text() {
+        assert_eq!(
+            parse_bytes("aé日😀".as_bytes()),
+            vec![Print('a'), Print('é'), Print('日'), Print('😀')]
+        );
+        // Stray continuation bytes and invalid lead bytes, then an overlong encoding and a
+        // surrogate, each replaced along with the byte ruling them out.
+        assert_eq!(
+            parse_bytes(b"\x80\xFF\xC0\xAF\xE0\x80\xED\xA0"),
+            (0..6)
+                .map(|_| Print(REPLACEMENT_CHARACTER))
+                .collect::<Vec<_>>()
+        );
+        // A lead byte cuts the character before it short.
+        assert_eq!(
+            parse_bytes(b"\xE6\x97\xC3\xA9"),
+            vec![Print(REPLACEMENT_CHARACTER), Print('é')]
+        );
+        // Controls and sequences are still recognized in the middle of a character.
+        assert_eq!(
+            parse_bytes(b"\xE6\x1B[m\xC3a"),
+            vec![CsiDispatch(b'm', vec![], vec![]), Print('a')]
+        );
+    }
+
+    #[test]
+    fn st_inside_utf8_character() {
+        // `ќ` is D1 9C, the same as ST for its second byte.
+        assert_eq!(
+            parse_bytes(b"\x1B]2;\xD1\x9C\x9Ca"),
+            vec![
+                OscStart,
+                OscPut(b'2'),
+                OscPut(b';'),
+                OscPut(0xD1),
+                OscPut(0x9C),
+                OscEnd,
+                Print('a'),
+            ]
+        );
+        assert_eq!(
+            parse_bytes(b"\x1BPq\xD1\x9C\x9C"),
+            vec![Hook(b'q', vec![], vec![]), Put(0xD1), Put(0x9C), Unhook]
+        );
+        assert_eq!(
+            parse_bytes(b"\x1B_\xD1\x9C\x9Cb\x1B^\xE2\x9C\x93\x9Cc"),
+            vec![Print('b'), Print('c')]
+        );
+    }
+
+    #[test]
+    fn osc_string_too_long() {
+        let mut parser = VTParser::default();
+        parser.set_max_string_len(3);
+        let actions: Vec<_> = b"\x1B]0;abc\x07d\x1B]0;ab\x9Ce"
+            .iter()
+            .map(|&byte| parser.parse_byte(byte))
+            .filter(|action| *action != Action::None)
+            .collect();
+
+        assert_eq!(
+            actions,
+            vec![
+                OscStart,
+                OscPut(b'0'),
+                OscPut(b';'),
+                OscPut(b'a'),
+                OscAbort,
+                Print('d'),
+                OscStart,
+                OscPut(b'0'),
+                OscPut(b';'),
+                OscPut(b'a'),
+                OscAbort,
+                Print('e'),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignored_strings() {
+        assert_eq!(
+            parse_bytes(b"\x1B^pm \x07[1m\x1B\\a"),
+            vec![EscDispatch(b'\\', vec![]), Print('a')]
+        );
+        assert_eq!(
+            parse_bytes(b"\x1B_Gf=100;\x9Cb\x1BXsos\x18c"),
+            vec![Print('b'), Execute(0x18), Print('c')]
+        );
+    }
+
+    #[test]
+    fn device_control_string() {
+        assert_eq!(
+            parse_bytes(b"\x1BP+q54\x1B\\a"),
+            vec![
+                Hook(b'q', vec![], vec![b'+']),
+                Put(b'5'),
+                Put(b'4'),
+                Unhook,
+                EscDispatch(b'\\', vec![]),
+                Print('a'),
+            ]
+        );
+        assert_eq!(
+            parse_bytes(b"\x1BP1;2$\x07t\ry\x9C"),
+            vec![
+                Hook(b't', vec![Some(1), Some(2)], vec![b'$']),
+                Put(b'\r'),
+                Put(b'y'),
+                Unhook,
+            ]
+        );
+        // Aborted by CAN, or ignored when malformed.
+        assert_eq!(
+            parse_bytes(b"\x1BPqx\x18b"),
+            vec![Hook(b'q', vec![], vec![]), Put(b'x'), Unhook, Print('b')]
+        );
+        assert_eq!(parse_bytes(b"\x1BP1:2qx\x9Cc"), vec![Print('c')]);
+    }
+
+    #[test]
+    fn device_control_string_too_long() {
+        let mut parser = VTParser::default();
+        parser.set_max_string_len(1);
+        let actions: Vec<_> = b"\x1BPqab\x9C"
+            .iter()
+            .map(|&byte| parser.parse_byte(byte))
+            .filter(|action| *action != Action::None)
+            .collect();
+
+        assert_eq!(actions, vec![Hook(b'q', vec![], vec![]), Put(b'a'), Unhook]);
+    }
+
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl AnsiHandler for Recorder {
+        fn print(&mut self, ch: char) {
+            self.0.push(format!("print {ch}"));
+        }
+
+        fn execute(&mut self, byte: u8) {
+            self.0.push(format!("execute {byte:#04x}"));
+        }
+
+        fn csi_dispatch(&mut self, params: &[Option<i32>], intermediates: &[u8], byte: u8) {
+            self.0.push(format!(
+                "csi {params:?} {:?} {}",
+                String::from_utf8_lossy(intermediates),
+                byte as char
+            ));
+        }
+
+        fn esc_dispatch(&mut self, intermediates: &[u8], byte: u8) {
+            self.0.push(format!(
+                "esc {:?} {}",
+                String::from_utf8_lossy(intermediates),
+                byte as char
+            ));
+        }
+
+        fn osc_dispatch(&mut self, params: &[&[u8]]) {
+            let params: Vec<_> = params.iter().map(|p| String::from_utf8_lossy(p)).collect();
+            self.0.push(format!("osc {params:?}"));
+        }
+
+        fn hook(&mut self, params: &[Option<i32>], intermediates: &[u8], byte: u8) {
+            self.0.push(format!(
+                "hook {params:?} {:?} {}",
+                String::from_utf8_lossy(intermediates),
+                byte as char
+            ));
+        }
+
+        fn put(&mut self, byte: u8) {
+            self.0.push(format!("put {}", byte as char));
+        }
+
+        fn unhook(&mut self) {
+            self.0.push("unhook".into());
+        }
+    }
+
+    #[test]
+    fn handler() {
+        let mut parser = VTParser::default();
+        let mut recorder = Recorder::default();
+
+        parser.advance(&mut recorder, b"a\x07\x1B[?1;2h\x1B(0\x1B]2;a;b\x07\x1B[3");
+        parser.advance(&mut recorder, b"1mz\x1BP+qx\x9C");
+
+        assert_eq!(
+            recorder.0,
+            [
+                "print a",
+                "execute 0x07",
+                "csi [Some(1), Some(2)] \"?\" h",
+                "esc \"(\" 0",
+                "osc [\"2\", \"a\", \"b\"]",
+                "csi [Some(31)] \"\" m",
+                "print z",
+                "hook [] \"+\" q",
+                "put x",
+                "unhook",
+            ]
+        );
+    }
+
+    #[test]
+    fn control_inside_sequence() {
+        assert_eq!(
+            parse_bytes(b"\x1B[1\x07;2H"),
+            vec![
+                Execute(0x07),
+                CsiDispatch(b'H', vec![Some(1), Some(2)], vec![])
+            ]
+        );
+        assert_eq!(
+            parse_bytes(b"\x1B\r[3m"),
+            vec![Execute(0x0D), CsiDispatch(b'm', vec![Some(3)], vec![])]
+        );
+    }
+
+    #[test]
+    fn cancel_aborts_sequence() {
+        assert_eq!(
+            parse_bytes(b"\x1B[31\x18m"),
+            vec![Execute(0x18), Print('m')]
+        );
+        assert_eq!(
+            parse_bytes(b"\x1B[1;\x1B[2m"),
+            vec![CsiDispatch(b'm', vec![Some(2)], vec![])]
+        );
+    }
+
     #[test]
     fn cursor_movement() {
         let tests = vec![
             (
                 b"\x1B[A".to_vec(),
                 // FIXME: should be `vec![None]
-                vec![CsiDispatch(b'A', vec![])],
+                vec![CsiDispatch(b'A', vec![], vec![])],
                 "cursor up default",
             ),
             (
                 b"\x1B[5B".to_vec(),
-                vec![CsiDispatch(b'B', vec![Some(5)])],
+                vec![CsiDispatch(b'B', vec![Some(5)], vec![])],
                 "cursor down with value",
             ),
             (
                 b"\x1B[;C".to_vec(),
-                vec![CsiDispatch(b'C', vec![Option::None, Option::None])],
+                vec![CsiDispatch(b'C', vec![Option::None, Option::None], vec![])],
                 "cursor right empty param",
             ),
             (
                 b"\x1B[10;20H".to_vec(),
-                vec![CsiDispatch(b'H', vec![Some(10), Some(20)])],
+                vec![CsiDispatch(b'H', vec![Some(10), Some(20)], vec![])],
                 "cursor position",
             ),
         ];
@@ -238,17 +1039,21 @@ mod tests {
         let tests = vec![
             (
                 b"\x1B[31m".to_vec(),
-                vec![CsiDispatch(b'm', vec![Some(31)])],
+                vec![CsiDispatch(b'm', vec![Some(31)], vec![])],
                 "basic foreground color",
             ),
             (
                 b"\x1B[46m".to_vec(),
-                vec![CsiDispatch(b'm', vec![Some(46)])],
+                vec![CsiDispatch(b'm', vec![Some(46)], vec![])],
                 "basic background color",
             ),
             (
                 b"\x1B[38;5;123m".to_vec(),
-                vec![CsiDispatch(b'm', vec![Some(38), Some(5), Some(123)])],
+                vec![CsiDispatch(
+                    b'm',
+                    vec![Some(38), Some(5), Some(123)],
+                    vec![],
+                )],
                 "256 color foreground",
             ),
             (
@@ -256,6 +1061,7 @@ mod tests {
                 vec![CsiDispatch(
                     b'm',
                     vec![Some(48), Some(2), Some(255), Some(128), Some(0)],
+                    vec![],
                 )],
                 "RGB background color",
             ),
@@ -266,35 +1072,184 @@ mod tests {
         }
     }
 
-    // #[test]
-    // fn dec_private_modes() {
-    //     let tests = vec![
-    //         (
-    //             b"\x1B[?25h".to_vec(),
-    //             vec![Action::DecSetMode(PrivateMode::ShowCursor)],
-    //             "show cursor"
-    //         ),
-    //         (
-    //             b"\x1B[?25l".to_vec(),
-    //             vec![Action::DecResetMode(PrivateMode::ShowCursor)],
-    //             "hide cursor"
-    //         ),
-    //         (
-    //             b"\x1B[?1h".to_vec(),
-    //             vec![Action::DecSetMode(PrivateMode::CursorKeys)],
-    //             "set cursor keys"
-    //         ),
-    //         (
-    //             b"\x1B[?1049h".to_vec(),
-    //             vec![Action::DecSetMode(PrivateMode::Other(1049))],
-    //             "alternate screen buffer"
-    //         ),
-    //     ];
-    //
-    //     for (input, expected, message) in tests {
-    //         assert_eq!(parse_bytes(&input), expected, "{}", message);
-    //     }
-    // }
+    #[test]
+    fn dec_private_modes() {
+        let tests = vec![
+            (
+                b"\x1B[?25h".to_vec(),
+                vec![CsiDispatch(b'h', vec![Some(25)], vec![b'?'])],
+                "show cursor",
+            ),
+            (
+                b"\x1B[?25l".to_vec(),
+                vec![CsiDispatch(b'l', vec![Some(25)], vec![b'?'])],
+                "hide cursor",
+            ),
+            (
+                b"\x1B[?1049h\x1B[1h".to_vec(),
+                vec![
+                    CsiDispatch(b'h', vec![Some(1049)], vec![b'?']),
+                    CsiDispatch(b'h', vec![Some(1)], vec![]),
+                ],
+                "marker is not carried over",
+            ),
+            (
+                b"\x1B[1?h\x1B[2h".to_vec(),
+                vec![CsiDispatch(b'h', vec![Some(2)], vec![])],
+                "misplaced marker",
+            ),
+            (
+                b"\x1B[>4;1m".to_vec(),
+                vec![CsiDispatch(b'm', vec![Some(4), Some(1)], vec![b'>'])],
+                "modifyOtherKeys",
+            ),
+            (
+                b"\x1B[>c\x1B[=c".to_vec(),
+                vec![
+                    CsiDispatch(b'c', vec![Option::None], vec![b'>']),
+                    CsiDispatch(b'c', vec![Option::None], vec![b'=']),
+                ],
+                "secondary and tertiary device attributes",
+            ),
+        ];
+
+        for (input, expected, message) in tests {
+            assert_eq!(parse_bytes(&input), expected, "{}", message);
+        }
+    }
+
+    #[test]
+    fn subparameters() {
+        let mut parser = VTParser::default();
+        let actions: Vec<_> = b"\x1B[1;38:2::10:20:30m"
+            .iter()
+            .map(|&byte| parser.parse_byte(byte))
+            .filter(|action| *action != None)
+            .collect();
+
+        assert_eq!(
+            actions,
+            vec![CsiDispatch(
+                b'm',
+                vec![
+                    Some(1),
+                    Some(38),
+                    Some(2),
+                    Option::None,
+                    Some(10),
+                    Some(20),
+                    Some(30)
+                ],
+                vec![]
+            )]
+        );
+        let subparams: Vec<_> = (0..7).map(|i| parser.is_subparam(i)).collect();
+        assert_eq!(subparams, [false, false, true, true, true, true, true]);
+
+        for &byte in b"\x1B[4;3m" {
+            parser.parse_byte(byte);
+        }
+
+        assert!(!parser.is_subparam(1));
+    }
+
+    #[test]
+    fn recycled_vectors_are_reused() {
+        fn dispatch(parser: &mut VTParser, bytes: &[u8]) -> Action {
+            bytes
+                .iter()
+                .map(|&byte| parser.parse_byte(byte))
+                .find(|action| matches!(action, CsiDispatch(..)))
+                .unwrap()
+        }
+
+        let mut parser = VTParser::default();
+        let first = dispatch(&mut parser, b"\x1B[1;2;3m");
+        let CsiDispatch(_, params, _) = &first else {
+            unreachable!()
+        };
+        let ptr = params.as_ptr();
+        parser.recycle(first);
+
+        let second = dispatch(&mut parser, b"\x1B[4m");
+        assert_eq!(second, CsiDispatch(b'm', vec![Some(4)], vec![]));
+        let CsiDispatch(_, params, _) = &second else {
+            unreachable!()
+        };
+        assert_eq!(params.as_ptr(), ptr);
+    }
+
+    #[test]
+    fn esc_intermediates() {
+        assert_eq!(
+            parse_bytes(b"\x1B(0\x1B7\x1B[?1h\x1B)B"),
+            vec![
+                EscDispatch(b'0', vec![b'(']),
+                EscDispatch(b'7', vec![]),
+                CsiDispatch(b'h', vec![Some(1)], vec![b'?']),
+                EscDispatch(b'B', vec![b')']),
+            ]
+        );
+    }
+
+    #[test]
+    fn intermediates_not_shared() {
+        let mut parser = VTParser::<4>::with_intermediates_capacity(0);
+        let actions: Vec<_> = b"\x1B(\x1B)0\x1B#8\x1B7\x1B[ q\x1B[m"
+            .iter()
+            .map(|&byte| parser.parse_byte(byte))
+            .filter(|action| *action != Action::None)
+            .collect();
+
+        assert_eq!(
+            actions,
+            vec![
+                EscDispatch(b'0', vec![b')']),
+                EscDispatch(b'8', vec![b'#']),
+                EscDispatch(b'7', vec![]),
+                CsiDispatch(b'q', vec![], vec![b' ']),
+                CsiDispatch(b'm', vec![], vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn aborted_sequence_intermediates_dropped() {
+        assert_eq!(
+            parse_bytes(b"\x1B[?1$\x18\x1B(B\x1B[ \x1A\x1B[1\"q"),
+            vec![
+                Execute(0x18),
+                EscDispatch(b'B', vec![b'(']),
+                Execute(0x1A),
+                CsiDispatch(b'q', vec![Some(1)], vec![b'"']),
+            ]
+        );
+    }
+
+    #[test]
+    fn too_many_intermediates() {
+        assert_eq!(
+            parse_bytes(b"\x1B[1 !\"pa\x1B[?2 !p\x1B[ $!qb"),
+            vec![
+                Print('a'),
+                CsiDispatch(b'p', vec![Some(2)], vec![b'?', b' ', b'!']),
+                Print('b'),
+            ]
+        );
+    }
+
+    #[test]
+    fn csi_intermediates() {
+        assert_eq!(
+            parse_bytes(b"\x1B[1\"q\x1B[ q\x1B[?2$p"),
+            vec![
+                CsiDispatch(b'q', vec![Some(1)], vec![b'"']),
+                CsiDispatch(b'q', vec![], vec![b' ']),
+                CsiDispatch(b'p', vec![Some(2)], vec![b'?', b'$']),
+            ]
+        );
+        assert_eq!(parse_bytes(b"\x1B[1\"2qa"), vec![Print('a')]);
+    }
 
     #[test]
     fn partial_sequences() {
@@ -309,7 +1264,7 @@ mod tests {
             }
         }
 
-        assert_eq!(actions, vec![CsiDispatch(b'm', vec![Some(31)])]);
+        assert_eq!(actions, vec![CsiDispatch(b'm', vec![Some(31)], vec![])]);
     }
 
     #[test]
@@ -332,7 +1287,7 @@ mod tests {
         for (input, message) in tests {
             let actions = parse_bytes(&input);
             // Should not panic and should return to ground state
-            assert!(actions.len() > 0, "{}", message);
+            assert!(!actions.is_empty(), "{}", message);
         }
     }
 
@@ -347,13 +1302,13 @@ mod tests {
             Print('l'),
             Print('l'),
             Print('o'),
-            CsiDispatch(b'm', vec![Some(31)]),
+            CsiDispatch(b'm', vec![Some(31)], vec![]),
             Print('W'),
             Print('o'),
             Print('r'),
             Print('l'),
             Print('d'),
-            CsiDispatch(b'm', vec![Some(0)]),
+            CsiDispatch(b'm', vec![Some(0)], vec![]),
             Print('!'),
         ];
 
@@ -374,29 +1329,71 @@ mod tests {
         let actions = parse_bytes(&large_params);
         assert_eq!(actions.len(), 1, "should handle many parameters");
 
-        if let CsiDispatch(b'm', params) = &actions[0] {
+        if let CsiDispatch(b'm', params, _) = &actions[0] {
             assert!(params.len() <= 16, "should limit number of parameters");
         } else {
             panic!("unexpected action");
         }
     }
 
+    fn parse_bytes_with<const N: usize>(bytes: &[u8]) -> Vec<Action> {
+        let mut parser = VTParser::<N>::new();
+        bytes
+            .iter()
+            .map(|&byte| parser.parse_byte(byte))
+            .filter(|action| *action != None)
+            .collect()
+    }
+
+    #[test]
+    fn parameter_capacity_exact() {
+        assert_eq!(
+            parse_bytes_with::<4>(b"\x1B[1;2;3;4m"),
+            vec![CsiDispatch(
+                b'm',
+                vec![Some(1), Some(2), Some(3), Some(4)],
+                vec![]
+            )]
+        );
+    }
+
+    #[test]
+    fn parameter_capacity_exceeded() {
+        assert_eq!(
+            parse_bytes_with::<4>(b"\x1B[1;2;3;4;5m"),
+            vec![CsiDispatch(
+                b'm',
+                vec![Some(1), Some(2), Some(3), Some(4)],
+                vec![]
+            )]
+        );
+    }
+
     #[test]
     fn parameter_values() {
         let tests = vec![
             (
                 b"\x1B[123456789m".to_vec(),
-                vec![CsiDispatch(b'm', vec![Some(123456789)])],
+                vec![CsiDispatch(b'm', vec![Some(123456789)], vec![])],
                 "large parameter value",
             ),
+            (
+                b"\x1B[99999999999;2147483648m".to_vec(),
+                vec![CsiDispatch(
+                    b'm',
+                    vec![Some(i32::MAX), Some(i32::MAX)],
+                    vec![],
+                )],
+                "parameter values past i32::MAX",
+            ),
             (
                 b"\x1B[0m".to_vec(),
-                vec![CsiDispatch(b'm', vec![Some(0)])],
+                vec![CsiDispatch(b'm', vec![Some(0)], vec![])],
                 "zero parameter",
             ),
             (
                 b"\x1B[m".to_vec(),
-                vec![CsiDispatch(b'm', vec![])],
+                vec![CsiDispatch(b'm', vec![], vec![])],
                 "no parameter",
             ),
         ];
@@ -416,6 +1413,6 @@ mod tests {
 
         // Should not panic and should process all input
         let actions = parse_bytes(&large_input);
-        assert!(actions.len() > 0, "should process large input");
+        assert!(!actions.is_empty(), "should process large input");
     }
 }
@@ -0,0 +1,28 @@
+//! The parser has to keep building without the `std` feature. Checking the crate on its own is
+//! the only way to test this, since the rest of the workspace enables the feature.
+
+use std::process::Command;
+
+#[test]
+fn builds_without_std() {
+    let output = Command::new(env!("CARGO"))
+        .args([
+            "check",
+            "--offline",
+            "--quiet",
+            "--lib",
+            "--no-default-features",
+        ])
+        .arg("--manifest-path")
+        .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+        .arg("--target-dir")
+        .arg(concat!(env!("CARGO_TARGET_TMPDIR"), "/no-std"))
+        .output()
+        .expect("run cargo");
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
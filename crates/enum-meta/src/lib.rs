@@ -5,7 +5,7 @@ use syn::{
     MetaNameValue, Token,
 };
 
-#[proc_macro_derive(EnumMeta, attributes(meta_attrs, meta))]
+#[proc_macro_derive(EnumMeta, attributes(meta_attrs, meta, meta_display))]
 pub fn derive_enum_meta(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let enum_name = &input.ident;
@@ -26,6 +26,21 @@ pub fn derive_enum_meta(input: TokenStream) -> TokenStream {
         })
         .unwrap_or_default();
 
+    // Attribute whose value the enum is displayed as, if any.
+    let display_attr = input
+        .attrs
+        .iter()
+        .find(|cattr| cattr.path().is_ident("meta_display"))
+        .map(|cattr| {
+            let name = cattr
+                .parse_args::<Ident>()
+                .expect("meta_display must name one of the meta_attrs");
+            if !cattr_names.contains(&name) {
+                panic!("meta_display must name one of the meta_attrs");
+            }
+            name
+        });
+
     let data = if let syn::Data::Enum(data) = &input.data {
         data
     } else {
@@ -82,10 +97,29 @@ pub fn derive_enum_meta(input: TokenStream) -> TokenStream {
             }
         });
 
+    let variants = data.variants.iter().map(|variant| &variant.ident);
+
+    let display = display_attr.map(|display_attr| {
+        quote! {
+            impl ::core::fmt::Display for #enum_name {
+                fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                    f.write_str(self.#display_attr())
+                }
+            }
+        }
+    });
+
     let expanded = quote! {
         impl #enum_name {
             #(#methods)*
+
+            /// Get all variants in declaration order
+            pub fn all() -> &'static [Self] {
+                &[#(#enum_name::#variants,)*]
+            }
         }
+
+        #display
     };
 
     TokenStream::from(expanded)
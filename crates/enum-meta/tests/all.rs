@@ -0,0 +1,18 @@
+use enum_meta::EnumMeta;
+
+#[derive(Debug, PartialEq, EnumMeta)]
+#[meta_attrs(a)]
+enum Foo {
+    #[meta(a = "a")]
+    Bar,
+    #[meta(a = "b")]
+    Baz,
+}
+
+fn main() {
+    assert_eq!(Foo::all(), &[Foo::Bar, Foo::Baz]);
+    assert_eq!(
+        Foo::all().iter().map(Foo::a).collect::<Vec<_>>(),
+        ["a", "b"]
+    );
+}
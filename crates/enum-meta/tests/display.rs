@@ -0,0 +1,16 @@
+use enum_meta::EnumMeta;
+
+#[derive(EnumMeta)]
+#[meta_attrs(a, b)]
+#[meta_display(b)]
+enum Foo {
+    #[meta(a = "a", b = "b")]
+    Bar,
+    #[meta(a = "c", b = "d")]
+    Baz,
+}
+
+fn main() {
+    assert_eq!(Foo::Bar.to_string(), "b");
+    assert_eq!(format!("{}", Foo::Baz), "d");
+}
@@ -3,4 +3,6 @@ fn tests() {
     let t = trybuild::TestCases::new();
     t.pass("tests/compiles.rs");
     t.pass("tests/methods.rs");
+    t.pass("tests/display.rs");
+    t.pass("tests/all.rs");
 }
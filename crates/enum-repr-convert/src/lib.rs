@@ -2,7 +2,7 @@ use heck::ToShoutySnakeCase;
 use proc_macro::TokenStream;
 use proc_macro2::Span;
 use quote::quote;
-use syn::{parse_macro_input, DeriveInput, Expr, ExprLit, Ident, Lit, Meta, Path};
+use syn::{parse_macro_input, DeriveInput, Expr, ExprLit, ExprUnary, Ident, Lit, Meta, Path, UnOp};
 
 #[proc_macro_derive(ConvertRepr)]
 pub fn derive_enum_meta(input: TokenStream) -> TokenStream {
@@ -31,17 +31,11 @@ pub fn derive_enum_meta(input: TokenStream) -> TokenStream {
     let mut variants = Vec::new();
     for variant in &data.variants {
         let variant_ident = &variant.ident;
-        if let Some((
-            _,
-            Expr::Lit(ExprLit {
-                lit: Lit::Int(discriminant),
-                ..
-            }),
-        )) = &variant.discriminant
-        {
-            variants.push((variant_ident, discriminant));
-        } else {
-            panic!("ConvertRepr requires explicit discriminant value for all variants");
+        match &variant.discriminant {
+            Some((_, discriminant)) if is_int_literal(discriminant) => {
+                variants.push((variant_ident, discriminant));
+            }
+            _ => panic!("ConvertRepr requires explicit discriminant value for all variants"),
         }
     }
 
@@ -55,11 +49,14 @@ pub fn derive_enum_meta(input: TokenStream) -> TokenStream {
         }
     });
 
-    let from_arms = variants.iter().map(|(variant_ident, discriminant)| {
-        quote! {
-            #enum_name::#variant_ident => #discriminant
-        }
-    });
+    let from_arms: Vec<_> = variants
+        .iter()
+        .map(|(variant_ident, discriminant)| {
+            quote! {
+                #enum_name::#variant_ident => #discriminant
+            }
+        })
+        .collect();
 
     let try_from_arms = variants.iter().map(|(variant_ident, discriminant)| {
         quote! {
@@ -72,7 +69,7 @@ pub fn derive_enum_meta(input: TokenStream) -> TokenStream {
             #(#const_reprs;)*
         }
 
-        impl std::convert::From<#enum_name> for #repr_type {
+        impl ::core::convert::From<#enum_name> for #repr_type {
             #[inline]
             fn from(value: #enum_name) -> Self {
                 match value {
@@ -81,7 +78,16 @@ pub fn derive_enum_meta(input: TokenStream) -> TokenStream {
             }
         }
 
-        impl std::convert::TryFrom<#repr_type> for #enum_name {
+        impl ::core::convert::From<&#enum_name> for #repr_type {
+            #[inline]
+            fn from(value: &#enum_name) -> Self {
+                match value {
+                    #(#from_arms,)*
+                }
+            }
+        }
+
+        impl ::core::convert::TryFrom<#repr_type> for #enum_name {
             type Error = #repr_type;
 
             #[inline]
@@ -96,3 +102,25 @@ pub fn derive_enum_meta(input: TokenStream) -> TokenStream {
 
     TokenStream::from(expanded)
 }
+
+/// Whether `expr` is an integer literal, with a minus sign for negative discriminants of signed
+/// repr types, which makes it usable as a pattern.
+fn is_int_literal(expr: &Expr) -> bool {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(_), ..
+        }) => true,
+        Expr::Unary(ExprUnary {
+            op: UnOp::Neg(_),
+            expr,
+            ..
+        }) => matches!(
+            **expr,
+            Expr::Lit(ExprLit {
+                lit: Lit::Int(_),
+                ..
+            })
+        ),
+        _ => false,
+    }
+}
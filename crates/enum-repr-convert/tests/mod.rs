@@ -0,0 +1,7 @@
+#[test]
+fn tests() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/unsigned.rs");
+    t.pass("tests/signed.rs");
+    t.pass("tests/reference.rs");
+}
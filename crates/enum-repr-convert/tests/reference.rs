@@ -0,0 +1,15 @@
+use enum_repr_convert::ConvertRepr;
+
+#[derive(Debug, PartialEq, ConvertRepr)]
+#[repr(u8)]
+enum Foo {
+    Bar = 1,
+    Baz = 2,
+}
+
+fn main() {
+    let variants = [Foo::Bar, Foo::Baz, Foo::Bar];
+    let reprs: Vec<u8> = variants.iter().map(u8::from).collect();
+
+    assert_eq!(reprs, [1, 2, 1]);
+}
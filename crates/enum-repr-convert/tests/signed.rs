@@ -0,0 +1,18 @@
+use enum_repr_convert::ConvertRepr;
+
+#[derive(Debug, PartialEq, ConvertRepr)]
+#[repr(i16)]
+enum Foo {
+    Negative = -300,
+    Zero = 0,
+    Positive = 300,
+}
+
+fn main() {
+    assert_eq!(Foo::NEGATIVE, -300);
+    assert_eq!(i16::from(Foo::Negative), -300);
+    assert_eq!(i16::from(Foo::Positive), 300);
+    assert_eq!(Foo::try_from(-300), Ok(Foo::Negative));
+    assert_eq!(Foo::try_from(0), Ok(Foo::Zero));
+    assert_eq!(Foo::try_from(-1), Err(-1));
+}
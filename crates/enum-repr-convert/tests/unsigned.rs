@@ -0,0 +1,15 @@
+use enum_repr_convert::ConvertRepr;
+
+#[derive(Debug, PartialEq, ConvertRepr)]
+#[repr(u8)]
+enum Foo {
+    Bar = 1,
+    BazQux = 2,
+}
+
+fn main() {
+    assert_eq!(Foo::BAZ_QUX, 2);
+    assert_eq!(u8::from(Foo::Bar), 1);
+    assert_eq!(Foo::try_from(2), Ok(Foo::BazQux));
+    assert_eq!(Foo::try_from(3), Err(3));
+}
@@ -1,38 +1,239 @@
-use std::sync::Arc;
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use glyphon::{Attrs, Color, Family, Resolution, Shaping, TextArea, TextBounds};
+use glyphon::{
+    cosmic_text::LineEnding, AttrsList, BufferLine, Color, Resolution, Shaping, TextArea,
+    TextBounds,
+};
+use unicode_width::UnicodeWidthChar;
 use wgpu::{
     CommandEncoderDescriptor, LoadOp, Operations, RenderPassColorAttachment, RenderPassDescriptor,
     TextureViewDescriptor,
 };
 use winit::{
     application::ApplicationHandler,
-    dpi::LogicalSize,
-    event::WindowEvent,
-    keyboard::{Key, NamedKey},
+    dpi::{LogicalSize, Size},
+    event::{MouseScrollDelta, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow},
+    keyboard::{Key, ModifiersState},
     window::Window,
 };
 
-use crate::{pty::Pty, window::WindowState};
+use crate::{
+    bell::{BellMode, VisualBell},
+    blink::CursorBlink,
+    cell::{Buffer, ResizeMode},
+    keymap,
+    offscreen::OffscreenRenderer,
+    pacer::FramePacer,
+    pty::{Pty, PtyEvent},
+    style_cache::StyleCache,
+    terminal::Terminal,
+    window::{self, CellMetrics, SurfaceOptions, SurfaceRecovery, WindowState},
+};
+
+/// Window title until the child sets one.
+const DEFAULT_TITLE: &str = "ttyrant";
 
+/// Interval of [`PtyEvent::Tick`] while the shell is idle.
+const TICK_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Default)]
 pub struct Application {
     window_state: Option<WindowState>,
     pty: Option<Pty>,
-    content: String,
+    terminal: Terminal,
+    cursor_blink: CursorBlink,
+    /// Input typed while the child paused transmission with XOFF, and replies to queries not
+    /// written yet.
+    held_input: Vec<u8>,
+    modifiers: ModifiersState,
+    /// Pixels scrolled with a touchpad that don't add up to a whole row yet.
+    wheel_pixels: f64,
+    /// Row the cursor was on when the screen was last drawn, redrawn along with the damaged rows
+    /// to erase it.
+    drawn_cursor_row: Option<usize>,
+    /// Title last given to the window, empty for the default one.
+    title: String,
+    /// Shown while the child hasn't set a title, [`DEFAULT_TITLE`] unless set.
+    default_title: Option<String>,
+    /// Program run instead of the user's shell.
+    command: Option<Vec<String>>,
+    /// Working directory of the child, ttyrant's own unless set.
+    working_directory: Option<PathBuf>,
+    /// Bytes taken from the PTY per read, the builder's default unless set.
+    read_buffer_size: Option<usize>,
+    /// Columns and rows to size the window for initially.
+    initial_grid_size: Option<(usize, usize)>,
+    bell_mode: BellMode,
+    visual_bell: VisualBell,
+    frame_pacer: FramePacer,
+    cell_metrics: CellMetrics,
+    surface_options: SurfaceOptions,
+    /// Monospace font, [`window::FONT_FAMILY`] unless set.
+    font_family: Option<String>,
+    /// Text attributes of cells, resolved with the palette also giving the default foreground and
+    /// background.
+    style_cache: StyleCache,
 }
 
 impl Application {
     pub fn new() -> Self {
         Self::default()
     }
-}
 
-impl Default for Application {
-    fn default() -> Self {
-        Self {
-            window_state: None,
-            pty: None,
-            content: String::new(),
+    /// Honor XON/XOFF sent by the child, see [`Terminal::set_flow_control`].
+    pub fn with_flow_control(mut self, enabled: bool) -> Self {
+        self.terminal.set_flow_control(enabled);
+        self
+    }
+
+    /// Also feed typed input into the terminal, see [`Terminal::set_local_echo`].
+    pub fn with_local_echo(mut self, enabled: bool) -> Self {
+        self.terminal.set_local_echo(enabled);
+        self
+    }
+
+    /// Show backspace overstrike as bold and underlined text, see [`Terminal::set_overstrike`].
+    pub fn with_overstrike(mut self, enabled: bool) -> Self {
+        self.terminal.set_overstrike(enabled);
+        self
+    }
+
+    /// How the grid follows the window size, see [`Terminal::set_resize_mode`].
+    pub fn with_resize_mode(mut self, mode: ResizeMode) -> Self {
+        self.terminal.set_resize_mode(mode);
+        self
+    }
+
+    /// Compose decomposed text, see [`Terminal::set_normalization`].
+    #[cfg(feature = "nfc")]
+    pub fn with_normalization(mut self, enabled: bool) -> Self {
+        self.terminal.set_normalization(enabled);
+        self
+    }
+
+    /// What to do when the child rings the bell.
+    pub fn with_bell_mode(mut self, mode: BellMode) -> Self {
+        self.bell_mode = mode;
+        self
+    }
+
+    /// Height of a row relative to the font size, 1.4 by default. Must be set before the window
+    /// is created.
+    pub fn with_line_spacing(mut self, spacing: f32) -> Self {
+        self.cell_metrics.line_spacing = spacing;
+        self
+    }
+
+    pub fn with_font_size(mut self, size: f32) -> Self {
+        self.cell_metrics.font_size = size;
+        self
+    }
+
+    /// Title of the window while the child hasn't set one.
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.default_title = Some(title.into());
+        self
+    }
+
+    /// Program and arguments to run instead of the user's shell.
+    pub fn with_command(mut self, command: Vec<String>) -> Self {
+        self.command = Some(command);
+        self
+    }
+
+    /// Start the child in `dir` rather than in ttyrant's working directory.
+    pub fn with_working_directory(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.working_directory = Some(dir.into());
+        self
+    }
+
+    /// Maximum number of bytes read from the PTY at once, see
+    /// [`PtyBuilder::buffer_size`](crate::pty::PtyBuilder::buffer_size).
+    pub fn with_read_buffer_size(mut self, size: usize) -> Self {
+        self.read_buffer_size = Some(size);
+        self
+    }
+
+    /// Size the window to fit `cols` x `rows` cells when it's created, instead of a fixed size.
+    pub fn with_grid_size(mut self, cols: usize, rows: usize) -> Self {
+        self.initial_grid_size = Some((cols, rows));
+        self
+    }
+
+    /// Opacity of the background, see [`SurfaceOptions::opacity`].
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.surface_options.opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
+
+    /// How frames are presented, see [`SurfaceOptions::present_mode`].
+    pub fn with_present_mode(mut self, mode: wgpu::PresentMode) -> Self {
+        self.surface_options.present_mode = mode;
+        self
+    }
+
+    /// Applies the settings of a config file. Options set afterwards take precedence.
+    #[cfg(feature = "config")]
+    pub fn with_config(mut self, config: crate::config::Config) -> Self {
+        self.font_family = Some(config.font_family);
+        self.cell_metrics.font_size = config.font_size;
+        self.terminal.set_scrollback_limit(config.scrollback);
+        self.command = config.shell.or(self.command);
+        self.style_cache.set_palette(config.palette);
+        self
+    }
+
+    fn default_title(&self) -> &str {
+        self.default_title.as_deref().unwrap_or(DEFAULT_TITLE)
+    }
+
+    /// Cell size in physical pixels, for the display the window is on once it's created.
+    fn physical_metrics(&self) -> CellMetrics {
+        self.window_state
+            .as_ref()
+            .map_or(self.cell_metrics, WindowState::physical_metrics)
+    }
+
+    /// Renders the grid with [`OffscreenRenderer`] at the size of the cells on screen and writes
+    /// it as PNG to a file named after the current time in the working directory. Returns the
+    /// path written to.
+    fn save_screenshot(&self) -> io::Result<PathBuf> {
+        let metrics = self.physical_metrics();
+        let image = OffscreenRenderer::new(
+            metrics.cell_width().round() as usize,
+            metrics.line_height().round() as usize,
+        )
+        .with_palette(self.style_cache.palette().clone())
+        .with_cursor(self.terminal.modes().show_cursor)
+        .render(self.terminal.buffer());
+
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let path = std::env::current_dir()?.join(format!("ttyrant-{}.png", time.as_millis()));
+        fs::write(&path, image.to_png())?;
+        Ok(path)
+    }
+
+    /// Fits the grid to a surface of `width` x `height` physical pixels and tells the child about
+    /// it.
+    fn resize_terminal(&mut self, width: u32, height: u32) {
+        // A minimized window reports a size of zero, keep the grid as it was.
+        if width == 0 || height == 0 {
+            return;
+        }
+        let (cols, rows) = self.physical_metrics().grid_size(width, height);
+        let (cols, rows) = self.terminal.resize(cols, rows);
+        self.terminal.set_pixel_size(width, height);
+        if let Some(pty) = &self.pty {
+            let _ = pty.resize(cols, rows);
         }
     }
 }
@@ -43,23 +244,55 @@ impl ApplicationHandler for Application {
             return;
         }
 
-        let (width, height) = (800, 600);
+        // Sized in logical pixels like the font, winit scales it for the display.
+        let size: Size = match self.initial_grid_size {
+            Some((cols, rows)) => {
+                let (width, height) = self.cell_metrics.surface_size(cols, rows);
+                LogicalSize::new(width, height).into()
+            }
+            None => LogicalSize::new(800, 600).into(),
+        };
         let window_attributes = Window::default_attributes()
-            .with_inner_size(LogicalSize::new(width, height))
-            .with_title("ttyrant");
+            .with_inner_size(size)
+            .with_title(self.default_title())
+            .with_transparent(self.surface_options.is_transparent());
         let window = Arc::new(
             event_loop
                 .create_window(window_attributes)
                 .expect("create window"),
         );
 
-        self.window_state = Some(pollster::block_on(WindowState::new(window.clone())));
-        self.pty = Some(Pty::new(Arc::downgrade(&window)));
+        match pollster::block_on(WindowState::new(
+            window.clone(),
+            self.font_family.as_deref().unwrap_or(window::FONT_FAMILY),
+            self.cell_metrics,
+            self.surface_options,
+        )) {
+            Ok(state) => self.window_state = Some(state),
+            Err(err) => {
+                eprintln!("ttyrant: {err}");
+                event_loop.exit();
+                return;
+            }
+        }
+        let mut pty = Pty::builder().tick(TICK_INTERVAL);
+        if let Some(command) = &self.command {
+            pty = pty.command(command.clone());
+        }
+        if let Some(dir) = &self.working_directory {
+            pty = pty.cwd(dir);
+        }
+        if let Some(size) = self.read_buffer_size {
+            pty = pty.buffer_size(size);
+        }
+        self.pty = Some(pty.build(Arc::downgrade(&window)));
+        let size = window.inner_size();
+        self.resize_terminal(size.width, size.height);
     }
 
     fn window_event(
         &mut self,
-        _event_loop: &winit::event_loop::ActiveEventLoop,
+        event_loop: &winit::event_loop::ActiveEventLoop,
         _window_id: winit::window::WindowId,
         event: winit::event::WindowEvent,
     ) {
@@ -69,35 +302,104 @@ impl ApplicationHandler for Application {
 
         match event {
             WindowEvent::Resized(size) => {
-                state.surface_config.width = size.width;
-                state.surface_config.height = size.height;
-                state
-                    .surface
-                    .configure(&state.device, &state.surface_config);
+                state.resize(size.width, size.height);
                 state.window.request_redraw();
+                self.resize_terminal(size.width, size.height);
             }
-            WindowEvent::KeyboardInput { event, .. } => {
-                if event.state.is_pressed() {
-                    if let Some(pty) = &mut self.pty {
-                        match event.logical_key {
-                            Key::Character(c) => {
-                                let _ = pty.write(c.as_bytes());
-                            }
-                            Key::Named(NamedKey::Space) => {
-                                let _ = pty.write(" ".as_bytes());
-                            }
-                            Key::Named(NamedKey::Enter) => {
-                                let _ = pty.write(b"\r");
-                            }
-                            Key::Named(NamedKey::Backspace) => {
-                                let _ = pty.write(b"\x7f");
-                            }
-                            _ => {}
-                        }
+            // Moved to a display with another scale factor. The font is scaled to it, and the grid
+            // refitted to the surface until the `Resized` to the size winit suggests follows.
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                state.set_scale_factor(scale_factor);
+                state.window.request_redraw();
+                let (width, height) = (state.surface_config.width, state.surface_config.height);
+                self.resize_terminal(width, height);
+            }
+            WindowEvent::KeyboardInput { event, .. }
+                if event.state.is_pressed()
+                    && is_screenshot_key(&event.logical_key, self.modifiers) =>
+            {
+                match self.save_screenshot() {
+                    Ok(path) => eprintln!("ttyrant: screenshot saved to {}", path.display()),
+                    Err(err) => eprintln!("ttyrant: screenshot: {err}"),
+                }
+            }
+            WindowEvent::KeyboardInput { event, .. } if event.state.is_pressed() => {
+                let Some(bytes) =
+                    keymap::encode_key(&event.logical_key, self.modifiers, self.terminal.modes())
+                else {
+                    return;
+                };
+                let bytes = &bytes[..];
+
+                self.cursor_blink.reset();
+                self.terminal.input(bytes);
+                if self.terminal.transmit_paused() {
+                    self.held_input.extend_from_slice(bytes);
+                } else if let Some(pty) = &mut self.pty {
+                    let _ = pty.write(bytes);
+                }
+                // Redraw to show the cursor again in case it was in the off phase.
+                state.window.request_redraw();
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y.round() as i32,
+                    MouseScrollDelta::PixelDelta(position) => {
+                        let line_height = state.physical_metrics().line_height() as f64;
+                        self.wheel_pixels += position.y;
+                        let lines = (self.wheel_pixels / line_height).trunc();
+                        self.wheel_pixels -= lines * line_height;
+                        lines as i32
                     }
+                };
+                let Some(bytes) = self.terminal.wheel(lines) else {
+                    return;
+                };
+                if self.terminal.transmit_paused() {
+                    self.held_input.extend_from_slice(&bytes);
+                } else if let Some(pty) = &mut self.pty {
+                    let _ = pty.write(&bytes);
                 }
             }
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = modifiers.state(),
+            WindowEvent::Focused(focused) => {
+                self.cursor_blink.set_focused(focused);
+                state.window.request_redraw();
+            }
             WindowEvent::RedrawRequested => {
+                if let Some(pty) = &mut self.pty {
+                    while let Some(event) = pty.try_recv() {
+                        match event {
+                            PtyEvent::Output(output) => self.terminal.feed(&output),
+                            PtyEvent::Tick => {
+                                self.cursor_blink.tick();
+                            }
+                        }
+                    }
+                    // Replies to queries are sent like typed input, after anything held back.
+                    self.held_input.extend(self.terminal.take_responses());
+                    // Flush input held back by XOFF once the child sent XON.
+                    if !self.terminal.transmit_paused() && !self.held_input.is_empty() {
+                        let _ = pty.write(&self.held_input);
+                        self.held_input.clear();
+                    }
+                }
+
+                // Redraws asked for within a frame interval of the last one are drawn together
+                // once it has passed, see `about_to_wait`. So are those while the child writes a
+                // frame with synchronized output.
+                let now = Instant::now();
+                self.frame_pacer
+                    .hold(self.terminal.modes().synchronized_output, now);
+                self.frame_pacer.request();
+                if !self.frame_pacer.should_draw(now) {
+                    if let Some(deadline) = self.frame_pacer.deadline() {
+                        event_loop.set_control_flow(ControlFlow::WaitUntil(deadline));
+                    }
+                    return;
+                }
+                self.frame_pacer.drawn(now);
+
                 state.viewport.update(
                     &state.queue,
                     Resolution {
@@ -106,28 +408,52 @@ impl ApplicationHandler for Application {
                     },
                 );
 
-                let inner_size = state.window.inner_size();
-
-                let mut content_updated = false;
-                if let Some(pty) = &self.pty {
-                    while let Some(output) = pty.try_read() {
-                        self.content.push_str(&output);
-                        content_updated = true;
+                if self.terminal.take_bell() {
+                    match self.bell_mode {
+                        BellMode::None => {}
+                        BellMode::Visual => self.visual_bell.ring(),
+                        // There is no portable way to beep, pass it on to the terminal ttyrant
+                        // was started from, if any.
+                        BellMode::Audible => {
+                            let _ = std::io::stderr().write_all(b"\x07");
+                        }
                     }
                 }
 
-                if content_updated {
-                    state.text_buffer.set_text(
-                        &mut state.font_system,
-                        &self.content,
-                        Attrs::new().family(Family::Monospace),
-                        Shaping::Advanced,
-                    );
-                    state
-                        .text_buffer
-                        .shape_until_scroll(&mut state.font_system, false);
+                if self.terminal.title() != self.title {
+                    self.title = self.terminal.title().to_string();
+                    state.window.set_title(if self.title.is_empty() {
+                        self.default_title.as_deref().unwrap_or(DEFAULT_TITLE)
+                    } else {
+                        &self.title
+                    });
                 }
 
+                let damage = self.terminal.take_damage();
+                let cursor_row = self.terminal.buffer().cursor().y;
+                let rows = [
+                    damage.map(|(first, _)| first),
+                    damage.map(|(_, last)| last),
+                    self.drawn_cursor_row,
+                    Some(cursor_row),
+                ]
+                .into_iter()
+                .flatten();
+                let (buffer, style_cache) = (self.terminal.buffer(), &mut self.style_cache);
+                update_rows(
+                    &mut state.text_buffer,
+                    &screen_text(
+                        buffer,
+                        self.cursor_blink.is_visible() && self.terminal.modes().show_cursor,
+                    ),
+                    rows.clone().min().zip(rows.max()),
+                    |y, row| style_cache.row_attrs(buffer, y, row),
+                );
+                self.drawn_cursor_row = Some(cursor_row);
+                state
+                    .text_buffer
+                    .shape_until_scroll(&mut state.font_system, false);
+
                 state
                     .text_renderer
                     .prepare(
@@ -144,17 +470,36 @@ impl ApplicationHandler for Application {
                             bounds: TextBounds {
                                 left: 0,
                                 top: 0,
-                                right: inner_size.width as i32,
-                                bottom: inner_size.height as i32,
+                                right: state.surface_config.width as i32,
+                                bottom: state.surface_config.height as i32,
+                            },
+                            default_color: {
+                                let (r, g, b) = self.style_cache.palette().foreground;
+                                Color::rgb(r, g, b)
                             },
-                            default_color: Color::rgb(255, 255, 255),
                             custom_glyphs: &[],
                         }],
                         &mut state.swash_cache,
                     )
                     .unwrap();
 
-                let frame = state.surface.get_current_texture().unwrap();
+                let frame = match state.surface.get_current_texture() {
+                    Ok(frame) => frame,
+                    Err(error) => {
+                        match SurfaceRecovery::for_error(&error) {
+                            SurfaceRecovery::Reconfigure => {
+                                state.configure_surface();
+                                state.window.request_redraw();
+                            }
+                            SurfaceRecovery::Skip => {}
+                            SurfaceRecovery::Exit => {
+                                eprintln!("ttyrant: {error}");
+                                event_loop.exit();
+                            }
+                        }
+                        return;
+                    }
+                };
                 let view = frame.texture.create_view(&TextureViewDescriptor::default());
                 let mut encoder = state
                     .device
@@ -166,7 +511,12 @@ impl ApplicationHandler for Application {
                             view: &view,
                             resolve_target: None,
                             ops: Operations {
-                                load: LoadOp::Clear(wgpu::Color::BLACK),
+                                load: LoadOp::Clear(window::clear_color(
+                                    self.style_cache.palette().background,
+                                    0.5 * self.visual_bell.intensity(),
+                                    self.surface_options.opacity,
+                                    state.surface_config.alpha_mode,
+                                )),
                                 store: wgpu::StoreOp::Store,
                             },
                         })],
@@ -183,13 +533,125 @@ impl ApplicationHandler for Application {
 
                 state.queue.submit(Some(encoder.finish()));
                 frame.present();
+                if self.visual_bell.advance() {
+                    state.window.request_redraw();
+                }
 
                 state.atlas.trim();
             }
             _ => {}
         }
     }
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(state) = &self.window_state else {
+            return;
+        };
+        if self.frame_pacer.should_draw(Instant::now()) {
+            state.window.request_redraw();
+            event_loop.set_control_flow(ControlFlow::Wait);
+        }
+    }
+
     fn exiting(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
         self.pty = None
     }
 }
+
+/// Ctrl+Shift+S, which saves a screenshot instead of being sent to the child.
+fn is_screenshot_key(key: &Key, modifiers: ModifiersState) -> bool {
+    matches!(key, Key::Character(ch) if ch.eq_ignore_ascii_case("s"))
+        && modifiers.control_key()
+        && modifiers.shift_key()
+}
+
+/// Replaces the rows from `first` through `last` of `text_buffer` with the lines of `text`, so
+/// that only those are shaped again. All rows are replaced if their number changed. `attrs`
+/// gives the attributes of a row from its index and text.
+fn update_rows(
+    text_buffer: &mut glyphon::Buffer,
+    text: &str,
+    damage: Option<(usize, usize)>,
+    mut attrs: impl FnMut(usize, &str) -> AttrsList,
+) {
+    let rows: Vec<&str> = text.split('\n').collect();
+
+    if text_buffer.lines.len() != rows.len() {
+        text_buffer.lines = rows
+            .iter()
+            .enumerate()
+            .map(|(y, row)| BufferLine::new(*row, LineEnding::Lf, attrs(y, row), Shaping::Advanced))
+            .collect();
+    } else if let Some((first, last)) = damage {
+        for (y, (line, row)) in text_buffer.lines[first..=last.min(rows.len() - 1)]
+            .iter_mut()
+            .zip(&rows[first..])
+            .enumerate()
+        {
+            line.set_text(row, LineEnding::Lf, attrs(first + y, row));
+        }
+    }
+}
+
+/// Text of the grid with the cursor drawn as a block over the cell it is on if `show_cursor`.
+fn screen_text(buffer: &Buffer, show_cursor: bool) -> String {
+    if !show_cursor {
+        return buffer.to_string();
+    }
+
+    let cursor = buffer.cursor();
+    let mut text = String::new();
+    for (y, row) in buffer.to_string().split('\n').enumerate() {
+        if y > 0 {
+            text.push('\n');
+        }
+        if y != cursor.y {
+            text.push_str(row);
+            continue;
+        }
+
+        // Walk the cells rather than the characters: a cell may hold a whole cluster, a wide
+        // character is followed by a placeholder, and the cursor may be past the text.
+        let len = buffer
+            .line(y)
+            .map_or(0, |line| line.occupied_len() as usize);
+        let mut x = 0;
+        while x < len.max(cursor.x + 1) {
+            let cell = buffer.cell(x, y).unwrap_or_default();
+            if x == cursor.x {
+                text.push('\u{2588}');
+            } else {
+                buffer.push_text(&mut text, cell);
+            }
+            x += cell.ch.width().unwrap_or(1).max(1);
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cell::Cell;
+
+    fn print(buffer: &mut Buffer, text: &str) {
+        for ch in text.chars() {
+            let mut cell = Cell::default();
+            cell.ch = ch;
+            buffer.print_cell(cell);
+        }
+    }
+
+    #[test]
+    fn cursor_block_over_cell() {
+        let mut buffer = Buffer::new(6, 2);
+        print(&mut buffer, "e\u{301}x中y");
+        buffer.cursor_position(1, 2);
+        assert_eq!(screen_text(&buffer, true), "e\u{301}\u{2588}中y\n");
+
+        buffer.cursor_position(1, 5);
+        assert_eq!(screen_text(&buffer, true), "e\u{301}x中\u{2588}\n");
+        buffer.cursor_position(2, 3);
+        assert_eq!(screen_text(&buffer, true), "e\u{301}x中y\n  \u{2588}");
+        assert_eq!(screen_text(&buffer, false), buffer.to_string());
+    }
+}
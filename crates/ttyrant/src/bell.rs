@@ -0,0 +1,73 @@
+/// What happens when the child rings the bell with BEL.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum BellMode {
+    /// The bell is ignored.
+    None,
+    /// The background flashes, see [`VisualBell`].
+    #[default]
+    Visual,
+    /// A beep is played.
+    Audible,
+}
+
+/// Number of frames a visual bell flash takes to fade out.
+const FLASH_FRAMES: u8 = 4;
+
+/// Fading flash of the background for [`BellMode::Visual`], advanced once per drawn frame.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct VisualBell {
+    frames_left: u8,
+}
+
+impl VisualBell {
+    /// Starts the flash over at full intensity.
+    pub fn ring(&mut self) {
+        self.frames_left = FLASH_FRAMES;
+    }
+
+    /// How much of the flash color to mix into the background, from 0 (none) to 1.
+    pub fn intensity(&self) -> f64 {
+        f64::from(self.frames_left) / f64::from(FLASH_FRAMES)
+    }
+
+    /// Moves on to the next frame. Returns whether the flash is still visible, i.e. another frame
+    /// should be drawn.
+    pub fn advance(&mut self) -> bool {
+        self.frames_left = self.frames_left.saturating_sub(1);
+        self.frames_left > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flash_fades_out() {
+        let mut bell = VisualBell::default();
+        assert_eq!(bell.intensity(), 0.0);
+
+        bell.ring();
+        assert_eq!(bell.intensity(), 1.0);
+        assert!(bell.advance());
+        assert_eq!(bell.intensity(), 0.75);
+        assert!(bell.advance());
+        assert!(bell.advance());
+        assert_eq!(bell.intensity(), 0.25);
+
+        assert!(!bell.advance());
+        assert_eq!(bell.intensity(), 0.0);
+        assert!(!bell.advance());
+    }
+
+    #[test]
+    fn ringing_restarts_flash() {
+        let mut bell = VisualBell::default();
+        bell.ring();
+        bell.advance();
+        bell.advance();
+
+        bell.ring();
+        assert_eq!(bell.intensity(), 1.0);
+    }
+}
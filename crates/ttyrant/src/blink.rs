@@ -0,0 +1,87 @@
+/// Blink phase of the cursor, advanced by [`PtyEvent::Tick`](crate::pty::PtyEvent::Tick).
+///
+/// The cursor only blinks while the window is focused and is shown again right away on input, so
+/// it never disappears while the user is typing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CursorBlink {
+    visible: bool,
+    focused: bool,
+}
+
+impl Default for CursorBlink {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            focused: true,
+        }
+    }
+}
+
+impl CursorBlink {
+    /// Whether the cursor is drawn in the current phase.
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Toggles the phase. Returns whether it changed, i.e. a redraw is needed.
+    pub fn tick(&mut self) -> bool {
+        if !self.focused {
+            return false;
+        }
+        self.visible = !self.visible;
+        true
+    }
+
+    /// Restarts the phase with the cursor shown.
+    pub fn reset(&mut self) {
+        self.visible = true;
+    }
+
+    /// Blinking pauses with the cursor shown while the window is unfocused.
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+        self.visible = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_toggle_phase() {
+        let mut blink = CursorBlink::default();
+
+        assert!(blink.is_visible());
+        assert!(blink.tick());
+        assert!(!blink.is_visible());
+        assert!(blink.tick());
+        assert!(blink.is_visible());
+    }
+
+    #[test]
+    fn input_resets_phase() {
+        let mut blink = CursorBlink::default();
+        blink.tick();
+        blink.reset();
+
+        assert!(blink.is_visible());
+        blink.tick();
+        assert!(!blink.is_visible());
+    }
+
+    #[test]
+    fn paused_while_unfocused() {
+        let mut blink = CursorBlink::default();
+        blink.tick();
+        blink.set_focused(false);
+
+        assert!(blink.is_visible());
+        assert!(!blink.tick());
+        assert!(blink.is_visible());
+
+        blink.set_focused(true);
+        assert!(blink.tick());
+        assert!(!blink.is_visible());
+    }
+}
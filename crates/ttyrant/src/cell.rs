@@ -1,276 +1,2598 @@
 #![allow(dead_code)]
 
-use std::ops::Deref;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    hash::{Hash, Hasher},
+    mem,
+    ops::{BitOr, Deref, Range},
+};
+
+use ansi::ansi::{Rectangle, UnderlineStyle};
+use unicode_width::UnicodeWidthChar;
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq, Hash)]
 pub struct Buffer {
-    lines: Vec<Line>,
+    /// Rows of the screen, top to bottom. A ring buffer so that scrolling the whole screen only
+    /// moves the row leaving it rather than all of them.
+    lines: VecDeque<Line>,
+    /// Rows scrolled off the top of the screen, oldest first.
+    scrollback: VecDeque<Line>,
+    /// Maximum number of rows kept in `scrollback`.
+    scrollback_limit: usize,
     width: usize,
     height: usize,
+    cursor: Cursor,
+    /// Set when a character was printed into the last column. The wrap to the next line is
+    /// deferred until the next character is printed, so that filling the last column doesn't
+    /// scroll the screen.
+    pending_wrap: bool,
+    /// DECAWM. When disabled, printing at the last column overwrites it instead of wrapping.
+    autowrap: bool,
+    /// IRM. When enabled, printing shifts the rest of the line right instead of overwriting it.
+    insert: bool,
+    tab_stops: Vec<bool>,
+    /// DECSTBM: the rows between the top and bottom margins, which scroll when a line feed
+    /// reaches the bottom margin.
+    scroll_region: Range<usize>,
+    damage: Damage,
+    /// Grapheme clusters too long to be stored in their cell, see [`Buffer::join`].
+    clusters: Clusters,
 }
 
-impl Buffer {
-    pub fn new(width: usize, height: usize) -> Self {
-        let mut lines = Vec::with_capacity(height);
-        for _ in 0..height {
-            lines.push(Line::new(width as u16));
-        }
-        Self {
-            lines,
-            width,
-            height,
+/// Characters following the first one of the grapheme clusters in a buffer that don't fit into
+/// their [`Cell`]. Entries are shared by all cells holding the same cluster. Clusters are built up
+/// as their characters arrive, so their prefixes are stored as well; entries no cell refers to
+/// anymore are freed by [`Buffer::collect_clusters`].
+///
+/// Cells are compared by their ids, so like [`Damage`] the table is ignored when buffers are
+/// compared or hashed.
+#[derive(Debug, Default)]
+struct Clusters {
+    /// Indexed by id minus [`Clusters::FIRST_ID`], `None` for freed entries.
+    entries: Vec<Option<Box<str>>>,
+    ids: HashMap<Box<str>, u16>,
+    /// Ids of freed entries, reused before new ones are added.
+    free: Vec<u16>,
+}
+
+impl Clusters {
+    /// The ids are the surrogate code points, which aren't characters and so can't be mistaken
+    /// for one stored inline.
+    const FIRST_ID: u16 = 0xD800;
+    const CAPACITY: usize = 0x800;
+
+    fn is_id(id: u16) -> bool {
+        (Self::FIRST_ID..Self::FIRST_ID + Self::CAPACITY as u16).contains(&id)
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn get(&self, id: u16) -> &str {
+        self.entries
+            .get((id - Self::FIRST_ID) as usize)
+            .and_then(Option::as_deref)
+            .unwrap_or("")
+    }
+
+    /// Id of `extra`, added if it isn't stored yet. `None` if the table is full.
+    fn intern(&mut self, extra: &str) -> Option<u16> {
+        if let Some(&id) = self.ids.get(extra) {
+            return Some(id);
         }
+        let id = match self.free.pop() {
+            Some(id) => id,
+            None if self.entries.len() < Self::CAPACITY => {
+                self.entries.push(None);
+                Self::FIRST_ID + (self.entries.len() - 1) as u16
+            }
+            None => return None,
+        };
+        self.entries[(id - Self::FIRST_ID) as usize] = Some(extra.into());
+        self.ids.insert(extra.into(), id);
+        Some(id)
     }
 
-    pub fn write_str(&mut self, x: usize, y: usize, s: &str, attrs: CellAttributes) {
-        if y >= self.height {
+    /// Frees the entries whose id isn't marked in `live`, indexed like `entries`.
+    fn retain(&mut self, live: &[bool]) {
+        if !live.contains(&true) {
+            *self = Self::default();
             return;
         }
-
-        let line = &mut self.lines[y];
-        for (i, ch) in s.chars().enumerate() {
-            if x + i >= self.width {
-                break;
+        for (index, entry) in self.entries.iter_mut().enumerate() {
+            if !live[index] {
+                if let Some(extra) = entry.take() {
+                    self.ids.remove(&extra);
+                    self.free.push(Self::FIRST_ID + index as u16);
+                }
             }
-            line.set((x + i) as u16, Cell::new(ch, attrs))
         }
     }
 }
 
-const INLINE_CELLS: usize = 8;
+impl PartialEq for Clusters {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
 
-#[derive(Debug, Clone)]
-pub struct Line {
-    inline_cells: [(u16, Cell); INLINE_CELLS],
-    inline_count: u8,
-    overflow: Option<Box<Vec<(u16, Cell)>>>,
-    attributes: CellAttributes,
-    width: u16,
+impl Eq for Clusters {}
+
+impl Hash for Clusters {
+    fn hash<H: Hasher>(&self, _: &mut H) {}
 }
 
-impl Line {
-    pub fn new(width: u16) -> Self {
-        Self {
-            inline_cells: Default::default(),
-            inline_count: 0,
-            overflow: None,
-            attributes: CellAttributes::default(),
-            width,
+/// First and last row changed since the damage was last taken, see [`Buffer::take_damage`].
+///
+/// It describes what needs to be redrawn rather than the content, so it is ignored when buffers
+/// are compared or hashed.
+#[derive(Debug, Default, Clone, Copy)]
+struct Damage(Option<(usize, usize)>);
+
+impl Damage {
+    fn add(&mut self, rows: Range<usize>) {
+        if rows.is_empty() {
+            return;
         }
+        let (first, last) = (rows.start, rows.end - 1);
+        self.0 = Some(match self.0 {
+            Some((min, max)) => (min.min(first), max.max(last)),
+            None => (first, last),
+        });
     }
+}
 
-    pub fn get(&self, x: u16) -> Cell {
-        dbg!("get with lc", self.inline_count);
-        if x >= self.width {
-            return Cell::default();
-        }
+impl PartialEq for Damage {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
 
-        for i in 0..self.inline_count as usize {
-            dbg!(self.inline_cells[i]);
-            if self.inline_cells[i].0 == x {
-                return self.inline_cells[i].1;
-            }
+impl Eq for Damage {}
+
+impl Hash for Damage {
+    fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+
+const TAB_WIDTH: usize = 8;
+
+/// Joins the characters around it into a single grapheme cluster, e.g. in emoji sequences.
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+
+pub const DEFAULT_SCROLLBACK_LIMIT: usize = 10_000;
+
+/// How [`Buffer::resize`] treats existing content.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum ResizeMode {
+    /// Truncate or pad each row independently. Cheap, but lines wrapped at the old width stay
+    /// broken at that width.
+    Clip,
+    /// Rejoin wrapped rows into logical lines and wrap them again at the new width.
+    #[default]
+    Reflow,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Cursor {
+    pub x: usize,
+    pub y: usize,
+}
+
+impl Buffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        let mut lines = VecDeque::with_capacity(height);
+        for _ in 0..height {
+            lines.push_back(Line::new(width as u16));
+        }
+        Self {
+            lines,
+            scrollback: VecDeque::new(),
+            scrollback_limit: DEFAULT_SCROLLBACK_LIMIT,
+            width,
+            height,
+            cursor: Cursor::default(),
+            pending_wrap: false,
+            autowrap: true,
+            insert: false,
+            tab_stops: (0..width).map(|x| x % TAB_WIDTH == 0).collect(),
+            scroll_region: 0..height,
+            damage: Damage(Some((0, height.saturating_sub(1)))),
+            clusters: Clusters::default(),
         }
+    }
 
-        if let Some(overflow) = &self.overflow {
-            if let Ok(i) = overflow.binary_search_by_key(&x, |(pos, _)| *pos) {
-                return overflow[i].1;
-            }
+    /// Returns the cell at `(x, y)`, or `None` if the position is outside the grid.
+    pub fn cell(&self, x: usize, y: usize) -> Option<Cell> {
+        if x >= self.width || y >= self.height {
+            return None;
         }
+        Some(self.lines[y].get(x as u16))
+    }
 
-        Cell::default()
+    /// Text of the cell at `(x, y)`: its whole grapheme cluster, see [`Buffer::push_text`].
+    pub fn text_at(&self, x: usize, y: usize) -> Option<String> {
+        let mut text = String::new();
+        self.push_text(&mut text, self.cell(x, y)?);
+        Some(text)
     }
 
-    pub fn set(&mut self, x: u16, cell: Cell) {
-        if x >= self.width {
-            return;
+    /// Appends the grapheme cluster held by `cell`, which must be from this buffer, to `out`.
+    pub fn push_text(&self, out: &mut String, cell: Cell) {
+        out.push(cell.ch);
+        out.push_str(self.extra(cell, &mut [0; 4]));
+    }
+
+    /// `cell` with `ch` added to the end of its grapheme cluster. A second character from the
+    /// Basic Multilingual Plane is kept in the cell, longer clusters are stored in the buffer.
+    pub fn join(&mut self, mut cell: Cell, ch: char) -> Cell {
+        if cell.extra == 0 && (ch as u32) < 0x10000 {
+            cell.extra = ch as u16;
+            return cell;
         }
 
-        let cell_is_default = cell.is_default();
+        let mut extra = String::from(self.extra(cell, &mut [0; 4]));
+        extra.push(ch);
+        let id = self.clusters.intern(&extra).or_else(|| {
+            self.collect_clusters();
+            self.clusters.intern(&extra)
+        });
+        // Still full: every stored cluster is on the screen or in scrollback.
+        if let Some(id) = id {
+            cell.extra = id;
+        }
+        cell
+    }
 
-        for i in 0..self.inline_count as usize {
-            if self.inline_cells[i].0 == x {
-                if cell_is_default {
-                    self.inline_cells
-                        .copy_within(i + 1..self.inline_count as usize, i);
-                    self.inline_count -= 1;
-                } else {
-                    self.inline_cells[i].1 = cell;
+    /// Frees the stored clusters no cell on the screen or in scrollback refers to, such as the
+    /// prefixes of clusters built up character by character.
+    fn collect_clusters(&mut self) {
+        let mut live = vec![false; self.clusters.entries.len()];
+        for line in self.lines.iter().chain(&self.scrollback) {
+            for cell in line.cells() {
+                if Clusters::is_id(cell.extra) {
+                    live[(cell.extra - Clusters::FIRST_ID) as usize] = true;
                 }
             }
-            return;
         }
+        self.clusters.retain(&live);
+    }
 
-        if cell_is_default {
-            if let Some(overflow) = &mut self.overflow {
-                if let Ok(i) = overflow.binary_search_by_key(&x, |(pos, _)| *pos) {
-                    overflow.remove(i);
-                }
-            };
-            return;
+    /// Characters of the cluster in `cell` following [`Cell::ch`], encoded into `scratch` if it is
+    /// stored inline.
+    fn extra<'a>(&'a self, cell: Cell, scratch: &'a mut [u8; 4]) -> &'a str {
+        match cell.extra {
+            0 => "",
+            id if Clusters::is_id(id) => self.clusters.get(id),
+            // Only ever set from a character.
+            ch => char::from_u32(ch as u32).map_or("", |ch| ch.encode_utf8(scratch)),
         }
+    }
 
-        if self.inline_count < INLINE_CELLS as u8 {
-            let i = self.find_insert_position(x);
-            self.inline_cells
-                .copy_within(i..self.inline_count as usize, i + 1);
-            self.inline_cells[i] = (x, cell);
-            self.inline_count += 1;
-            dbg!("set with count", self.inline_count);
+    fn last_char(&self, cell: Cell) -> char {
+        self.extra(cell, &mut [0; 4])
+            .chars()
+            .next_back()
+            .unwrap_or(cell.ch)
+    }
+
+    /// Replaces the cell at `(x, y)`. Positions outside the grid are ignored.
+    pub fn set_cell(&mut self, x: usize, y: usize, cell: Cell) {
+        if x >= self.width || y >= self.height {
             return;
         }
+        self.lines[y].set(x as u16, cell);
+        self.damage.add(y..y + 1);
+    }
 
-        let overflow = self.overflow.get_or_insert_with(|| Box::new(Vec::new()));
-        match overflow.binary_search_by_key(&x, |(pos, _)| *pos) {
-            Ok(i) => overflow[i].1 = cell,
-            Err(i) => overflow.insert(i, (x, cell)),
-        };
+    pub fn width(&self) -> usize {
+        self.width
     }
 
-    fn find_insert_position(&self, x: u16) -> usize {
-        for i in 0..self.inline_count as usize {
-            if self.inline_cells[i].0 > x {
-                return i;
-            }
-        }
-        self.inline_count as usize
+    pub fn height(&self) -> usize {
+        self.height
     }
-}
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub struct Cell {
-    pub ch: char,
-    pub attrs: CellAttributes,
-    _padding: [u8; 3],
-    pub fg: Color,
-    pub bg: Color,
-}
+    pub fn line(&self, y: usize) -> Option<&Line> {
+        self.lines.get(y)
+    }
 
-impl Cell {
-    pub fn new(ch: char, attrs: CellAttributes) -> Self {
-        Self {
-            ch,
-            attrs,
-            ..Default::default()
+    /// First and last row of the logical line `row` is part of, i.e. of the rows joined by
+    /// autowrap. Only rows on the screen are considered; `row` is clamped to it.
+    pub fn logical_line_bounds(&self, row: usize) -> (usize, usize) {
+        let row = row.min(self.height - 1);
+        let mut start = row;
+        while start > 0 && self.lines[start].is_wrapped() {
+            start -= 1;
+        }
+        let mut end = row;
+        while self.lines.get(end + 1).is_some_and(Line::is_wrapped) {
+            end += 1;
         }
+        (start, end)
     }
-    pub fn is_default(self) -> bool {
-        self == Self::default()
+
+    pub fn cursor(&self) -> Cursor {
+        self.cursor
     }
-}
 
-impl Default for Cell {
-    fn default() -> Self {
-        Self {
-            ch: ' ',
-            attrs: CellAttributes::default(),
-            _padding: [0x00, 0x00, 0x00],
-            fg: Color::indexed(0),
-            bg: Color::indexed(0),
+    /// Whether a character was printed into the last column and the cursor waits there for the
+    /// next one to wrap onto the following line.
+    pub fn pending_wrap(&self) -> bool {
+        self.pending_wrap
+    }
+
+    /// First and last row whose content changed since the last [`Buffer::take_damage`], or
+    /// `None` if nothing did. A new buffer is damaged entirely.
+    pub fn damage(&self) -> Option<(usize, usize)> {
+        self.damage.0
+    }
+
+    /// Like [`Buffer::damage`], resetting it so that only changes made from now on are reported.
+    /// Meant to be called when the rows are drawn.
+    pub fn take_damage(&mut self) -> Option<(usize, usize)> {
+        self.damage.0.take()
+    }
+
+    /// Marks every row as changed, for when the buffer is shown again in place of another one.
+    pub fn damage_all(&mut self) {
+        self.damage.add(0..self.height);
+    }
+
+    pub fn scrollback_len(&self) -> usize {
+        self.scrollback.len()
+    }
+
+    /// Returns a row of scrollback, `0` being the oldest one kept.
+    pub fn scrollback_line(&self, index: usize) -> Option<&Line> {
+        self.scrollback.get(index)
+    }
+
+    /// The `height` rows to show when scrolled `offset` rows back into scrollback, top to bottom.
+    /// The offset is clamped to the available history.
+    pub fn visible_rows(&self, offset: usize) -> impl Iterator<Item = &Line> {
+        let offset = offset.min(self.scrollback.len());
+        self.scrollback
+            .range(self.scrollback.len() - offset..)
+            .chain(&self.lines)
+            .take(self.height)
+    }
+
+    pub fn scrollback_limit(&self) -> usize {
+        self.scrollback_limit
+    }
+
+    /// Sets the maximum number of rows kept in scrollback, dropping the oldest rows if there are
+    /// more already. A limit of 0 disables scrollback.
+    pub fn set_scrollback_limit(&mut self, limit: usize) {
+        self.scrollback_limit = limit;
+        if self.scrollback.len() > limit {
+            self.scrollback.drain(..self.scrollback.len() - limit);
+            self.scrollback.shrink_to_fit();
         }
     }
-}
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub struct CellAttributes {}
+    /// Approximate number of bytes used by the screen and scrollback.
+    pub fn memory_estimate(&self) -> usize {
+        let lines: usize = self
+            .lines
+            .iter()
+            .chain(&self.scrollback)
+            .map(Line::memory_estimate)
+            .sum();
 
-impl Default for CellAttributes {
-    fn default() -> Self {
-        Self {}
+        mem::size_of::<Self>() + lines + self.tab_stops.capacity()
     }
-}
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub struct Color(u32);
+    /// Handles both CUP (`CSI row ; col H`) and HVP (`CSI row ; col f`).
+    ///
+    /// `row` and `col` are 1-based as received from the parser. A value of 0 is treated as 1 and
+    /// values past the screen edge are clamped to the last row/column.
+    pub fn cursor_position(&mut self, row: u16, col: u16) {
+        self.cursor.y = (row.max(1) as usize - 1).min(self.height.saturating_sub(1));
+        self.cursor.x = (col.max(1) as usize - 1).min(self.width.saturating_sub(1));
+        self.pending_wrap = false;
+    }
 
-impl Color {
-    const R_SHIFT: u32 = 16;
-    const G_SHIFT: u32 = 8;
-    const B_SHIFT: u32 = 0;
+    pub fn scroll_region(&self) -> Range<usize> {
+        self.scroll_region.clone()
+    }
 
-    const R_MASK: u32 = 0xFF << Self::R_SHIFT;
-    const G_MASK: u32 = 0xFF << Self::G_SHIFT;
-    const B_MASK: u32 = 0xFF << Self::B_SHIFT;
+    /// DECSTBM (`CSI top ; bottom r`): sets the 1-based, inclusive margins of the scroll region
+    /// and moves the cursor home. 0 selects the first or last row respectively. Regions of less
+    /// than two rows are ignored.
+    pub fn set_scroll_region(&mut self, top: u16, bottom: u16) {
+        let top = top.max(1) as usize - 1;
+        let bottom = match bottom {
+            0 => self.height,
+            bottom => (bottom as usize).min(self.height),
+        };
+        if top + 1 >= bottom {
+            return;
+        }
 
-    const INDEX_FLAG: u32 = 1 << 31;
+        self.scroll_region = top..bottom;
+        self.cursor = Cursor::default();
+        self.pending_wrap = false;
+    }
 
-    #[inline]
-    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
-        Self(
-            ((r as u32) << Self::R_SHIFT)
-                | ((g as u32) << Self::G_SHIFT)
-                | ((b as u32) << Self::B_SHIFT),
-        )
+    pub fn set_autowrap(&mut self, enabled: bool) {
+        self.autowrap = enabled;
+        if !enabled {
+            self.pending_wrap = false;
+        }
     }
 
-    #[inline]
-    pub const fn indexed(index: u8) -> Self {
-        Self(Self::INDEX_FLAG | (index as u32))
+    pub fn set_insert(&mut self, enabled: bool) {
+        self.insert = enabled;
     }
 
-    #[inline]
-    pub fn r(&self) -> u8 {
-        ((**self & Self::R_MASK) >> Self::R_SHIFT) as u8
+    /// Moves the cursor up `n` rows (at least one), stopping at the top.
+    pub fn cursor_up(&mut self, n: u16) {
+        self.cursor.y = self.cursor.y.saturating_sub(n.max(1) as usize);
+        self.pending_wrap = false;
     }
 
-    #[inline]
-    pub fn g(&self) -> u8 {
-        ((**self & Self::G_MASK) >> Self::G_SHIFT) as u8
+    /// Moves the cursor down `n` rows (at least one), stopping at the bottom.
+    pub fn cursor_down(&mut self, n: u16) {
+        self.cursor.y = (self.cursor.y + n.max(1) as usize).min(self.height - 1);
+        self.pending_wrap = false;
     }
 
-    #[inline]
-    pub fn b(&self) -> u8 {
-        ((**self & Self::B_MASK) >> Self::B_SHIFT) as u8
+    /// Moves the cursor right `n` columns (at least one), stopping at the last column.
+    pub fn cursor_forward(&mut self, n: u16) {
+        self.cursor.x = (self.cursor.x + n.max(1) as usize).min(self.width - 1);
+        self.pending_wrap = false;
     }
 
-    #[inline]
-    pub fn is_indexed(&self) -> bool {
-        **self & Self::INDEX_FLAG != 0
+    /// Moves the cursor left `n` columns (at least one), stopping at the first column.
+    pub fn cursor_backward(&mut self, n: u16) {
+        self.cursor.x = self.cursor.x.saturating_sub(n.max(1) as usize);
+        self.pending_wrap = false;
     }
 
-    #[inline]
-    pub fn index(&self) -> Option<u8> {
-        if self.is_indexed() {
-            Some(**self as u8)
+    /// Prints `ch` at the cursor and advances it, wrapping onto the next line when the end of the
+    /// line is reached.
+    pub fn print(&mut self, ch: char, attrs: CellAttributes) {
+        self.print_cell(Cell::new(ch, attrs));
+    }
+
+    /// Position of the character the cursor follows on its row, i.e. the one printed last when
+    /// printing sequentially. `None` at the start of a row.
+    pub fn previous_cell(&self) -> Option<(usize, usize)> {
+        let Cursor { x, y } = self.cursor;
+        let x = if self.pending_wrap {
+            x
         } else {
-            None
+            x.checked_sub(1)?
+        };
+        if self.lines[y].get(x as u16).is_wide_continuation() {
+            return Some((x - 1, y));
         }
+        Some((x, y))
     }
-}
 
-impl Deref for Color {
-    type Target = u32;
+    /// Like [`Buffer::print`], taking the colors along with the attributes from `cell`.
+    ///
+    /// Zero-width characters such as combining marks, and characters following a zero width
+    /// joiner, are added to the grapheme cluster of the previous cell instead.
+    pub fn print_cell(&mut self, cell: Cell) {
+        let width = cell.ch.width().unwrap_or(0);
+        if let Some((x, y)) = self.previous_cell() {
+            let previous = self.lines[y].get(x as u16);
+            if cell.ch.width() == Some(0) || self.last_char(previous) == ZERO_WIDTH_JOINER {
+                let joined = self.join(previous, cell.ch);
+                self.lines[y].set(x as u16, joined);
+                self.damage.add(y..y + 1);
+                return;
+            }
+        }
+        if width == 0 || width > self.width {
+            return;
+        }
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+        if self.pending_wrap || self.cursor.x + width > self.width {
+            if self.autowrap {
+                self.wrap();
+            } else {
+                self.cursor.x = self.width - width;
+            }
+        }
+
+        if self.insert {
+            self.lines[self.cursor.y].insert_blank(self.cursor.x as u16, width as u16);
+        }
+        self.lines[self.cursor.y].put(self.cursor.x as u16, cell);
+        self.damage.add(self.cursor.y..self.cursor.y + 1);
+
+        self.cursor.x += width;
+        if self.cursor.x >= self.width {
+            self.cursor.x = self.width - 1;
+            self.pending_wrap = self.autowrap;
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Moves the cursor down one line, scrolling the screen up if it is on the last line.
+    pub fn linefeed(&mut self) {
+        self.pending_wrap = false;
+        if self.cursor.y + 1 == self.scroll_region.end {
+            self.scroll_up();
+        } else if self.cursor.y + 1 < self.height {
+            self.cursor.y += 1;
+        }
+    }
 
-    #[test]
-    fn write_single_char_to_cell() {
-        let mut buffer = Buffer::new(10, 10);
-        buffer.write_str(0, 0, "C", CellAttributes::default());
-        let char = buffer.lines.first().unwrap().get(0).ch;
+    /// RI: moves the cursor up one line, scrolling the scroll region down if it is on the top
+    /// margin. Nothing scrolls above the region, where the cursor stops at the first line.
+    pub fn reverse_index(&mut self) {
+        self.pending_wrap = false;
+        let Range { start, end } = self.scroll_region;
+        if self.cursor.y == start {
+            let mut line = self
+                .lines
+                .remove(end - 1)
+                .expect("scroll region is on the screen");
+            line.reset(self.width as u16);
+            self.lines.insert(start, line);
+            self.damage.add(start..end);
+        } else if self.cursor.y > 0 {
+            self.cursor.y -= 1;
+        }
+    }
 
-        assert_eq!(char, 'C');
+    /// Moves the cursor one column to the left, stopping at the first column. Unlike erase
+    /// operations this leaves the cell contents untouched.
+    pub fn backspace(&mut self) {
+        self.cursor.x = self.cursor.x.saturating_sub(1);
+        self.pending_wrap = false;
     }
 
-    #[test]
-    fn write_string_to_cells() {
-        let mut buffer = Buffer::new(10, 10);
-        buffer.write_str(0, 0, "CCC", CellAttributes::default());
-        let char1 = buffer.lines.first().unwrap().get(0).ch;
-        let char2 = buffer.lines.first().unwrap().get(1).ch;
-        let char3 = buffer.lines.first().unwrap().get(2).ch;
+    /// IL (`CSI Ps L`): inserts `n` blank lines at the cursor row. Lines below move down and
+    /// those pushed past the bottom margin are lost. Does nothing outside the scroll region.
+    pub fn insert_blank_lines(&mut self, n: u16) {
+        let Range { start, end } = self.scroll_region;
+        let y = self.cursor.y;
+        if !(start..end).contains(&y) {
+            return;
+        }
 
-        assert_eq!(char1, 'C');
-        assert_eq!(char2, 'C');
-        assert_eq!(char3, 'C');
+        for _ in 0..(n.max(1) as usize).min(end - y) {
+            let mut line = self
+                .lines
+                .remove(end - 1)
+                .expect("scroll region is on the screen");
+            line.reset(self.width as u16);
+            self.lines.insert(y, line);
+        }
+        self.damage.add(y..end);
+        self.cursor.x = 0;
+        self.pending_wrap = false;
+    }
+
+    /// DL (`CSI Ps M`): deletes `n` lines starting at the cursor row. Lines below move up and
+    /// blank lines are added at the bottom margin. Does nothing outside the scroll region.
+    pub fn delete_lines(&mut self, n: u16) {
+        let Range { start, end } = self.scroll_region;
+        let y = self.cursor.y;
+        if !(start..end).contains(&y) {
+            return;
+        }
+
+        for _ in 0..(n.max(1) as usize).min(end - y) {
+            let mut line = self
+                .lines
+                .remove(y)
+                .expect("scroll region is on the screen");
+            line.reset(self.width as u16);
+            self.lines.insert(end - 1, line);
+        }
+        self.damage.add(y..end);
+        self.cursor.x = 0;
+        self.pending_wrap = false;
+    }
+
+    pub fn carriage_return(&mut self) {
+        self.cursor.x = 0;
+        self.pending_wrap = false;
+    }
+
+    /// Advances the cursor to the next tab stop, filling the skipped cells with blanks carrying
+    /// `attrs`. The cursor stops at the last column if there is no further tab stop.
+    pub fn write_tab(&mut self, attrs: CellAttributes) {
+        let start = self.cursor.x;
+        let end = (start + 1..self.width)
+            .find(|&x| self.tab_stops[x])
+            .unwrap_or(self.width - 1);
+
+        let line = &mut self.lines[self.cursor.y];
+        for x in start..end {
+            line.set(x as u16, Cell::new(' ', attrs));
+        }
+        self.damage.add(self.cursor.y..self.cursor.y + 1);
+        self.cursor.x = end;
+    }
+
+    /// EL (`CSI Ps K`): erases from the cursor to the end of the line (0), from the start of the
+    /// line through the cursor (1) or the whole line (2).
+    ///
+    /// A selective erase (DECSEL, `CSI ? Ps K`) leaves cells marked
+    /// [`CellAttributes::PROTECTED`] untouched. Erased cells take the background color `bg`,
+    /// which is the current one of the pen.
+    pub fn erase_in_line(&mut self, mode: u8, selective: bool, bg: Color) {
+        let (x, y) = (self.cursor.x, self.cursor.y);
+        let columns = match mode {
+            0 => x..self.width,
+            1 => 0..x + 1,
+            2 => 0..self.width,
+            _ => return,
+        };
+        self.erase(y, columns, selective, bg);
+        self.pending_wrap = false;
+    }
+
+    /// ED (`CSI Ps J`): erases from the cursor to the end of the screen (0), from the start of
+    /// the screen through the cursor (1) or the whole screen (2). Mode 3 clears the scrollback.
+    ///
+    /// A selective erase (DECSED, `CSI ? Ps J`) leaves cells marked
+    /// [`CellAttributes::PROTECTED`] untouched, and doesn't affect the scrollback. Erased cells
+    /// take the background color `bg`.
+    pub fn erase_in_display(&mut self, mode: u8, selective: bool, bg: Color) {
+        let (x, y) = (self.cursor.x, self.cursor.y);
+        match mode {
+            0 => {
+                self.erase(y, x..self.width, selective, bg);
+                for y in y + 1..self.height {
+                    self.erase(y, 0..self.width, selective, bg);
+                }
+            }
+            1 => {
+                for y in 0..y {
+                    self.erase(y, 0..self.width, selective, bg);
+                }
+                self.erase(y, 0..x + 1, selective, bg);
+            }
+            2 => {
+                for y in 0..self.height {
+                    self.erase(y, 0..self.width, selective, bg);
+                }
+            }
+            3 if !selective => {
+                self.scrollback.clear();
+                self.collect_clusters();
+            }
+            _ => return,
+        }
+        self.pending_wrap = false;
+    }
+
+    /// DECFRA: fills `area` with copies of `cell`, which must hold a single-width character.
+    pub fn fill_rectangle(&mut self, area: Rectangle, cell: Cell) {
+        let (columns, rows) = self.rectangle(area);
+        for y in rows {
+            // Also clears wide characters sticking out of the area.
+            self.erase(y, columns.clone(), false, Color::DEFAULT);
+            for x in columns.clone() {
+                self.lines[y].set(x as u16, cell);
+            }
+        }
+    }
+
+    /// DECERA: erases the cells in `area`.
+    pub fn erase_rectangle(&mut self, area: Rectangle) {
+        let (columns, rows) = self.rectangle(area);
+        for y in rows {
+            self.erase(y, columns.clone(), false, Color::DEFAULT);
+        }
+    }
+
+    /// Columns and rows covered by the 1-based, inclusive bounds of `area`. Omitted bounds
+    /// extend to the edge of the screen, bounds past it are clamped.
+    fn rectangle(&self, area: Rectangle) -> (Range<usize>, Range<usize>) {
+        let bound = |value: u16, len: usize| match value {
+            0 => len,
+            value => (value as usize).min(len),
+        };
+        let top = area.top.max(1) as usize - 1;
+        let left = area.left.max(1) as usize - 1;
+        let bottom = bound(area.bottom, self.height);
+        let right = bound(area.right, self.width);
+        (left..right.max(left), top..bottom.max(top))
+    }
+
+    /// Blanks the screen, resets the scroll region and moves the cursor home, as done by RIS or
+    /// `clear`. The scrollback is kept.
+    pub fn clear(&mut self) {
+        for line in &mut self.lines {
+            *line = Line::new(self.width as u16);
+        }
+        self.scroll_region = 0..self.height;
+        self.cursor = Cursor::default();
+        self.pending_wrap = false;
+        self.damage.add(0..self.height);
+        self.collect_clusters();
+    }
+
+    fn erase(&mut self, y: usize, columns: Range<usize>, selective: bool, bg: Color) {
+        self.damage.add(y..y + 1);
+        let line = &mut self.lines[y];
+        let erased = |cell: Cell| !(selective && cell.attrs.contains(CellAttributes::PROTECTED));
+
+        let (start, end) = (columns.start as u16, columns.end as u16);
+        if erased(line.get(start)) {
+            line.split_wide(start, start);
+        }
+        if end > start && erased(line.get(end - 1)) {
+            line.split_wide(end, end);
+        }
+        let blank = Cell {
+            bg,
+            ..Cell::default()
+        };
+        for x in start..end {
+            if erased(line.get(x)) {
+                line.set(x, blank);
+            }
+        }
+    }
+
+    fn wrap(&mut self) {
+        self.linefeed();
+        self.cursor.x = 0;
+        self.lines[self.cursor.y].set_wrapped(true);
+    }
+
+    /// Scrolls the scroll region up by one row. Only rows scrolled off the top of the screen are
+    /// kept in scrollback.
+    ///
+    /// Without margins this rotates the ring buffer in constant time. The new blank row reuses
+    /// the row dropped from the screen or the scrollback to avoid allocating.
+    fn scroll_up(&mut self) {
+        let Range { start, end } = self.scroll_region;
+        let line = self
+            .lines
+            .remove(start)
+            .expect("scroll region is on the screen");
+        self.damage.add(start..end);
+
+        let reused = if start > 0 {
+            Some(line)
+        } else {
+            self.push_scrollback(line)
+        };
+        let mut blank = reused.unwrap_or_else(|| Line::new(self.width as u16));
+        blank.reset(self.width as u16);
+        self.lines.insert(end - 1, blank);
+    }
+
+    /// Adds a row leaving the top of the screen to scrollback, returning the row that no longer
+    /// fits: the oldest one kept, or `line` itself if scrollback is disabled.
+    fn push_scrollback(&mut self, line: Line) -> Option<Line> {
+        if self.scrollback_limit == 0 {
+            return Some(line);
+        }
+        let dropped = if self.scrollback.len() == self.scrollback_limit {
+            self.scrollback.pop_front()
+        } else {
+            None
+        };
+        self.scrollback.push_back(line);
+        dropped
+    }
+
+    /// Resizes the grid, see [`ResizeMode`] for how existing rows are carried over.
+    ///
+    /// Rows pushed past the top of the screen to keep the cursor visible move to scrollback, as if
+    /// they had been scrolled off.
+    pub fn resize(&mut self, width: usize, height: usize, mode: ResizeMode) {
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        let (mut lines, mut cursor) = match mode {
+            ResizeMode::Clip => self.clip(width),
+            ResizeMode::Reflow => self.reflow(width),
+        };
+
+        // Scroll rows off the top if the cursor would end up below the screen.
+        let excess = (cursor.y + 1).saturating_sub(height);
+        for line in lines.drain(..excess.min(lines.len())) {
+            self.push_scrollback(line);
+        }
+        cursor.y -= excess;
+        lines.resize_with(height, || Line::new(width as u16));
+
+        self.lines = lines.into();
+        self.width = width;
+        self.height = height;
+        self.cursor = Cursor {
+            x: cursor.x.min(width - 1),
+            y: cursor.y,
+        };
+        self.pending_wrap = false;
+        self.scroll_region = 0..height;
+        self.damage = Damage(Some((0, height - 1)));
+        self.tab_stops.truncate(width);
+        let len = self.tab_stops.len();
+        self.tab_stops
+            .extend((len..width).map(|x| x % TAB_WIDTH == 0));
+    }
+
+    fn clip(&self, width: usize) -> (Vec<Line>, Cursor) {
+        let lines = self
+            .lines
+            .iter()
+            .map(|line| {
+                let mut line = line.clone();
+                line.resize(width as u16);
+                line
+            })
+            .collect();
+
+        (lines, self.cursor)
+    }
+
+    /// Rewraps logical lines (rows joined by autowrap) to `width`. The cursor keeps its position
+    /// within its logical line.
+    fn reflow(&self, width: usize) -> (Vec<Line>, Cursor) {
+        // Split the grid into logical lines, remembering where the cursor sits in its own.
+        let mut logical_lines: Vec<Vec<Cell>> = Vec::new();
+        let mut cursor = (0, 0);
+        for (y, line) in self.lines.iter().enumerate() {
+            if !line.is_wrapped() || logical_lines.is_empty() {
+                logical_lines.push(Vec::new());
+            }
+            let index = logical_lines.len() - 1;
+            let cells = &mut logical_lines[index];
+            if y == self.cursor.y {
+                cursor = (index, cells.len() + self.cursor.x);
+            }
+
+            // Wrapped rows are full by construction, only the last row of a logical line is
+            // trimmed so that trailing blanks don't get carried over.
+            let continues = self.lines.get(y + 1).is_some_and(Line::is_wrapped);
+            let len = if continues {
+                self.width as u16
+            } else {
+                line.occupied_len()
+            };
+            cells.extend((0..len).map(|x| line.get(x)));
+        }
+
+        // Blank lines at the bottom have nothing to reflow, unless the cursor is on them.
+        while logical_lines.len() > cursor.0 + 1 && logical_lines.last().is_some_and(Vec::is_empty)
+        {
+            logical_lines.pop();
+        }
+
+        let mut lines = Vec::new();
+        let mut new_cursor = Cursor::default();
+        for (i, cells) in logical_lines.iter().enumerate() {
+            let (rows, position) = Self::rewrap(cells, width, (i == cursor.0).then_some(cursor.1));
+            if let Some(position) = position {
+                new_cursor = Cursor {
+                    x: position.x,
+                    y: lines.len() + position.y,
+                };
+            }
+            lines.extend(rows);
+        }
+
+        (lines, new_cursor)
+    }
+
+    /// Lays out the cells of one logical line into rows of `width`, returning the rows and the
+    /// position of the cell at `offset` in them.
+    fn rewrap(cells: &[Cell], width: usize, offset: Option<usize>) -> (Vec<Line>, Option<Cursor>) {
+        let mut rows = vec![Line::new(width as u16)];
+        let mut position = None;
+        let mut x = 0;
+        let mut i = 0;
+        while i < cells.len() {
+            let cell = cells[i];
+            let cell_width = cell.ch.width().unwrap_or(1).clamp(1, width);
+            if x + cell_width > width {
+                let mut row = Line::new(width as u16);
+                row.set_wrapped(true);
+                rows.push(row);
+                x = 0;
+            }
+            if offset.is_some_and(|offset| (i..i + cell_width).contains(&offset)) {
+                position = Some(Cursor {
+                    x: x + offset.unwrap() - i,
+                    y: rows.len() - 1,
+                });
+            }
+            // Also recreates the continuation trailing a wide character.
+            rows.last_mut().unwrap().put(x as u16, cell);
+            x += cell_width;
+            i += cell_width;
+        }
+
+        // The cursor may sit past the end of the content, keep it on the last row.
+        if let Some(offset) = offset.filter(|_| position.is_none()) {
+            position = Some(Cursor {
+                x: (x + offset - cells.len()).min(width - 1),
+                y: rows.len() - 1,
+            });
+        }
+
+        (rows, position)
+    }
+
+    /// Writes `s` starting at `(x, y)` and returns the position right after the last written
+    /// cell, so that sequential writes can be chained.
+    ///
+    /// Wide characters occupy two cells and are not split at the line end. The returned column
+    /// is clamped to the line width.
+    pub fn write_str(&mut self, x: usize, y: usize, s: &str, attrs: CellAttributes) -> Cursor {
+        if y >= self.height {
+            return Cursor { x, y };
+        }
+
+        let line = &mut self.lines[y];
+        let mut x = x.min(self.width);
+        for ch in s.chars() {
+            let width = ch.width().unwrap_or(0);
+            if width == 0 {
+                continue;
+            }
+            if x + width > self.width {
+                break;
+            }
+            line.put(x as u16, Cell::new(ch, attrs));
+            x += width;
+        }
+        self.damage.add(y..y + 1);
+
+        Cursor { x, y }
+    }
+}
+
+/// Writes the grid as plain text, one row per line with trailing blanks trimmed.
+impl fmt::Display for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (y, line) in self.lines.iter().enumerate() {
+            if y > 0 {
+                writeln!(f)?;
+            }
+            let mut x = 0;
+            while x < line.occupied_len() {
+                let cell = line.get(x);
+                write!(f, "{}{}", cell.ch, self.extra(cell, &mut [0; 4]))?;
+                // Skip the placeholder trailing a wide character.
+                x += cell.ch.width().unwrap_or(1).max(1) as u16;
+            }
+        }
+        Ok(())
+    }
+}
+
+const INLINE_CELLS: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct Line {
+    inline_cells: [(u16, Cell); INLINE_CELLS],
+    inline_count: u8,
+    overflow: Option<Vec<(u16, Cell)>>,
+    attributes: CellAttributes,
+    width: u16,
+    wrapped: bool,
+}
+
+impl Line {
+    pub fn new(width: u16) -> Self {
+        Self {
+            inline_cells: Default::default(),
+            inline_count: 0,
+            overflow: None,
+            attributes: CellAttributes::default(),
+            width,
+            wrapped: false,
+        }
+    }
+
+    pub fn get(&self, x: u16) -> Cell {
+        if x >= self.width {
+            return Cell::default();
+        }
+
+        for i in 0..self.inline_count as usize {
+            if self.inline_cells[i].0 == x {
+                return self.inline_cells[i].1;
+            }
+        }
+
+        if let Some(overflow) = &self.overflow {
+            if let Ok(i) = overflow.binary_search_by_key(&x, |(pos, _)| *pos) {
+                return overflow[i].1;
+            }
+        }
+
+        Cell::default()
+    }
+
+    pub fn set(&mut self, x: u16, cell: Cell) {
+        if x >= self.width {
+            return;
+        }
+
+        let cell_is_default = cell.is_default();
+
+        for i in 0..self.inline_count as usize {
+            if self.inline_cells[i].0 == x {
+                if cell_is_default {
+                    self.inline_cells
+                        .copy_within(i + 1..self.inline_count as usize, i);
+                    self.inline_count -= 1;
+                } else {
+                    self.inline_cells[i].1 = cell;
+                }
+                return;
+            }
+        }
+
+        if let Some(overflow) = &mut self.overflow {
+            if let Ok(i) = overflow.binary_search_by_key(&x, |(pos, _)| *pos) {
+                if cell_is_default {
+                    overflow.remove(i);
+                } else {
+                    overflow[i].1 = cell;
+                }
+                return;
+            }
+        }
+
+        if cell_is_default {
+            return;
+        }
+
+        if self.inline_count < INLINE_CELLS as u8 {
+            let i = self.find_insert_position(x);
+            self.inline_cells
+                .copy_within(i..self.inline_count as usize, i + 1);
+            self.inline_cells[i] = (x, cell);
+            self.inline_count += 1;
+            return;
+        }
+
+        let overflow = self.overflow.get_or_insert_with(Vec::new);
+        if let Err(i) = overflow.binary_search_by_key(&x, |(pos, _)| *pos) {
+            overflow.insert(i, (x, cell));
+        }
+    }
+
+    /// The cells that aren't blank, in no particular order.
+    fn cells(&self) -> impl Iterator<Item = Cell> + '_ {
+        let overflow = self.overflow.as_deref().unwrap_or_default();
+        self.inline_cells[..self.inline_count as usize]
+            .iter()
+            .chain(overflow)
+            .map(|(_, cell)| *cell)
+    }
+
+    /// Writes `cell` at `x`, followed by continuation cells if it holds a wide character. Wide
+    /// characters partly overwritten are cleared entirely.
+    pub fn put(&mut self, x: u16, cell: Cell) {
+        let width = cell.ch.width().unwrap_or(1).max(1) as u16;
+        self.split_wide(x, x + width);
+        self.set(x, cell);
+        for i in 1..width {
+            self.set(x + i, cell.continuation());
+        }
+    }
+
+    /// Shifts the cells from `x` on right by `n` blank cells. Cells pushed past the end of the
+    /// line are dropped.
+    pub fn insert_blank(&mut self, x: u16, n: u16) {
+        if x >= self.width || n == 0 {
+            return;
+        }
+        self.split_wide(x, x);
+        let n = n.min(self.width - x);
+        // Don't keep the lead of a wide character whose continuation is pushed off.
+        if self.get(self.width - n).is_wide_continuation() {
+            self.set(self.width - n - 1, Cell::default());
+        }
+        for i in (x + n..self.width).rev() {
+            self.set(i, self.get(i - n));
+        }
+        for i in x..x + n {
+            self.set(i, Cell::default());
+        }
+    }
+
+    /// Clears the halves of wide characters left over when the cells in `start..end` are
+    /// overwritten: the lead of a continuation at `start` and the continuation at `end`.
+    fn split_wide(&mut self, start: u16, end: u16) {
+        if start > 0 && self.get(start).is_wide_continuation() {
+            self.set(start - 1, Cell::default());
+        }
+        if self.get(end).is_wide_continuation() {
+            self.set(end, Cell::default());
+        }
+    }
+
+    /// Clears all cells and sets the width, keeping the memory allocated for the cells.
+    pub fn reset(&mut self, width: u16) {
+        self.inline_count = 0;
+        if let Some(overflow) = &mut self.overflow {
+            overflow.clear();
+        }
+        self.attributes = CellAttributes::default();
+        self.width = width;
+        self.wrapped = false;
+    }
+
+    /// Whether this line is a continuation of the previous one, i.e. it was started by autowrap
+    /// rather than by an explicit line break.
+    pub fn is_wrapped(&self) -> bool {
+        self.wrapped
+    }
+
+    pub fn set_wrapped(&mut self, wrapped: bool) {
+        self.wrapped = wrapped;
+    }
+
+    /// Changes the width of the line, dropping cells past the new width.
+    pub fn resize(&mut self, width: u16) {
+        // Don't keep the lead of a wide character whose continuation is cut off.
+        if width > 0 && self.get(width).is_wide_continuation() {
+            self.set(width - 1, Cell::default());
+        }
+
+        let mut count = 0;
+        for i in 0..self.inline_count as usize {
+            if self.inline_cells[i].0 < width {
+                self.inline_cells[count] = self.inline_cells[i];
+                count += 1;
+            }
+        }
+        self.inline_count = count as u8;
+        if let Some(overflow) = &mut self.overflow {
+            overflow.retain(|(x, _)| *x < width);
+        }
+        self.width = width;
+    }
+
+    /// Number of cells up to and including the last non-default one.
+    pub fn occupied_len(&self) -> u16 {
+        let inline = self.inline_cells[..self.inline_count as usize]
+            .iter()
+            .map(|(x, _)| x + 1)
+            .max();
+        let overflow = self
+            .overflow
+            .as_ref()
+            .and_then(|overflow| overflow.last())
+            .map(|(x, _)| x + 1);
+
+        inline.max(overflow).unwrap_or(0)
+    }
+
+    /// Approximate number of bytes used by the line, including cells spilled to the heap.
+    pub fn memory_estimate(&self) -> usize {
+        let overflow = self.overflow.as_ref().map_or(0, |overflow| {
+            overflow.capacity() * mem::size_of::<(u16, Cell)>()
+        });
+
+        mem::size_of::<Self>() + overflow
+    }
+
+    /// The stored cells in no particular order. A cell can live inline or in the overflow
+    /// depending on the order it was set in.
+    fn stored_cells(&self) -> impl Iterator<Item = &(u16, Cell)> {
+        self.inline_cells[..self.inline_count as usize]
+            .iter()
+            .chain(self.overflow.iter().flatten())
+    }
+
+    fn find_insert_position(&self, x: u16) -> usize {
+        for i in 0..self.inline_count as usize {
+            if self.inline_cells[i].0 > x {
+                return i;
+            }
+        }
+        self.inline_count as usize
+    }
+}
+
+/// Lines are equal if they have the same cells, regardless of whether those are stored inline or
+/// in the overflow.
+impl PartialEq for Line {
+    fn eq(&self, other: &Self) -> bool {
+        self.width == other.width
+            && self.wrapped == other.wrapped
+            && self.attributes == other.attributes
+            && self.stored_cells().count() == other.stored_cells().count()
+            && self.stored_cells().all(|&(x, cell)| other.get(x) == cell)
+    }
+}
+
+impl Eq for Line {}
+
+impl Hash for Line {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.width.hash(state);
+        self.wrapped.hash(state);
+        self.attributes.hash(state);
+        let mut cells: Vec<_> = self.stored_cells().collect();
+        cells.sort_unstable_by_key(|(x, _)| *x);
+        cells.hash(state);
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Cell {
+    /// First character of the grapheme cluster held by the cell.
+    pub ch: char,
+    pub attrs: CellAttributes,
+    /// Rest of the cluster, see [`Buffer::join`]: 0 if there is none, the character itself if it
+    /// is a single one from the Basic Multilingual Plane, otherwise the id of the cluster in
+    /// the buffer's side table.
+    extra: u16,
+    pub fg: Color,
+    pub bg: Color,
+}
+
+impl Cell {
+    pub fn new(ch: char, attrs: CellAttributes) -> Self {
+        Self {
+            ch,
+            attrs,
+            ..Default::default()
+        }
+    }
+    /// Whether more characters than [`Cell::ch`] make up the grapheme cluster in the cell.
+    pub fn is_cluster(self) -> bool {
+        self.extra != 0
+    }
+
+    pub fn is_default(self) -> bool {
+        self == Self::default()
+    }
+
+    /// Whether this is the trailing half of a wide character, whose lead is in the cell to its
+    /// left.
+    pub fn is_wide_continuation(self) -> bool {
+        self.attrs.contains(CellAttributes::WIDE_CONTINUATION)
+    }
+
+    /// Cell trailing `self` when it holds a wide character. It shares the attributes and colors
+    /// of the lead so that backgrounds and protection cover the whole character.
+    fn continuation(self) -> Self {
+        let mut attrs = self.attrs;
+        attrs.set(CellAttributes::WIDE_CONTINUATION, true);
+        Self {
+            ch: ' ',
+            attrs,
+            extra: 0,
+            ..self
+        }
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            ch: ' ',
+            attrs: CellAttributes::default(),
+            extra: 0,
+            fg: Color::DEFAULT,
+            bg: Color::DEFAULT,
+        }
+    }
+}
+
+/// Rendition flags of a cell as selected by SGR, plus the underline style packed into three bits.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CellAttributes(u16);
+
+impl CellAttributes {
+    pub const BOLD: Self = Self(1 << 0);
+    pub const FAINT: Self = Self(1 << 1);
+    pub const ITALIC: Self = Self(1 << 2);
+    pub const BLINK: Self = Self(1 << 3);
+    pub const INVERSE: Self = Self(1 << 4);
+    pub const HIDDEN: Self = Self(1 << 5);
+    pub const STRIKETHROUGH: Self = Self(1 << 6);
+    /// Set by DECSCA or SPA/EPA: the cell survives selective erases.
+    pub const PROTECTED: Self = Self(1 << 10);
+    /// Set on the cell trailing a wide character, see [`Cell::is_wide_continuation`].
+    pub const WIDE_CONTINUATION: Self = Self(1 << 11);
+
+    const UNDERLINE_SHIFT: u16 = 7;
+    const UNDERLINE_MASK: u16 = 0b111 << Self::UNDERLINE_SHIFT;
+
+    #[inline]
+    pub fn contains(&self, flags: Self) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+
+    #[inline]
+    pub fn set(&mut self, flags: Self, enabled: bool) {
+        if enabled {
+            self.0 |= flags.0;
+        } else {
+            self.0 &= !flags.0;
+        }
+    }
+
+    #[inline]
+    pub fn underline(&self) -> UnderlineStyle {
+        let style = (self.0 & Self::UNDERLINE_MASK) >> Self::UNDERLINE_SHIFT;
+        UnderlineStyle::try_from(style as u8).unwrap_or_default()
+    }
+
+    #[inline]
+    pub fn set_underline(&mut self, style: UnderlineStyle) {
+        let style = u8::from(style) as u16;
+        self.0 = (self.0 & !Self::UNDERLINE_MASK) | (style << Self::UNDERLINE_SHIFT);
+    }
+}
+
+impl BitOr for CellAttributes {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Color(u32);
+
+impl Color {
+    const R_SHIFT: u32 = 16;
+    const G_SHIFT: u32 = 8;
+    const B_SHIFT: u32 = 0;
+
+    const R_MASK: u32 = 0xFF << Self::R_SHIFT;
+    const G_MASK: u32 = 0xFF << Self::G_SHIFT;
+    const B_MASK: u32 = 0xFF << Self::B_SHIFT;
+
+    const INDEX_FLAG: u32 = 1 << 31;
+    const DEFAULT_FLAG: u32 = 1 << 30;
+
+    /// The terminal's default foreground or background color, depending on where it's used.
+    pub const DEFAULT: Self = Self(Self::DEFAULT_FLAG);
+
+    #[inline]
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self(
+            ((r as u32) << Self::R_SHIFT)
+                | ((g as u32) << Self::G_SHIFT)
+                | ((b as u32) << Self::B_SHIFT),
+        )
+    }
+
+    #[inline]
+    pub const fn indexed(index: u8) -> Self {
+        Self(Self::INDEX_FLAG | (index as u32))
+    }
+
+    #[inline]
+    pub fn r(&self) -> u8 {
+        ((**self & Self::R_MASK) >> Self::R_SHIFT) as u8
+    }
+
+    #[inline]
+    pub fn g(&self) -> u8 {
+        ((**self & Self::G_MASK) >> Self::G_SHIFT) as u8
+    }
+
+    #[inline]
+    pub fn b(&self) -> u8 {
+        ((**self & Self::B_MASK) >> Self::B_SHIFT) as u8
+    }
+
+    #[inline]
+    pub fn is_default(&self) -> bool {
+        **self & Self::DEFAULT_FLAG != 0
+    }
+
+    #[inline]
+    pub fn is_indexed(&self) -> bool {
+        **self & Self::INDEX_FLAG != 0
+    }
+
+    #[inline]
+    pub fn index(&self) -> Option<u8> {
+        if self.is_indexed() {
+            Some(**self as u8)
+        } else {
+            None
+        }
+    }
+
+    /// RGB value of `index` in the default 256 color palette: the xterm system colors, a 6x6x6
+    /// color cube and a 24 step grayscale ramp.
+    pub fn ansi_256_rgb(index: u8) -> (u8, u8, u8) {
+        let level = |i: u8| if i == 0 { 0 } else { 55 + i * 40 };
+        match index {
+            0..=15 => SYSTEM_COLORS[index as usize],
+            16..=231 => {
+                let i = index - 16;
+                (level(i / 36), level(i / 6 % 6), level(i % 6))
+            }
+            232..=255 => {
+                let gray = 8 + (index - 232) * 10;
+                (gray, gray, gray)
+            }
+        }
+    }
+
+    /// Nearest entry of the color cube and grayscale ramp (16-255), for output limited to 256
+    /// colors. The system colors are skipped as they are commonly changed by themes. Indexed
+    /// colors are returned as they are.
+    ///
+    /// The default color has no RGB value and maps to black, like in [`Color::to_ansi_16`].
+    pub fn to_indexed_256(self) -> u8 {
+        match self.index() {
+            Some(index) => index,
+            None => self.nearest(16..=255),
+        }
+    }
+
+    /// Nearest of the 16 system colors, for output limited to 16 colors.
+    pub fn to_ansi_16(self) -> u8 {
+        match self.index() {
+            Some(index) if index < 16 => index,
+            _ => self.nearest(0..=15),
+        }
+    }
+
+    /// Index in `indices` whose default RGB value is closest to this color, using the "redmean"
+    /// approximation of perceived distance.
+    fn nearest(self, indices: std::ops::RangeInclusive<u8>) -> u8 {
+        let (r, g, b) = match self.index() {
+            Some(index) => Self::ansi_256_rgb(index),
+            None if self.is_default() => (0, 0, 0),
+            None => (self.r(), self.g(), self.b()),
+        };
+        let distance = |index: &u8| {
+            let (r2, g2, b2) = Self::ansi_256_rgb(*index);
+            let mean = (r as i32 + r2 as i32) / 2;
+            let (dr, dg, db) = (
+                r as i32 - r2 as i32,
+                g as i32 - g2 as i32,
+                b as i32 - b2 as i32,
+            );
+            (512 + mean) * dr * dr + 1024 * dg * dg + (767 - mean) * db * db
+        };
+        indices.min_by_key(distance).unwrap_or(0)
+    }
+}
+
+/// The 16 system colors, as used by xterm.
+const SYSTEM_COLORS: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0xcd, 0x00, 0x00),
+    (0x00, 0xcd, 0x00),
+    (0xcd, 0xcd, 0x00),
+    (0x00, 0x00, 0xee),
+    (0xcd, 0x00, 0xcd),
+    (0x00, 0xcd, 0xcd),
+    (0xe5, 0xe5, 0xe5),
+    (0x7f, 0x7f, 0x7f),
+    (0xff, 0x00, 0x00),
+    (0x00, 0xff, 0x00),
+    (0xff, 0xff, 0x00),
+    (0x5c, 0x5c, 0xff),
+    (0xff, 0x00, 0xff),
+    (0x00, 0xff, 0xff),
+    (0xff, 0xff, 0xff),
+];
+
+impl From<ansi::ansi::Color> for Color {
+    fn from(color: ansi::ansi::Color) -> Self {
+        match color {
+            ansi::ansi::Color::Default => Self::DEFAULT,
+            ansi::ansi::Color::Indexed(index) => Self::indexed(index),
+            ansi::ansi::Color::RGB(r, g, b) => Self::rgb(r, g, b),
+        }
+    }
+}
+
+impl From<Color> for ansi::ansi::Color {
+    fn from(color: Color) -> Self {
+        if color.is_default() {
+            Self::Default
+        } else if let Some(index) = color.index() {
+            Self::Indexed(index)
+        } else {
+            Self::RGB(color.r(), color.g(), color.b())
+        }
+    }
+}
+
+impl Deref for Color {
+    type Target = u32;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ansi::ansi::{AnsiCommand, AnsiParser};
+
+    fn print_str(buffer: &mut Buffer, s: &str) {
+        for ch in s.chars() {
+            buffer.print(ch, CellAttributes::default());
+        }
+    }
+
+    fn row_text(buffer: &Buffer, y: usize) -> String {
+        let line = buffer.line(y).unwrap();
+        (0..line.occupied_len()).map(|x| line.get(x).ch).collect()
+    }
+
+    fn apply(buffer: &mut Buffer, data: &[u8]) {
+        AnsiParser::new().parse(data, |cmd| {
+            if let AnsiCommand::CursorPosition(row, col) = cmd {
+                buffer.cursor_position(row, col);
+            }
+        });
+    }
+
+    #[test]
+    fn write_single_char_to_cell() {
+        let mut buffer = Buffer::new(10, 10);
+        buffer.write_str(0, 0, "C", CellAttributes::default());
+        let char = buffer.cell(0, 0).unwrap().ch;
+
+        assert_eq!(char, 'C');
+    }
+
+    #[test]
+    fn write_string_to_cells() {
+        let mut buffer = Buffer::new(10, 10);
+        buffer.write_str(0, 0, "CCC", CellAttributes::default());
+        let char1 = buffer.cell(0, 0).unwrap().ch;
+        let char2 = buffer.cell(1, 0).unwrap().ch;
+        let char3 = buffer.cell(2, 0).unwrap().ch;
+
+        assert_eq!(char1, 'C');
+        assert_eq!(char2, 'C');
+        assert_eq!(char3, 'C');
+    }
+
+    #[test]
+    fn cursor_position_zero_is_treated_as_one() {
+        let mut buffer = Buffer::new(10, 5);
+        buffer.cursor_position(3, 3);
+        apply(&mut buffer, b"\x1B[0;0H");
+
+        assert_eq!(buffer.cursor(), Cursor { x: 0, y: 0 });
+    }
+
+    #[test]
+    fn cursor_position_clamps_to_edge() {
+        let mut buffer = Buffer::new(10, 5);
+        apply(&mut buffer, b"\x1B[999;999H");
+
+        assert_eq!(buffer.cursor(), Cursor { x: 9, y: 4 });
+    }
+
+    #[test]
+    fn cursor_position_default_homes() {
+        let mut buffer = Buffer::new(10, 5);
+        buffer.cursor_position(3, 3);
+        apply(&mut buffer, b"\x1B[H");
+
+        assert_eq!(buffer.cursor(), Cursor { x: 0, y: 0 });
+    }
+
+    #[test]
+    fn horizontal_vertical_position_matches_cursor_position() {
+        let mut buffer = Buffer::new(10, 5);
+        apply(&mut buffer, b"\x1B[2;4f");
+
+        assert_eq!(buffer.cursor(), Cursor { x: 3, y: 1 });
+    }
+
+    #[test]
+    fn write_str_returns_next_position() {
+        let mut buffer = Buffer::new(10, 10);
+        let cursor = buffer.write_str(2, 1, "abc", CellAttributes::default());
+
+        assert_eq!(cursor, Cursor { x: 5, y: 1 });
+    }
+
+    #[test]
+    fn write_str_chains() {
+        let mut buffer = Buffer::new(10, 10);
+        let cursor = buffer.write_str(0, 0, "ab", CellAttributes::default());
+        let cursor = buffer.write_str(cursor.x, cursor.y, "cd", CellAttributes::default());
+
+        assert_eq!(cursor, Cursor { x: 4, y: 0 });
+        assert_eq!(buffer.cell(2, 0).unwrap().ch, 'c');
+        assert_eq!(buffer.cell(3, 0).unwrap().ch, 'd');
+    }
+
+    #[test]
+    fn write_str_chains_across_wide_char() {
+        let mut buffer = Buffer::new(10, 10);
+        let cursor = buffer.write_str(0, 0, "a漢", CellAttributes::default());
+        let cursor = buffer.write_str(cursor.x, cursor.y, "b", CellAttributes::default());
+
+        assert_eq!(cursor, Cursor { x: 4, y: 0 });
+        assert_eq!(buffer.cell(1, 0).unwrap().ch, '漢');
+        assert_eq!(buffer.cell(3, 0).unwrap().ch, 'b');
+    }
+
+    #[test]
+    fn write_str_clamps_at_line_end() {
+        let mut buffer = Buffer::new(4, 1);
+        let cursor = buffer.write_str(0, 0, "abc漢", CellAttributes::default());
+
+        assert_eq!(cursor, Cursor { x: 3, y: 0 });
+
+        let cursor = buffer.write_str(cursor.x, cursor.y, "de", CellAttributes::default());
+
+        assert_eq!(cursor, Cursor { x: 4, y: 0 });
+    }
+
+    #[test]
+    fn cell_in_bounds() {
+        let mut buffer = Buffer::new(10, 5);
+        buffer.set_cell(9, 4, Cell::new('x', CellAttributes::default()));
+
+        assert_eq!(buffer.cell(9, 4).map(|cell| cell.ch), Some('x'));
+        assert_eq!(buffer.cell(0, 0), Some(Cell::default()));
+    }
+
+    #[test]
+    fn cell_out_of_bounds() {
+        let mut buffer = Buffer::new(10, 5);
+        buffer.set_cell(10, 0, Cell::new('x', CellAttributes::default()));
+        buffer.set_cell(0, 5, Cell::new('x', CellAttributes::default()));
+
+        assert_eq!(buffer.cell(10, 0), None);
+        assert_eq!(buffer.cell(0, 5), None);
+    }
+
+    #[test]
+    fn print_defers_wrap_at_last_column() {
+        let mut buffer = Buffer::new(5, 3);
+        print_str(&mut buffer, "abcde");
+
+        assert_eq!(buffer.cursor(), Cursor { x: 4, y: 0 });
+        assert!(!buffer.line(1).unwrap().is_wrapped());
+    }
+
+    #[test]
+    fn print_marks_wrapped_lines() {
+        let mut buffer = Buffer::new(5, 3);
+        print_str(&mut buffer, "abcdefgh");
+
+        assert_eq!(row_text(&buffer, 0), "abcde");
+        assert_eq!(row_text(&buffer, 1), "fgh");
+        assert!(!buffer.line(0).unwrap().is_wrapped());
+        assert!(buffer.line(1).unwrap().is_wrapped());
+        assert_eq!(buffer.cursor(), Cursor { x: 3, y: 1 });
+    }
+
+    #[test]
+    fn logical_line_bounds() {
+        let mut buffer = Buffer::new(3, 5);
+        print_str(&mut buffer, "ab");
+        buffer.linefeed();
+        buffer.carriage_return();
+        print_str(&mut buffer, "cdefghi");
+
+        assert_eq!(buffer.logical_line_bounds(0), (0, 0));
+        for row in 1..4 {
+            assert_eq!(buffer.logical_line_bounds(row), (1, 3));
+        }
+        assert_eq!(buffer.logical_line_bounds(4), (4, 4));
+        assert_eq!(buffer.logical_line_bounds(9), (4, 4));
+    }
+
+    #[test]
+    fn resize_narrower_reflows_wrapped_line() {
+        let mut buffer = Buffer::new(5, 4);
+        print_str(&mut buffer, "abcdefgh");
+        buffer.resize(3, 4, ResizeMode::Reflow);
+
+        assert_eq!(row_text(&buffer, 0), "abc");
+        assert_eq!(row_text(&buffer, 1), "def");
+        assert_eq!(row_text(&buffer, 2), "gh");
+        assert!(!buffer.line(0).unwrap().is_wrapped());
+        assert!(buffer.line(1).unwrap().is_wrapped());
+        assert!(buffer.line(2).unwrap().is_wrapped());
+        assert_eq!(buffer.cursor(), Cursor { x: 2, y: 2 });
+    }
+
+    #[test]
+    fn resize_wider_rejoins_wrapped_line() {
+        let mut buffer = Buffer::new(5, 3);
+        print_str(&mut buffer, "abcdefgh");
+        buffer.resize(10, 3, ResizeMode::Reflow);
+
+        assert_eq!(row_text(&buffer, 0), "abcdefgh");
+        assert_eq!(row_text(&buffer, 1), "");
+        assert!(!buffer.line(1).unwrap().is_wrapped());
+        assert_eq!(buffer.cursor(), Cursor { x: 8, y: 0 });
+    }
+
+    #[test]
+    fn resize_keeps_explicit_line_breaks() {
+        let mut buffer = Buffer::new(4, 4);
+        print_str(&mut buffer, "ab");
+        buffer.carriage_return();
+        buffer.linefeed();
+        print_str(&mut buffer, "cdef");
+        buffer.resize(8, 4, ResizeMode::Reflow);
+
+        assert_eq!(row_text(&buffer, 0), "ab");
+        assert_eq!(row_text(&buffer, 1), "cdef");
+        assert!(!buffer.line(1).unwrap().is_wrapped());
+    }
+
+    #[test]
+    fn resize_drops_rows_above_cursor() {
+        let mut buffer = Buffer::new(4, 2);
+        print_str(&mut buffer, "abcdefg");
+        buffer.resize(2, 2, ResizeMode::Reflow);
+
+        assert_eq!(row_text(&buffer, 0), "ef");
+        assert_eq!(row_text(&buffer, 1), "g");
+        assert_eq!(buffer.cursor(), Cursor { x: 1, y: 1 });
+    }
+
+    #[test]
+    fn resize_keeps_content_below_cursor() {
+        let mut buffer = Buffer::new(4, 4);
+        print_str(&mut buffer, "top");
+        buffer.cursor_position(3, 1);
+        print_str(&mut buffer, "abcdef");
+        buffer.cursor_position(1, 4);
+        buffer.resize(8, 4, ResizeMode::Reflow);
+
+        assert_eq!(buffer.to_string(), "top\n\nabcdef\n");
+        assert_eq!(buffer.cursor(), Cursor { x: 3, y: 0 });
+    }
+
+    #[test]
+    fn resize_moves_rows_above_cursor_to_scrollback() {
+        let mut buffer = Buffer::new(4, 4);
+        buffer.set_scrollback_limit(2);
+        print_str(&mut buffer, "x");
+        for ch in ["a", "b", "c"] {
+            buffer.linefeed();
+            buffer.carriage_return();
+            print_str(&mut buffer, ch);
+        }
+        buffer.resize(4, 1, ResizeMode::Clip);
+
+        assert_eq!(buffer.to_string(), "c");
+        assert_eq!(buffer.scrollback_len(), 2);
+        assert_eq!(buffer.scrollback_line(0).unwrap().get(0).ch, 'a');
+        assert_eq!(buffer.scrollback_line(1).unwrap().get(0).ch, 'b');
+    }
+
+    #[test]
+    fn line_set_looks_past_first_inline_cell() {
+        let mut line = Line::new(10);
+        line.set(3, Cell::new('a', CellAttributes::default()));
+        line.set(1, Cell::new('b', CellAttributes::default()));
+        line.set(5, Cell::new('c', CellAttributes::default()));
+        line.set(5, Cell::new('d', CellAttributes::default()));
+        line.set(1, Cell::default());
+
+        assert_eq!(line.get(1), Cell::default());
+        assert_eq!(line.get(3).ch, 'a');
+        assert_eq!(line.get(5).ch, 'd');
+        assert_eq!(line.occupied_len(), 6);
+    }
+
+    #[test]
+    fn line_set_overwrites_overflow_cell() {
+        let mut line = Line::new(20);
+        for x in 0..=INLINE_CELLS as u16 {
+            line.set(x, Cell::new('a', CellAttributes::default()));
+        }
+        line.set(0, Cell::default());
+        line.set(
+            INLINE_CELLS as u16,
+            Cell::new('b', CellAttributes::default()),
+        );
+        line.set(INLINE_CELLS as u16, Cell::default());
+
+        assert_eq!(line.get(INLINE_CELLS as u16), Cell::default());
+        assert_eq!(line.occupied_len(), INLINE_CELLS as u16);
+    }
+
+    #[test]
+    fn write_tab_from_line_start() {
+        let mut buffer = Buffer::new(20, 1);
+        buffer.write_tab(CellAttributes::default());
+
+        assert_eq!(buffer.cursor(), Cursor { x: 8, y: 0 });
+    }
+
+    #[test]
+    fn write_tab_fills_skipped_cells() {
+        let mut buffer = Buffer::new(20, 1);
+        print_str(&mut buffer, "abc");
+        buffer.write_tab(CellAttributes::default());
+        print_str(&mut buffer, "d");
+
+        assert_eq!(row_text(&buffer, 0), "abc     d");
+        assert_eq!(buffer.cursor(), Cursor { x: 9, y: 0 });
+    }
+
+    #[test]
+    fn write_tab_on_stop_moves_to_next_stop() {
+        let mut buffer = Buffer::new(20, 1);
+        buffer.cursor_position(1, 9);
+        buffer.write_tab(CellAttributes::default());
+
+        assert_eq!(buffer.cursor(), Cursor { x: 16, y: 0 });
+    }
+
+    #[test]
+    fn write_tab_stops_at_last_column() {
+        let mut buffer = Buffer::new(20, 1);
+        buffer.cursor_position(1, 17);
+        buffer.write_tab(CellAttributes::default());
+
+        assert_eq!(buffer.cursor(), Cursor { x: 19, y: 0 });
+    }
+
+    #[test]
+    fn backspace_at_first_column() {
+        let mut buffer = Buffer::new(10, 2);
+        buffer.backspace();
+
+        assert_eq!(buffer.cursor(), Cursor { x: 0, y: 0 });
+    }
+
+    #[test]
+    fn backspace_mid_line_keeps_content() {
+        let mut buffer = Buffer::new(10, 2);
+        print_str(&mut buffer, "abc");
+        buffer.backspace();
+
+        assert_eq!(buffer.cursor(), Cursor { x: 2, y: 0 });
+        assert_eq!(row_text(&buffer, 0), "abc");
+
+        print_str(&mut buffer, "d");
+
+        assert_eq!(row_text(&buffer, 0), "abd");
+    }
+
+    #[test]
+    fn backspace_clears_pending_wrap() {
+        let mut buffer = Buffer::new(3, 2);
+        print_str(&mut buffer, "abc");
+        buffer.backspace();
+        print_str(&mut buffer, "d");
+
+        assert_eq!(row_text(&buffer, 0), "adc");
+        assert_eq!(buffer.cursor(), Cursor { x: 2, y: 0 });
+    }
+
+    #[test]
+    fn cursor_movement_clamps() {
+        let mut buffer = Buffer::new(5, 5);
+        buffer.cursor_down(2);
+        buffer.cursor_forward(0);
+
+        assert_eq!(buffer.cursor(), Cursor { x: 1, y: 2 });
+
+        buffer.cursor_up(10);
+        buffer.cursor_forward(10);
+
+        assert_eq!(buffer.cursor(), Cursor { x: 4, y: 0 });
+
+        buffer.cursor_down(10);
+        buffer.cursor_backward(10);
+
+        assert_eq!(buffer.cursor(), Cursor { x: 0, y: 4 });
+    }
+
+    #[test]
+    fn display_trims_rows() {
+        let mut buffer = Buffer::new(6, 3);
+        print_str(&mut buffer, "a漢b");
+        buffer.carriage_return();
+        buffer.linefeed();
+        buffer.linefeed();
+        print_str(&mut buffer, "c");
+
+        assert_eq!(buffer.to_string(), "a漢b\n\nc");
+    }
+
+    #[test]
+    fn pending_wrap_until_next_print() {
+        let mut buffer = Buffer::new(3, 2);
+        print_str(&mut buffer, "ab");
+        assert!(!buffer.pending_wrap());
+
+        print_str(&mut buffer, "c");
+        assert!(buffer.pending_wrap());
+        assert_eq!(buffer.cursor(), Cursor { x: 2, y: 0 });
+
+        print_str(&mut buffer, "d");
+        assert!(!buffer.pending_wrap());
+        assert_eq!(buffer.cursor(), Cursor { x: 1, y: 1 });
+
+        print_str(&mut buffer, "ef");
+        assert!(buffer.pending_wrap());
+        buffer.carriage_return();
+        assert!(!buffer.pending_wrap());
+    }
+
+    #[test]
+    fn print_without_autowrap_overwrites_last_column() {
+        let mut buffer = Buffer::new(3, 2);
+        buffer.set_autowrap(false);
+        print_str(&mut buffer, "abcd");
+
+        assert_eq!(row_text(&buffer, 0), "abd");
+        assert_eq!(row_text(&buffer, 1), "");
+        assert_eq!(buffer.cursor(), Cursor { x: 2, y: 0 });
+        assert!(!buffer.pending_wrap());
+    }
+
+    #[test]
+    fn insert_mode_shifts_line() {
+        let mut buffer = Buffer::new(5, 1);
+        print_str(&mut buffer, "abcd");
+        buffer.cursor_position(1, 2);
+        buffer.set_insert(true);
+        print_str(&mut buffer, "xy");
+
+        assert_eq!(row_text(&buffer, 0), "axybc");
+        assert_eq!(buffer.cursor(), Cursor { x: 3, y: 0 });
+    }
+
+    #[test]
+    fn insert_blank_drops_cut_wide_char() {
+        let mut line = Line::new(4);
+        line.put(0, Cell::new('a', CellAttributes::default()));
+        line.put(2, Cell::new('漢', CellAttributes::default()));
+        line.insert_blank(1, 1);
+
+        assert_eq!(line.get(1), Cell::default());
+        assert_eq!(line.get(2).ch, ' ');
+        assert_eq!(line.get(3), Cell::default());
+    }
+
+    #[test]
+    fn resize_clip_truncates_rows() {
+        let mut buffer = Buffer::new(5, 3);
+        print_str(&mut buffer, "abcdefgh");
+        buffer.resize(3, 3, ResizeMode::Clip);
+
+        assert_eq!(row_text(&buffer, 0), "abc");
+        assert_eq!(row_text(&buffer, 1), "fgh");
+        assert!(buffer.line(1).unwrap().is_wrapped());
+        assert_eq!(buffer.cursor(), Cursor { x: 2, y: 1 });
+    }
+
+    #[test]
+    fn resize_clip_pads_rows() {
+        let mut buffer = Buffer::new(5, 3);
+        print_str(&mut buffer, "abcdefgh");
+        buffer.resize(10, 4, ResizeMode::Clip);
+
+        assert_eq!(row_text(&buffer, 0), "abcde");
+        assert_eq!(row_text(&buffer, 1), "fgh");
+        assert_eq!(row_text(&buffer, 3), "");
+        assert_eq!(buffer.cursor(), Cursor { x: 3, y: 1 });
+    }
+
+    #[test]
+    fn resize_modes_differ_on_wrapped_content() {
+        let mut clipped = Buffer::new(5, 3);
+        print_str(&mut clipped, "abcdefgh");
+        let mut reflowed = Buffer::new(5, 3);
+        print_str(&mut reflowed, "abcdefgh");
+
+        clipped.resize(10, 3, ResizeMode::Clip);
+        reflowed.resize(10, 3, ResizeMode::Reflow);
+
+        assert_eq!(clipped.to_string(), "abcde\nfgh\n");
+        assert_eq!(reflowed.to_string(), "abcdefgh\n\n");
+    }
+
+    #[test]
+    fn scrollback_keeps_scrolled_rows() {
+        let mut buffer = Buffer::new(4, 2);
+        print_str(&mut buffer, "a");
+        buffer.linefeed();
+        buffer.carriage_return();
+        print_str(&mut buffer, "b");
+        buffer.linefeed();
+        buffer.carriage_return();
+        print_str(&mut buffer, "c");
+
+        assert_eq!(buffer.scrollback_len(), 1);
+        assert_eq!(buffer.scrollback_line(0).unwrap().get(0).ch, 'a');
+        assert_eq!(buffer.to_string(), "b\nc");
+    }
+
+    #[test]
+    fn scrollback_limit_enforced() {
+        let mut buffer = Buffer::new(4, 2);
+        buffer.set_scrollback_limit(3);
+        for ch in "abcdefgh".chars() {
+            buffer.print(ch, CellAttributes::default());
+            buffer.linefeed();
+            buffer.carriage_return();
+        }
+
+        assert_eq!(buffer.scrollback_len(), 3);
+        let oldest: String = (0..3)
+            .map(|i| buffer.scrollback_line(i).unwrap().get(0).ch)
+            .collect();
+        assert_eq!(oldest, "efg");
+
+        buffer.set_scrollback_limit(1);
+
+        assert_eq!(buffer.scrollback_len(), 1);
+        assert_eq!(buffer.scrollback_line(0).unwrap().get(0).ch, 'g');
+
+        buffer.set_scrollback_limit(0);
+        buffer.linefeed();
+
+        assert_eq!(buffer.scrollback_len(), 0);
+    }
+
+    #[test]
+    fn memory_estimate_tracks_content() {
+        let mut buffer = Buffer::new(40, 2);
+        let empty = buffer.memory_estimate();
+
+        for _ in 0..10 {
+            print_str(&mut buffer, &"x".repeat(20));
+            buffer.linefeed();
+            buffer.carriage_return();
+        }
+        let full = buffer.memory_estimate();
+
+        assert!(full > empty);
+
+        buffer.set_scrollback_limit(2);
+
+        assert!(buffer.memory_estimate() < full);
+    }
+
+    #[test]
+    fn cell_attributes_flags_and_underline() {
+        let mut attrs = CellAttributes::default();
+        attrs.set(CellAttributes::BOLD | CellAttributes::ITALIC, true);
+        attrs.set_underline(UnderlineStyle::Curly);
+
+        assert!(attrs.contains(CellAttributes::BOLD));
+        assert!(attrs.contains(CellAttributes::ITALIC));
+        assert!(!attrs.contains(CellAttributes::FAINT));
+        assert_eq!(attrs.underline(), UnderlineStyle::Curly);
+
+        attrs.set(CellAttributes::BOLD, false);
+        attrs.set_underline(UnderlineStyle::Dashed);
+
+        assert!(!attrs.contains(CellAttributes::BOLD));
+        assert!(attrs.contains(CellAttributes::ITALIC));
+        assert_eq!(attrs.underline(), UnderlineStyle::Dashed);
+        assert_eq!(mem::size_of::<Cell>(), 16);
+    }
+
+    fn visible_text(buffer: &Buffer, offset: usize) -> Vec<char> {
+        buffer
+            .visible_rows(offset)
+            .map(|line| line.get(0).ch)
+            .collect()
+    }
+
+    #[test]
+    fn visible_rows() {
+        let mut buffer = Buffer::new(4, 3);
+        for ch in "abcdefg".chars() {
+            buffer.print(ch, CellAttributes::default());
+            buffer.linefeed();
+            buffer.carriage_return();
+        }
+
+        assert_eq!(buffer.scrollback_len(), 5);
+        assert_eq!(visible_text(&buffer, 0), ['f', 'g', ' ']);
+        assert_eq!(visible_text(&buffer, 2), ['d', 'e', 'f']);
+        assert_eq!(visible_text(&buffer, 5), ['a', 'b', 'c']);
+        assert_eq!(visible_text(&buffer, 100), ['a', 'b', 'c']);
+    }
+
+    #[test]
+    fn erase_modes() {
+        let filled = || {
+            let mut buffer = Buffer::new(4, 3);
+            for y in 0..3 {
+                buffer.write_str(0, y, "abcd", CellAttributes::default());
+            }
+            buffer.cursor_position(2, 3);
+            buffer
+        };
+
+        let mut buffer = filled();
+        buffer.erase_in_line(0, false, Color::DEFAULT);
+        assert_eq!(buffer.to_string(), "abcd\nab\nabcd");
+        let mut buffer = filled();
+        buffer.erase_in_line(1, false, Color::DEFAULT);
+        assert_eq!(buffer.to_string(), "abcd\n   d\nabcd");
+
+        let mut buffer = filled();
+        buffer.erase_in_display(0, false, Color::DEFAULT);
+        assert_eq!(buffer.to_string(), "abcd\nab\n");
+        let mut buffer = filled();
+        buffer.erase_in_display(1, false, Color::DEFAULT);
+        assert_eq!(buffer.to_string(), "\n   d\nabcd");
+        let mut buffer = filled();
+        buffer.erase_in_display(2, false, Color::DEFAULT);
+        assert_eq!(buffer.to_string(), "\n\n");
+    }
+
+    #[test]
+    fn erase_fills_background() {
+        let blue = Color::indexed(4);
+        let mut buffer = Buffer::new(4, 3);
+        print_str(&mut buffer, "abcd");
+        buffer.cursor_position(1, 3);
+
+        buffer.erase_in_line(0, false, blue);
+        assert_eq!(buffer.cell(1, 0).unwrap().bg, Color::DEFAULT);
+        for x in 2..4 {
+            assert_eq!(
+                buffer.cell(x, 0).unwrap(),
+                Cell {
+                    bg: blue,
+                    ..Cell::default()
+                }
+            );
+        }
+
+        buffer.cursor_position(2, 1);
+        buffer.erase_in_display(0, false, blue);
+        for (x, y) in [(0, 1), (3, 1), (0, 2), (3, 2)] {
+            assert_eq!(buffer.cell(x, y).unwrap().bg, blue);
+        }
+        assert_eq!(buffer.cell(0, 0).unwrap().ch, 'a');
+    }
+
+    #[test]
+    fn ansi_256_rgb() {
+        assert_eq!(Color::ansi_256_rgb(1), (0xcd, 0x00, 0x00));
+        assert_eq!(Color::ansi_256_rgb(15), (0xff, 0xff, 0xff));
+        assert_eq!(Color::ansi_256_rgb(16), (0, 0, 0));
+        assert_eq!(Color::ansi_256_rgb(196), (255, 0, 0));
+        assert_eq!(Color::ansi_256_rgb(110), (135, 175, 215));
+        assert_eq!(Color::ansi_256_rgb(231), (255, 255, 255));
+        assert_eq!(Color::ansi_256_rgb(232), (8, 8, 8));
+        assert_eq!(Color::ansi_256_rgb(255), (238, 238, 238));
+    }
+
+    #[test]
+    fn downsample_colors() {
+        let red = Color::rgb(255, 0, 0);
+        let green = Color::rgb(0, 255, 0);
+        let blue = Color::rgb(0, 0, 255);
+
+        assert_eq!(red.to_indexed_256(), 196);
+        assert_eq!(green.to_indexed_256(), 46);
+        assert_eq!(blue.to_indexed_256(), 21);
+        assert_eq!(Color::rgb(0x80, 0x80, 0x80).to_indexed_256(), 244);
+        assert_eq!(Color::indexed(3).to_indexed_256(), 3);
+
+        assert_eq!(red.to_ansi_16(), 9);
+        assert_eq!(green.to_ansi_16(), 10);
+        assert_eq!(blue.to_ansi_16(), 4);
+        assert_eq!(Color::rgb(0xc0, 0x10, 0x10).to_ansi_16(), 1);
+        assert_eq!(Color::indexed(196).to_ansi_16(), 9);
+        assert_eq!(Color::DEFAULT.to_ansi_16(), 0);
+    }
+
+    #[test]
+    fn lines_compare_by_content() {
+        use std::hash::{BuildHasher, RandomState};
+
+        let mut forward = Line::new(12);
+        let mut backward = Line::new(12);
+        for x in 0..10 {
+            forward.set(x, Cell::new('x', CellAttributes::BOLD));
+            backward.set(9 - x, Cell::new('x', CellAttributes::BOLD));
+        }
+        // Leaves a gap in the inline cells that the next insert fills.
+        backward.set(5, Cell::default());
+        backward.set(5, Cell::new('x', CellAttributes::BOLD));
+
+        assert_eq!(forward, backward);
+        let hasher = RandomState::new();
+        assert_eq!(hasher.hash_one(&forward), hasher.hash_one(&backward));
+
+        backward.set(11, Cell::new('y', CellAttributes::default()));
+        assert_ne!(forward, backward);
+    }
+
+    #[test]
+    fn cleared_buffer_equals_new() {
+        let mut buffer = Buffer::new(4, 2);
+        print_str(&mut buffer, "abc");
+        buffer.linefeed();
+        print_str(&mut buffer, "d");
+        assert_ne!(buffer, Buffer::new(4, 2));
+
+        buffer.erase_in_display(2, false, Color::DEFAULT);
+        buffer.cursor_position(1, 1);
+        assert_eq!(buffer, Buffer::new(4, 2));
+    }
+
+    #[test]
+    fn linefeed_scrolls_region() {
+        let mut buffer = Buffer::new(2, 4);
+        for (y, ch) in "abcd".chars().enumerate() {
+            buffer.write_str(0, y, &ch.to_string(), CellAttributes::default());
+        }
+        buffer.set_scroll_region(2, 3);
+        assert_eq!(buffer.scroll_region(), 1..3);
+        assert_eq!(buffer.cursor(), Cursor::default());
+
+        buffer.cursor_position(3, 1);
+        buffer.linefeed();
+
+        assert_eq!(buffer.to_string(), "a\nc\n\nd");
+        assert_eq!(buffer.cursor(), Cursor { x: 0, y: 2 });
+        assert_eq!(buffer.scrollback_len(), 0);
+
+        buffer.set_scroll_region(3, 3);
+        assert_eq!(buffer.scroll_region(), 1..3);
+    }
+
+    #[test]
+    fn overwriting_wide_char_clears_both_halves() {
+        let mut buffer = Buffer::new(4, 1);
+        print_str(&mut buffer, "漢");
+        assert!(!buffer.cell(0, 0).unwrap().is_wide_continuation());
+        assert!(buffer.cell(1, 0).unwrap().is_wide_continuation());
+
+        buffer.cursor_position(1, 1);
+        print_str(&mut buffer, "a");
+        assert_eq!(buffer.cell(0, 0).unwrap().ch, 'a');
+        assert!(buffer.cell(1, 0).unwrap().is_default());
+
+        buffer.cursor_position(1, 1);
+        print_str(&mut buffer, "漢");
+        buffer.cursor_position(1, 2);
+        print_str(&mut buffer, "b");
+        assert!(buffer.cell(0, 0).unwrap().is_default());
+        assert_eq!(buffer.cell(1, 0).unwrap().ch, 'b');
+        assert!(!buffer.cell(1, 0).unwrap().is_wide_continuation());
+    }
+
+    #[test]
+    fn erasing_wide_char_half_clears_both() {
+        let mut buffer = Buffer::new(4, 1);
+        print_str(&mut buffer, "a漢");
+        buffer.cursor_position(1, 3);
+        buffer.erase_in_line(0, false, Color::DEFAULT);
+        assert_eq!(buffer.to_string(), "a");
+
+        let mut buffer = Buffer::new(4, 1);
+        print_str(&mut buffer, "漢a");
+        buffer.cursor_position(1, 1);
+        buffer.erase_in_line(1, false, Color::DEFAULT);
+        assert_eq!(buffer.to_string(), "  a");
+    }
+
+    #[test]
+    fn clip_drops_cut_wide_char() {
+        let mut buffer = Buffer::new(4, 1);
+        print_str(&mut buffer, "a漢");
+        buffer.resize(2, 1, ResizeMode::Clip);
+        assert_eq!(buffer.to_string(), "a");
+    }
+
+    #[test]
+    fn clear_homes_cursor_and_blanks_grid() {
+        let mut buffer = Buffer::new(4, 3);
+        print_str(&mut buffer, "abcdef");
+        buffer.set_scroll_region(2, 3);
+        buffer.cursor_down(1);
+
+        buffer.clear();
+        assert_eq!(buffer.cursor(), Cursor::default());
+        assert_eq!(buffer.scroll_region(), 0..3);
+        assert_eq!(buffer, Buffer::new(4, 3));
+    }
+
+    #[test]
+    fn damage_spans_changed_rows() {
+        let mut buffer = Buffer::new(4, 6);
+        assert_eq!(buffer.take_damage(), Some((0, 5)));
+        assert_eq!(buffer.damage(), None);
+
+        buffer.cursor_position(2, 1);
+        print_str(&mut buffer, "a");
+        buffer.write_str(0, 4, "b", CellAttributes::default());
+        assert_eq!(buffer.damage(), Some((1, 4)));
+        assert_eq!(buffer.take_damage(), Some((1, 4)));
+
+        buffer.cursor_position(6, 1);
+        assert_eq!(buffer.damage(), None, "cursor movement alone");
+        buffer.erase_in_line(2, false, Color::DEFAULT);
+        buffer.set_cell(0, 3, Cell::new('c', CellAttributes::default()));
+        assert_eq!(buffer.take_damage(), Some((3, 5)));
+
+        buffer.set_scroll_region(2, 4);
+        buffer.cursor_position(4, 1);
+        buffer.linefeed();
+        assert_eq!(buffer.take_damage(), Some((1, 3)));
+    }
+
+    #[test]
+    fn many_scrolls_keep_rows_in_order() {
+        let mut buffer = Buffer::new(12, 3);
+        buffer.set_scrollback_limit(4);
+        for i in 0..1000 {
+            if i > 0 {
+                buffer.carriage_return();
+                buffer.linefeed();
+            }
+            print_str(&mut buffer, &format!("line {i}"));
+        }
+        // A line long enough to spill into the overflow, reused for blank rows below.
+        buffer.write_str(0, 0, "abcdefghijkl", CellAttributes::default());
+
+        assert_eq!(buffer.to_string(), "abcdefghijkl\nline 998\nline 999");
+        assert_eq!(buffer.cell(5, 1).unwrap().ch, '9');
+        let scrollback: Vec<_> = (0..buffer.scrollback_len())
+            .map(|i| buffer.scrollback_line(i).unwrap().get(5).ch)
+            .collect();
+        assert_eq!(scrollback, ['9', '9', '9', '9']);
+
+        buffer.carriage_return();
+        for _ in 0..3 {
+            buffer.linefeed();
+        }
+        assert!((0..3).all(|y| buffer.line(y) == Some(&Line::new(12))));
+        assert_eq!(buffer.scrollback_line(1).unwrap().get(11).ch, 'l');
+    }
+
+    #[test]
+    fn region_scrolls_after_ring_rotation() {
+        let mut buffer = Buffer::new(2, 4);
+        for ch in "abcdef".chars() {
+            print_str(&mut buffer, &ch.to_string());
+            buffer.carriage_return();
+            buffer.linefeed();
+        }
+        assert_eq!(buffer.to_string(), "d\ne\nf\n");
+
+        buffer.set_scroll_region(2, 3);
+        buffer.cursor_position(3, 1);
+        buffer.linefeed();
+        assert_eq!(buffer.to_string(), "d\nf\n\n");
+        assert_eq!(buffer.line(1).unwrap().get(0).ch, 'f');
+    }
+
+    fn lines_buffer() -> Buffer {
+        let mut buffer = Buffer::new(2, 5);
+        for (i, ch) in "abcde".chars().enumerate() {
+            buffer.cursor_position(i as u16 + 1, 1);
+            print_str(&mut buffer, &ch.to_string());
+        }
+        buffer.set_scroll_region(2, 4);
+        buffer.take_damage();
+        buffer
+    }
+
+    #[test]
+    fn insert_lines_in_scroll_region() {
+        let mut buffer = lines_buffer();
+        buffer.cursor_position(3, 2);
+        buffer.insert_blank_lines(1);
+        assert_eq!(buffer.to_string(), "a\nb\n\nc\ne");
+        assert_eq!(buffer.cursor(), Cursor { x: 0, y: 2 });
+        assert_eq!(buffer.take_damage(), Some((2, 3)));
+
+        buffer.cursor_position(2, 1);
+        buffer.insert_blank_lines(10);
+        assert_eq!(buffer.to_string(), "a\n\n\n\ne");
+
+        buffer.cursor_position(5, 1);
+        buffer.insert_blank_lines(1);
+        assert_eq!(buffer.to_string(), "a\n\n\n\ne", "below the region");
+    }
+
+    #[test]
+    fn delete_lines_in_scroll_region() {
+        let mut buffer = lines_buffer();
+        buffer.cursor_position(2, 2);
+        buffer.delete_lines(1);
+        assert_eq!(buffer.to_string(), "a\nc\nd\n\ne");
+        assert_eq!(buffer.cursor(), Cursor { x: 0, y: 1 });
+        assert_eq!(buffer.take_damage(), Some((1, 3)));
+
+        buffer.cursor_position(3, 1);
+        buffer.delete_lines(10);
+        assert_eq!(buffer.to_string(), "a\nc\n\n\ne");
+
+        buffer.cursor_position(1, 1);
+        buffer.delete_lines(1);
+        assert_eq!(buffer.to_string(), "a\nc\n\n\ne", "above the region");
+    }
+
+    #[test]
+    fn reverse_index_scrolls_at_top_margin() {
+        let mut buffer = lines_buffer();
+        buffer.cursor_position(3, 2);
+        buffer.reverse_index();
+        assert_eq!(buffer.cursor(), Cursor { x: 1, y: 1 });
+        assert_eq!(buffer.damage(), None);
+
+        buffer.reverse_index();
+        assert_eq!(
+            buffer.to_string(),
+            "a
+
+b
+c
+e"
+        );
+        assert_eq!(buffer.cursor(), Cursor { x: 1, y: 1 });
+        assert_eq!(buffer.take_damage(), Some((1, 3)));
+
+        buffer.cursor_position(1, 1);
+        buffer.reverse_index();
+        assert_eq!(
+            buffer.to_string(),
+            "a
+
+b
+c
+e",
+            "above the region"
+        );
+        assert_eq!(buffer.cursor(), Cursor { x: 0, y: 0 });
+    }
+
+    #[test]
+    fn zwj_sequence_in_one_cell() {
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        let mut buffer = Buffer::new(6, 2);
+        print_str(&mut buffer, family);
+        print_str(&mut buffer, "x");
+
+        assert_eq!(buffer.text_at(0, 0).as_deref(), Some(family));
+        assert!(buffer.cell(0, 0).unwrap().is_cluster());
+        assert!(buffer.cell(1, 0).unwrap().is_wide_continuation());
+        assert_eq!(buffer.text_at(2, 0).as_deref(), Some("x"));
+        assert_eq!(buffer.to_string(), format!("{family}x\n"));
+
+        // The same cluster again shares the stored characters.
+        let stored = buffer.clusters.len();
+        buffer.cursor_position(2, 1);
+        print_str(&mut buffer, family);
+        assert_eq!(buffer.cell(0, 1), buffer.cell(0, 0));
+        assert_eq!(buffer.clusters.len(), stored);
+    }
+
+    #[test]
+    fn unused_clusters_are_freed() {
+        let mut buffer = Buffer::new(4, 1);
+        let marks: Vec<char> = ('\u{300}'..='\u{36F}').collect();
+        // Far more distinct clusters than fit, each overwriting the previous one.
+        for (i, a) in marks.iter().enumerate() {
+            for b in &marks[..marks.len().min(i + 40)] {
+                buffer.carriage_return();
+                print_str(&mut buffer, &format!("e{a}{b}\u{301}"));
+                assert_eq!(
+                    buffer.text_at(0, 0),
+                    Some(format!("e{a}{b}\u{301}")),
+                    "not dropped"
+                );
+            }
+        }
+        assert!(buffer.clusters.len() <= Clusters::CAPACITY);
+
+        buffer.clear();
+        assert_eq!(buffer.clusters.len(), 0);
+    }
+
+    #[test]
+    fn combining_marks_join_previous_cell() {
+        let mut buffer = Buffer::new(4, 1);
+        print_str(&mut buffer, "e\u{301}a\u{308}\u{301}");
+
+        assert_eq!(buffer.cursor(), Cursor { x: 2, y: 0 });
+        assert_eq!(buffer.text_at(0, 0).as_deref(), Some("e\u{301}"));
+        assert_eq!(buffer.text_at(1, 0).as_deref(), Some("a\u{308}\u{301}"));
+        // A single mark is kept in the cell itself.
+        assert_eq!(buffer.clusters.len(), 1);
+
+        // Nothing to join at the start of a line.
+        buffer.carriage_return();
+        print_str(&mut buffer, "\u{301}");
+        assert_eq!(buffer.text_at(0, 0).as_deref(), Some("e\u{301}"));
+
+        buffer.set_cell(0, 0, Cell::new('o', CellAttributes::default()));
+        assert!(!buffer.cell(0, 0).unwrap().is_cluster());
+    }
+
+    #[test]
+    fn full_cluster_table_drops_characters() {
+        // Only clusters still shown are kept, so fill the table from a line of them.
+        let mut buffer = Buffer::new(Clusters::CAPACITY, 1);
+        let cell = Cell::new('a', CellAttributes::default());
+        for i in 0..Clusters::CAPACITY as u32 {
+            let extra = char::from_u32(0x10000 + i).unwrap();
+            let joined = buffer.join(cell, extra);
+            buffer.set_cell(i as usize, 0, joined);
+        }
+
+        let joined = buffer.join(cell, '\u{10FFFF}');
+        assert_eq!(joined, cell);
+        let joined = buffer.join(cell, '\u{301}');
+        let mut text = String::new();
+        buffer.push_text(&mut text, joined);
+        assert_eq!(text, "a\u{301}");
+    }
+
+    #[test]
+    fn previous_cell() {
+        let mut buffer = Buffer::new(4, 2);
+        assert_eq!(buffer.previous_cell(), None);
+
+        print_str(&mut buffer, "a漢");
+        assert_eq!(buffer.previous_cell(), Some((1, 0)));
+        print_str(&mut buffer, "b");
+        assert_eq!(buffer.previous_cell(), Some((3, 0)), "pending wrap");
+    }
+
+    #[test]
+    fn fill_and_erase_rectangle() {
+        let mut buffer = Buffer::new(5, 4);
+        let area = Rectangle {
+            top: 2,
+            left: 2,
+            bottom: 3,
+            right: 4,
+        };
+        buffer.fill_rectangle(area, Cell::new('x', CellAttributes::BOLD));
+        assert_eq!(buffer.to_string(), "\n xxx\n xxx\n");
+        assert!(buffer
+            .cell(1, 1)
+            .unwrap()
+            .attrs
+            .contains(CellAttributes::BOLD));
+
+        let area = Rectangle {
+            top: 3,
+            left: 3,
+            ..Rectangle::default()
+        };
+        buffer.erase_rectangle(area);
+        assert_eq!(buffer.to_string(), "\n xxx\n x\n");
+
+        buffer.fill_rectangle(
+            Rectangle::default(),
+            Cell::new('#', CellAttributes::default()),
+        );
+        buffer.erase_rectangle(Rectangle {
+            top: 9,
+            left: 1,
+            bottom: 2,
+            right: 9,
+        });
+        assert_eq!(
+            buffer.to_string(),
+            "#####\n#####\n#####\n#####",
+            "top below bottom"
+        );
     }
 }
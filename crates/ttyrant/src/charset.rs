@@ -0,0 +1,79 @@
+/// Character sets that can be designated into G0 to G3.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum Charset {
+    #[default]
+    Ascii,
+    /// DEC Special Graphics: `0x5F..=0x7E` are replaced by line drawing and other symbols.
+    DecSpecialGraphics,
+}
+
+impl Charset {
+    /// Set identified by the final byte of an SCS sequence, `None` for unsupported sets.
+    pub fn from_final(byte: u8) -> Option<Self> {
+        match byte {
+            b'B' => Some(Charset::Ascii),
+            b'0' => Some(Charset::DecSpecialGraphics),
+            _ => None,
+        }
+    }
+
+    /// Character shown for `ch` printed while this set is selected.
+    pub fn map(self, ch: char) -> char {
+        match self {
+            Charset::Ascii => ch,
+            Charset::DecSpecialGraphics => match ch {
+                '_' => ' ',
+                '`' => '◆',
+                'a' => '▒',
+                'b' => '␉',
+                'c' => '␌',
+                'd' => '␍',
+                'e' => '␊',
+                'f' => '°',
+                'g' => '±',
+                'h' => '␤',
+                'i' => '␋',
+                'j' => '┘',
+                'k' => '┐',
+                'l' => '┌',
+                'm' => '└',
+                'n' => '┼',
+                'o' => '⎺',
+                'p' => '⎻',
+                'q' => '─',
+                'r' => '⎼',
+                's' => '⎽',
+                't' => '├',
+                'u' => '┤',
+                'v' => '┴',
+                'w' => '┬',
+                'x' => '│',
+                'y' => '≤',
+                'z' => '≥',
+                '{' => 'π',
+                '|' => '≠',
+                '}' => '£',
+                '~' => '·',
+                ch => ch,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dec_special_graphics() {
+        let map = |s: &str| -> String {
+            s.chars()
+                .map(|ch| Charset::DecSpecialGraphics.map(ch))
+                .collect()
+        };
+
+        assert_eq!(map("lqkxmj"), "┌─┐│└┘");
+        assert_eq!(map("AZ09"), "AZ09");
+        assert_eq!(Charset::Ascii.map('q'), 'q');
+    }
+}
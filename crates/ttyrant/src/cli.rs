@@ -0,0 +1,184 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+
+pub const USAGE: &str = "\
+usage: ttyrant [options] [-e program [args...]]
+
+options:
+    --command <command>   run <command> with `sh -c` instead of the shell
+    --font-size <size>    font size in pixels
+    --title <title>       window title until the program sets one
+    --cols <cols>         initial number of columns
+    --rows <rows>         initial number of rows
+    --directory <dir>     start the program in <dir>
+    -e <program> [args]   run <program> with the remaining arguments
+    -h, --help            show this message";
+
+/// Options given on the command line.
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    /// Program and arguments to run instead of the user's shell.
+    pub command: Option<Vec<String>>,
+    pub font_size: Option<f32>,
+    pub title: Option<String>,
+    pub cols: Option<usize>,
+    pub rows: Option<usize>,
+    pub working_directory: Option<PathBuf>,
+}
+
+/// Why the command line couldn't be parsed.
+#[derive(Debug, PartialEq)]
+pub enum ArgsError {
+    /// `-h` or `--help` was given, the caller should print [`USAGE`].
+    Help,
+    Unknown(String),
+    MissingValue(String),
+    /// Option and value that isn't valid for it.
+    InvalidValue(String, String),
+    /// `-e` isn't followed by a program.
+    MissingProgram,
+}
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Help => write!(f, "help requested"),
+            Self::Unknown(arg) => write!(f, "unknown argument {arg:?}"),
+            Self::MissingValue(option) => write!(f, "{option} needs a value"),
+            Self::InvalidValue(option, value) => write!(f, "invalid value {value:?} for {option}"),
+            Self::MissingProgram => write!(f, "-e needs a program to run"),
+        }
+    }
+}
+
+impl std::error::Error for ArgsError {}
+
+impl Args {
+    /// Parses the arguments following the program name. Everything after `-e` is passed on to
+    /// the program as is, even if it looks like an option.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, ArgsError> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| ArgsError::MissingValue(arg.clone()))
+            };
+            match arg.as_str() {
+                "-h" | "--help" => return Err(ArgsError::Help),
+                "--command" => {
+                    parsed.command = Some(vec!["sh".into(), "-c".into(), value()?]);
+                }
+                "--font-size" => {
+                    parsed.font_size = Some(positive(&arg, value()?)?);
+                }
+                "--title" => parsed.title = Some(value()?),
+                "--cols" => parsed.cols = Some(positive(&arg, value()?)?),
+                "--rows" => parsed.rows = Some(positive(&arg, value()?)?),
+                "--directory" => parsed.working_directory = Some(value()?.into()),
+                "-e" => {
+                    let command: Vec<String> = args.by_ref().collect();
+                    if command.is_empty() {
+                        return Err(ArgsError::MissingProgram);
+                    }
+                    parsed.command = Some(command);
+                }
+                _ => return Err(ArgsError::Unknown(arg)),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+/// Parses `value` given for `option` as a number greater than zero.
+fn positive<T: FromStr + PartialOrd + Default>(
+    option: &str,
+    value: String,
+) -> Result<T, ArgsError> {
+    match value.parse::<T>() {
+        Ok(number) if number > T::default() => Ok(number),
+        _ => Err(ArgsError::InvalidValue(option.into(), value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, ArgsError> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn no_arguments() {
+        assert_eq!(parse(&[]), Ok(Args::default()));
+    }
+
+    #[test]
+    fn options() {
+        assert_eq!(
+            parse(&[
+                "--font-size",
+                "18.5",
+                "--title",
+                "logs",
+                "--cols",
+                "120",
+                "--rows",
+                "40",
+                "--command",
+                "tail -f log",
+                "--directory",
+                "/var/log",
+            ]),
+            Ok(Args {
+                command: Some(vec!["sh".into(), "-c".into(), "tail -f log".into()]),
+                font_size: Some(18.5),
+                title: Some("logs".into()),
+                cols: Some(120),
+                rows: Some(40),
+                working_directory: Some("/var/log".into()),
+            })
+        );
+    }
+
+    #[test]
+    fn execute_passes_remaining_arguments() {
+        assert_eq!(
+            parse(&["--cols", "100", "-e", "vim", "--cols", "-e", "file"]),
+            Ok(Args {
+                command: Some(vec![
+                    "vim".into(),
+                    "--cols".into(),
+                    "-e".into(),
+                    "file".into()
+                ]),
+                cols: Some(100),
+                ..Args::default()
+            })
+        );
+        assert_eq!(parse(&["-e"]), Err(ArgsError::MissingProgram));
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert_eq!(
+            parse(&["--rows"]),
+            Err(ArgsError::MissingValue("--rows".into()))
+        );
+        assert_eq!(
+            parse(&["--cols", "0"]),
+            Err(ArgsError::InvalidValue("--cols".into(), "0".into()))
+        );
+        assert_eq!(
+            parse(&["--font-size", "big"]),
+            Err(ArgsError::InvalidValue("--font-size".into(), "big".into()))
+        );
+        assert_eq!(
+            parse(&["--verbose"]),
+            Err(ArgsError::Unknown("--verbose".into()))
+        );
+        assert_eq!(parse(&["-h"]), Err(ArgsError::Help));
+    }
+}
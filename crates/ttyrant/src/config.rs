@@ -0,0 +1,252 @@
+use std::{fmt, fs, io, path::Path};
+
+use ansi::osc::parse_color;
+use serde::Deserialize;
+
+use crate::{
+    cell::DEFAULT_SCROLLBACK_LIMIT,
+    palette::Palette,
+    window::{CellMetrics, FONT_FAMILY},
+};
+
+/// Settings read from a config file, with defaults for everything the file leaves out.
+///
+/// ```toml
+/// scrollback = 5000
+/// shell = ["zsh", "-l"]
+///
+/// [font]
+/// family = "Fira Mono"
+/// size = 18
+///
+/// [theme]
+/// foreground = "#dcdccc"
+/// background = "#3f3f3f"
+/// # Replaces the palette from index 0 on.
+/// colors = ["#3f3f3f", "#cc9393"]
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub font_family: String,
+    pub font_size: f32,
+    /// Rows kept in scrollback, 0 disables it.
+    pub scrollback: usize,
+    /// Program and arguments to run instead of the user's shell.
+    pub shell: Option<Vec<String>>,
+    pub palette: Palette,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            font_family: FONT_FAMILY.into(),
+            font_size: CellMetrics::default().font_size,
+            scrollback: DEFAULT_SCROLLBACK_LIMIT,
+            shell: None,
+            palette: Palette::default(),
+        }
+    }
+}
+
+/// Why a config file couldn't be loaded.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    /// Key and value that isn't valid for it.
+    InvalidValue(&'static str, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "read config: {err}"),
+            Self::Parse(err) => write!(f, "parse config: {err}"),
+            Self::InvalidValue(key, value) => write!(f, "invalid value {value:?} for {key}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Parse(err) => Some(err),
+            Self::InvalidValue(..) => None,
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(err: toml::de::Error) -> Self {
+        Self::Parse(err)
+    }
+}
+
+/// Layout of the file, `None` where a key is missing.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct File {
+    scrollback: Option<usize>,
+    shell: Option<Vec<String>>,
+    font: Font,
+    theme: Theme,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Font {
+    family: Option<String>,
+    size: Option<f32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Theme {
+    foreground: Option<String>,
+    background: Option<String>,
+    colors: Vec<String>,
+}
+
+impl Config {
+    /// Reads the TOML file at `path`, see [`Config`] for the keys.
+    pub fn load_from(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses a config given as TOML, see [`Config::load_from`].
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let file: File = toml::from_str(text)?;
+        let mut config = Self::default();
+
+        if let Some(family) = file.font.family {
+            config.font_family = family;
+        }
+        if let Some(size) = file.font.size {
+            if size.is_nan() || size <= 0.0 {
+                return Err(ConfigError::InvalidValue("font.size", size.to_string()));
+            }
+            config.font_size = size;
+        }
+        if let Some(scrollback) = file.scrollback {
+            config.scrollback = scrollback;
+        }
+        if let Some(shell) = file.shell {
+            if shell.is_empty() {
+                return Err(ConfigError::InvalidValue("shell", "[]".into()));
+            }
+            config.shell = Some(shell);
+        }
+
+        if let Some(foreground) = file.theme.foreground {
+            config.palette.foreground = color("theme.foreground", foreground)?;
+        }
+        if let Some(background) = file.theme.background {
+            config.palette.background = color("theme.background", background)?;
+        }
+        if file.theme.colors.len() > 256 {
+            return Err(ConfigError::InvalidValue(
+                "theme.colors",
+                format!("{} colors", file.theme.colors.len()),
+            ));
+        }
+        for (index, spec) in file.theme.colors.into_iter().enumerate() {
+            config
+                .palette
+                .set(index as u8, color("theme.colors", spec)?);
+        }
+
+        Ok(config)
+    }
+}
+
+/// Parses `spec` given for `key` as `#rrggbb` or any other spec understood by [`parse_color`].
+fn color(key: &'static str, spec: String) -> Result<(u8, u8, u8), ConfigError> {
+    parse_color(spec.as_bytes()).map_err(|_| ConfigError::InvalidValue(key, spec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_config_is_default() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn sample_config_merged_with_defaults() {
+        let config = Config::parse(
+            r##"
+            shell = ["zsh", "-l"]
+
+            [font]
+            size = 18
+
+            [theme]
+            background = "#3f3f3f"
+            colors = ["#000000", "rgb:cc/93/93"]
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(config.shell, Some(vec!["zsh".into(), "-l".into()]));
+        assert_eq!(config.font_size, 18.0);
+        assert_eq!(config.palette.background, (0x3f, 0x3f, 0x3f));
+        assert_eq!(config.palette.get(1), (0xcc, 0x93, 0x93));
+
+        // Omitted keys keep their defaults.
+        let default = Config::default();
+        assert_eq!(config.font_family, FONT_FAMILY);
+        assert_eq!(config.scrollback, DEFAULT_SCROLLBACK_LIMIT);
+        assert_eq!(config.palette.foreground, default.palette.foreground);
+        assert_eq!(config.palette.get(2), default.palette.get(2));
+    }
+
+    #[test]
+    fn load_from_file() {
+        let path = std::env::temp_dir().join(format!("ttyrant-config-{}.toml", std::process::id()));
+        fs::write(&path, "scrollback = 0\n[font]\nfamily = \"Fira Mono\"\n").unwrap();
+        let config = Config::load_from(&path);
+        fs::remove_file(&path).unwrap();
+
+        let config = config.unwrap();
+        assert_eq!(config.scrollback, 0);
+        assert_eq!(config.font_family, "Fira Mono");
+
+        assert!(matches!(
+            Config::load_from(&path),
+            Err(ConfigError::Io(err)) if err.kind() == io::ErrorKind::NotFound
+        ));
+    }
+
+    #[test]
+    fn invalid_config() {
+        assert!(matches!(
+            Config::parse("scrollback = -1"),
+            Err(ConfigError::Parse(_))
+        ));
+        assert!(matches!(
+            Config::parse("font_size = 12"),
+            Err(ConfigError::Parse(_))
+        ));
+        assert!(matches!(
+            Config::parse("[font]\nsize = 0"),
+            Err(ConfigError::InvalidValue("font.size", _))
+        ));
+        assert!(matches!(
+            Config::parse("[theme]\nforeground = \"red\""),
+            Err(ConfigError::InvalidValue("theme.foreground", spec)) if spec == "red"
+        ));
+        assert!(matches!(
+            Config::parse("shell = []"),
+            Err(ConfigError::InvalidValue("shell", _))
+        ));
+    }
+}
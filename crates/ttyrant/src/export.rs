@@ -0,0 +1,331 @@
+use std::fmt::Write;
+
+use ansi::{
+    ansi::{AnsiCommand, BlinkInterval, Sgr, UnderlineStyle},
+    encode::encode_sgr,
+};
+use unicode_width::UnicodeWidthChar;
+
+use crate::{
+    cell::{Buffer, Cell, CellAttributes},
+    palette::Palette,
+};
+
+impl Buffer {
+    /// Writes the grid as HTML with the default palette, see [`Buffer::to_html_with`].
+    pub fn to_html(&self) -> String {
+        self.to_html_with(&Palette::default())
+    }
+
+    /// Writes the grid as a `<pre>` block with a `<span>` for every run of identically styled
+    /// cells, colors resolved through `palette`. Trailing blanks are trimmed like in the plain
+    /// text output.
+    pub fn to_html_with(&self, palette: &Palette) -> String {
+        let mut html = String::from("<pre>");
+        for y in 0..self.height() {
+            if y > 0 {
+                html.push('\n');
+            }
+            let Some(line) = self.line(y) else {
+                continue;
+            };
+
+            let mut run: Option<(Cell, String)> = None;
+            let mut x = 0;
+            while x < line.occupied_len() as usize {
+                let cell = line.get(x as u16);
+                x += cell.ch.width().unwrap_or(1).max(1);
+
+                match &mut run {
+                    Some((style, text)) if same_style(style, &cell) => self.push_text(text, cell),
+                    _ => {
+                        if let Some((style, text)) = run.take() {
+                            push_span(&mut html, &style, &text, palette);
+                        }
+                        let mut text = String::new();
+                        self.push_text(&mut text, cell);
+                        run = Some((cell, text));
+                    }
+                }
+            }
+            if let Some((style, text)) = run {
+                push_span(&mut html, &style, &text, palette);
+            }
+        }
+        html.push_str("</pre>");
+        html
+    }
+}
+
+impl Buffer {
+    /// Writes the grid as text with SGR sequences for its styling, e.g. to view it with
+    /// `less -R`. Only the attributes that change between cells are emitted, rows continued by
+    /// autowrap are written out to the last column so that they wrap again when replayed.
+    pub fn to_ansi(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut pen = Cell::default();
+        let mut text = String::new();
+        for y in 0..self.height() {
+            let Some(line) = self.line(y) else {
+                continue;
+            };
+            let continues = self.line(y + 1).is_some_and(|line| line.is_wrapped());
+            let len = if continues {
+                self.width()
+            } else {
+                line.occupied_len() as usize
+            };
+
+            let mut x = 0;
+            while x < len {
+                let cell = line.get(x as u16);
+                x += cell.ch.width().unwrap_or(1).max(1);
+
+                encode_sgr(&sgr_transition(&pen, &cell), &mut out);
+                text.clear();
+                self.push_text(&mut text, cell);
+                for ch in text.chars() {
+                    AnsiCommand::Print(ch).encode(&mut out);
+                }
+                pen = cell;
+            }
+
+            if !continues {
+                if !same_style(&pen, &Cell::default()) {
+                    encode_sgr(&[Sgr::Reset], &mut out);
+                    pen = Cell::default();
+                }
+                if y + 1 < self.height() {
+                    AnsiCommand::CarriageReturn.encode(&mut out);
+                    AnsiCommand::LineFeed.encode(&mut out);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// SGR attributes changing the style of `from` into the one of `to`.
+fn sgr_transition(from: &Cell, to: &Cell) -> Vec<Sgr> {
+    if same_style(from, to) {
+        return Vec::new();
+    }
+    if same_style(to, &Cell::default()) {
+        return vec![Sgr::Reset];
+    }
+
+    let (old, new) = (from.attrs, to.attrs);
+    let changed = |flags| old.contains(flags) != new.contains(flags);
+    let mut sgr = Vec::new();
+
+    // Bold and faint are only turned off together.
+    let regular = (old.contains(CellAttributes::BOLD) && !new.contains(CellAttributes::BOLD))
+        || (old.contains(CellAttributes::FAINT) && !new.contains(CellAttributes::FAINT));
+    if regular {
+        sgr.push(Sgr::Regular);
+    }
+    if new.contains(CellAttributes::BOLD) && (regular || changed(CellAttributes::BOLD)) {
+        sgr.push(Sgr::Bold);
+    }
+    if new.contains(CellAttributes::FAINT) && (regular || changed(CellAttributes::FAINT)) {
+        sgr.push(Sgr::Faint);
+    }
+    if changed(CellAttributes::ITALIC) {
+        sgr.push(if new.contains(CellAttributes::ITALIC) {
+            Sgr::Italic
+        } else {
+            Sgr::NeitherItalicNorBlackletter
+        });
+    }
+    if old.underline() != new.underline() {
+        sgr.push(Sgr::Underlined(new.underline()));
+    }
+    if changed(CellAttributes::BLINK) {
+        sgr.push(Sgr::Blink(if new.contains(CellAttributes::BLINK) {
+            BlinkInterval::Slow
+        } else {
+            BlinkInterval::Static
+        }));
+    }
+    if changed(CellAttributes::INVERSE) {
+        sgr.push(Sgr::Inverted(new.contains(CellAttributes::INVERSE)));
+    }
+    if changed(CellAttributes::HIDDEN) {
+        sgr.push(Sgr::Conceal(new.contains(CellAttributes::HIDDEN)));
+    }
+    if changed(CellAttributes::STRIKETHROUGH) {
+        sgr.push(Sgr::CrossedOut(new.contains(CellAttributes::STRIKETHROUGH)));
+    }
+    if from.fg != to.fg {
+        sgr.push(Sgr::ForegroundColor(to.fg.into()));
+    }
+    if from.bg != to.bg {
+        sgr.push(Sgr::BackgroundColor(to.bg.into()));
+    }
+    sgr
+}
+
+fn same_style(a: &Cell, b: &Cell) -> bool {
+    a.attrs == b.attrs && a.fg == b.fg && a.bg == b.bg
+}
+
+fn push_span(html: &mut String, style: &Cell, text: &str, palette: &Palette) {
+    let css = css(style, palette);
+    if !css.is_empty() {
+        let _ = write!(html, "<span style=\"{css}\">");
+    }
+    for ch in text.chars() {
+        match ch {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            ch => html.push(ch),
+        }
+    }
+    if !css.is_empty() {
+        html.push_str("</span>");
+    }
+}
+
+fn css(cell: &Cell, palette: &Palette) -> String {
+    let attrs = cell.attrs;
+    let mut fg = (!cell.fg.is_default()).then(|| palette.foreground(cell.fg));
+    let mut bg = (!cell.bg.is_default()).then(|| palette.background(cell.bg));
+    if attrs.contains(CellAttributes::INVERSE) {
+        (fg, bg) = (
+            Some(bg.unwrap_or(palette.background)),
+            Some(fg.unwrap_or(palette.foreground)),
+        );
+    }
+    if attrs.contains(CellAttributes::HIDDEN) {
+        fg = Some(bg.unwrap_or(palette.background));
+    }
+
+    let mut css = String::new();
+    if let Some((r, g, b)) = fg {
+        let _ = write!(css, "color:#{r:02x}{g:02x}{b:02x};");
+    }
+    if let Some((r, g, b)) = bg {
+        let _ = write!(css, "background-color:#{r:02x}{g:02x}{b:02x};");
+    }
+    if attrs.contains(CellAttributes::BOLD) {
+        css.push_str("font-weight:bold;");
+    }
+    if attrs.contains(CellAttributes::FAINT) {
+        css.push_str("opacity:0.5;");
+    }
+    if attrs.contains(CellAttributes::ITALIC) {
+        css.push_str("font-style:italic;");
+    }
+
+    let mut decorations = Vec::new();
+    if attrs.underline() != UnderlineStyle::None {
+        decorations.push("underline");
+    }
+    if attrs.contains(CellAttributes::STRIKETHROUGH) {
+        decorations.push("line-through");
+    }
+    if attrs.contains(CellAttributes::BLINK) {
+        decorations.push("blink");
+    }
+    match attrs.underline() {
+        UnderlineStyle::Double => decorations.push("double"),
+        UnderlineStyle::Curly => decorations.push("wavy"),
+        UnderlineStyle::Dotted => decorations.push("dotted"),
+        UnderlineStyle::Dashed => decorations.push("dashed"),
+        UnderlineStyle::None | UnderlineStyle::Straight => {}
+    }
+    if !decorations.is_empty() {
+        let _ = write!(css, "text-decoration:{};", decorations.join(" "));
+    }
+
+    css
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cell::Color, terminal::Terminal};
+
+    fn styled(ch: char, attrs: CellAttributes, fg: Color) -> Cell {
+        let mut cell = Cell::new(ch, attrs);
+        cell.fg = fg;
+        cell
+    }
+
+    #[test]
+    fn html_colored_line() {
+        let mut buffer = Buffer::new(10, 2);
+        let red = Color::indexed(1);
+        for ch in "ab".chars() {
+            buffer.print_cell(styled(ch, CellAttributes::BOLD, red));
+        }
+        buffer.print_cell(styled('c', CellAttributes::default(), Color::rgb(1, 2, 3)));
+        buffer.print('d', CellAttributes::default());
+
+        assert_eq!(
+            buffer.to_html(),
+            "<pre><span style=\"color:#cd0000;font-weight:bold;\">ab</span>\
+             <span style=\"color:#010203;\">c</span>d\n</pre>"
+        );
+    }
+
+    #[test]
+    fn html_inverse_uses_palette_defaults() {
+        let mut buffer = Buffer::new(4, 1);
+        buffer.print('x', CellAttributes::INVERSE);
+
+        assert_eq!(
+            buffer.to_html(),
+            "<pre><span style=\"color:#000000;background-color:#ffffff;\">x</span></pre>"
+        );
+    }
+
+    #[test]
+    fn html_escapes_special_characters() {
+        let mut buffer = Buffer::new(10, 1);
+        buffer.write_str(0, 0, "<a & \"b\">", CellAttributes::default());
+
+        assert_eq!(buffer.to_html(), "<pre>&lt;a &amp; &quot;b&quot;&gt;</pre>");
+    }
+
+    #[test]
+    fn ansi_minimal_changes() {
+        let mut terminal = Terminal::new(10, 2);
+        terminal.feed(b"\x1B[1;31ma\x1B[1mb\x1B[22;4mc\x1B[0md\r\n\x1B[7me");
+
+        assert_eq!(
+            terminal.buffer().to_ansi(),
+            b"\x1B[1;31mab\x1B[22;4mc\x1B[0md\r\n\x1B[7me\x1B[0m"
+        );
+    }
+
+    #[test]
+    fn ansi_round_trip() {
+        let mut terminal = Terminal::new(10, 4);
+        terminal.feed(
+            b"\x1B[1;38;5;200mbold\x1B[0m plain\r\n\
+              \x1B[4:3;48;2;1;2;3mcurly \x1B[24;3mand wrapped\x1B[0m\r\n\
+              \x1B[9;7m<>&\x1B[27m x",
+        );
+        let buffer = terminal.buffer();
+
+        let mut replayed = Terminal::new(10, 4);
+        replayed.feed(&buffer.to_ansi());
+
+        for y in 0..buffer.height() {
+            assert_eq!(
+                buffer.line(y).unwrap().is_wrapped(),
+                replayed.buffer().line(y).unwrap().is_wrapped()
+            );
+            for x in 0..buffer.width() {
+                assert_eq!(
+                    buffer.cell(x, y),
+                    replayed.buffer().cell(x, y),
+                    "({x}, {y})"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    io::{self, Write},
+    sync::Weak,
+};
+
+use crate::{
+    cell::Buffer,
+    pty::{Pty, PtyEvent},
+    terminal::Terminal,
+};
+
+/// Size of the grid when running without a window.
+const COLS: usize = 80;
+const ROWS: usize = 24;
+
+/// How the grid is written once the child exits.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Plain text, see [`Buffer`]'s `Display` implementation.
+    #[default]
+    Text,
+    /// See [`Buffer::to_html`].
+    Html,
+    /// Text with SGR sequences, see [`Buffer::to_ansi`].
+    Ansi,
+}
+
+/// Runs `command` with `sh -c`, or the user's shell if it is empty, on a PTY without opening a
+/// window. Once the child exits, the grid is written to `out` in `format`.
+pub fn run(command: &str, format: Format, out: &mut impl Write) -> io::Result<()> {
+    let mut builder = Pty::builder();
+    if !command.is_empty() {
+        builder = builder.command(vec!["sh".into(), "-c".into(), command.into()]);
+    }
+    let mut pty = builder.build(Weak::new());
+    let mut terminal = Terminal::new(COLS, ROWS);
+    let _ = pty.resize(COLS, ROWS);
+
+    while let Some(event) = pty.recv() {
+        if let PtyEvent::Output(bytes) = event {
+            terminal.feed(&bytes);
+            let responses = terminal.take_responses();
+            if !responses.is_empty() {
+                // The child may be gone already.
+                let _ = pty.write(&responses);
+            }
+        }
+    }
+
+    write_grid(out, terminal.buffer(), format)
+}
+
+/// Writes the grid in `format`, followed by a line break.
+pub fn write_grid(out: &mut impl Write, buffer: &Buffer, format: Format) -> io::Result<()> {
+    match format {
+        Format::Text => writeln!(out, "{buffer}")?,
+        Format::Html => writeln!(out, "{}", buffer.to_html())?,
+        Format::Ansi => {
+            out.write_all(&buffer.to_ansi())?;
+            writeln!(out)?;
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_output_matches_buffer_text() {
+        let mut terminal = Terminal::new(10, 3);
+        terminal.feed(b"one\r\n\x1B[31mtwo\x1B[3;4Hx");
+
+        let mut out = Vec::new();
+        write_grid(&mut out, terminal.buffer(), Format::Text).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("{}\n", terminal.buffer())
+        );
+    }
+
+    #[test]
+    fn grid_output_formats() {
+        let mut terminal = Terminal::new(10, 2);
+        terminal.feed(b"\x1B[31mred\x1B[0m <b>");
+        let buffer = terminal.buffer();
+
+        let mut out = Vec::new();
+        write_grid(&mut out, buffer, Format::Html).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), buffer.to_html() + "\n");
+
+        let mut out = Vec::new();
+        write_grid(&mut out, buffer, Format::Ansi).unwrap();
+        assert_eq!(out, [buffer.to_ansi(), b"\n".to_vec()].concat());
+    }
+
+    #[test]
+    fn run_dumps_command_output() {
+        let mut out = Vec::new();
+        run("printf 'a\\nb'", Format::Text, &mut out).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("a\nb\n"), "{text:?}");
+        assert_eq!(text.lines().count(), ROWS);
+    }
+}
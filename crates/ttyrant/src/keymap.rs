@@ -0,0 +1,266 @@
+use winit::keyboard::{Key, ModifiersState, NamedKey};
+
+use crate::terminal::Modes;
+
+/// Bytes sent to the child for `key` pressed with `modifiers`, following xterm. `None` for keys
+/// that send nothing, such as modifiers on their own.
+///
+/// `modes` selects between the normal and application cursor keys (DECCKM) and whether Enter
+/// sends CR LF (LNM). Alt prefixes characters with ESC, Control turns them into C0 controls.
+pub fn encode_key(key: &Key, modifiers: ModifiersState, modes: &Modes) -> Option<Vec<u8>> {
+    let named = match key {
+        Key::Character(text) => return Some(character(text, modifiers)),
+        Key::Named(named) => *named,
+        _ => return None,
+    };
+
+    let bytes: &[u8] = match named {
+        NamedKey::Space => return Some(character(" ", modifiers)),
+        NamedKey::Enter if modes.newline => b"\r\n",
+        NamedKey::Enter => b"\r",
+        NamedKey::Backspace if modifiers.control_key() => b"\x08",
+        NamedKey::Backspace => b"\x7f",
+        NamedKey::Tab if modifiers.shift_key() => return Some(b"\x1B[Z".to_vec()),
+        NamedKey::Tab => b"\t",
+        NamedKey::Escape => b"\x1B",
+        _ => return cursor_key(named, modifiers, modes).or_else(|| function_key(named, modifiers)),
+    };
+    Some(alt_prefixed(bytes, modifiers))
+}
+
+/// Modifier parameter of xterm's `CSI 1 ; Pm X` and `CSI Ps ; Pm ~` forms, or `None` if no
+/// modifier is held.
+fn modifier_param(modifiers: ModifiersState) -> Option<u8> {
+    let param = 1
+        + modifiers.shift_key() as u8
+        + 2 * modifiers.alt_key() as u8
+        + 4 * modifiers.control_key() as u8
+        + 8 * modifiers.super_key() as u8;
+    (param > 1).then_some(param)
+}
+
+fn alt_prefixed(bytes: &[u8], modifiers: ModifiersState) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len() + 1);
+    if modifiers.alt_key() {
+        out.push(0x1B);
+    }
+    out.extend_from_slice(bytes);
+    out
+}
+
+/// Text typed with a key. With Control, `@` to `_` and letters become the C0 controls `^@` to
+/// `^_`, and `?` becomes DEL.
+fn character(text: &str, modifiers: ModifiersState) -> Vec<u8> {
+    let mut chars = text.chars();
+    let control = match (chars.next(), chars.next()) {
+        (Some(ch), None) if modifiers.control_key() => match ch.to_ascii_uppercase() {
+            ch @ '@'..='_' => Some(ch as u8 - b'@'),
+            ' ' => Some(0),
+            '?' => Some(0x7f),
+            _ => None,
+        },
+        _ => None,
+    };
+    match control {
+        Some(byte) => alt_prefixed(&[byte], modifiers),
+        None => alt_prefixed(text.as_bytes(), modifiers),
+    }
+}
+
+/// Bytes xterm sends for the cursor and editing keys, `None` for other keys.
+///
+/// Arrows, Home and End are `CSI X`, or SS3 (`ESC O X`) in application cursor key mode; the
+/// others are `CSI Ps ~`. Modifiers are sent as an additional parameter like for
+/// [`function_key`], which always uses CSI.
+fn cursor_key(key: NamedKey, modifiers: ModifiersState, modes: &Modes) -> Option<Vec<u8>> {
+    let modifier = modifier_param(modifiers);
+    let letter = |final_byte: char| match modifier {
+        None if modes.cursor_keys => format!("\x1BO{final_byte}"),
+        None => format!("\x1B[{final_byte}"),
+        Some(modifier) => format!("\x1B[1;{modifier}{final_byte}"),
+    };
+    let tilde = |code: u8| match modifier {
+        None => format!("\x1B[{code}~"),
+        Some(modifier) => format!("\x1B[{code};{modifier}~"),
+    };
+
+    let sequence = match key {
+        NamedKey::ArrowUp => letter('A'),
+        NamedKey::ArrowDown => letter('B'),
+        NamedKey::ArrowRight => letter('C'),
+        NamedKey::ArrowLeft => letter('D'),
+        NamedKey::Home => letter('H'),
+        NamedKey::End => letter('F'),
+        NamedKey::Insert => tilde(2),
+        NamedKey::Delete => tilde(3),
+        NamedKey::PageUp => tilde(5),
+        NamedKey::PageDown => tilde(6),
+        _ => return None,
+    };
+    Some(sequence.into_bytes())
+}
+
+/// Bytes xterm sends for F1 through F12, `None` for other keys.
+///
+/// F1 to F4 are SS3 sequences (`ESC O P`), the others `CSI Ps ~`. With modifiers held both are sent
+/// as CSI with the modifier as an additional parameter, e.g. `CSI 1 ; 2 P` for Shift-F1.
+fn function_key(key: NamedKey, modifiers: ModifiersState) -> Option<Vec<u8>> {
+    let modifier = modifier_param(modifiers);
+    let ss3 = |final_byte: char| match modifier {
+        None => format!("\x1BO{final_byte}"),
+        Some(modifier) => format!("\x1B[1;{modifier}{final_byte}"),
+    };
+    let tilde = |code: u8| match modifier {
+        None => format!("\x1B[{code}~"),
+        Some(modifier) => format!("\x1B[{code};{modifier}~"),
+    };
+
+    let sequence = match key {
+        NamedKey::F1 => ss3('P'),
+        NamedKey::F2 => ss3('Q'),
+        NamedKey::F3 => ss3('R'),
+        NamedKey::F4 => ss3('S'),
+        NamedKey::F5 => tilde(15),
+        NamedKey::F6 => tilde(17),
+        NamedKey::F7 => tilde(18),
+        NamedKey::F8 => tilde(19),
+        NamedKey::F9 => tilde(20),
+        NamedKey::F10 => tilde(21),
+        NamedKey::F11 => tilde(23),
+        NamedKey::F12 => tilde(24),
+        _ => return None,
+    };
+    Some(sequence.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn function_keys() {
+        let none = ModifiersState::empty();
+        assert_eq!(function_key(NamedKey::F1, none), Some(b"\x1BOP".to_vec()));
+        assert_eq!(
+            function_key(NamedKey::F12, none),
+            Some(b"\x1B[24~".to_vec())
+        );
+        assert_eq!(function_key(NamedKey::Enter, none), None);
+
+        assert_eq!(
+            function_key(NamedKey::F5, ModifiersState::SHIFT),
+            Some(b"\x1B[15;2~".to_vec())
+        );
+        assert_eq!(
+            function_key(NamedKey::F1, ModifiersState::SHIFT),
+            Some(b"\x1B[1;2P".to_vec())
+        );
+        assert_eq!(
+            function_key(NamedKey::F3, ModifiersState::CONTROL),
+            Some(b"\x1B[1;5R".to_vec())
+        );
+        assert_eq!(
+            function_key(NamedKey::F7, ModifiersState::SHIFT | ModifiersState::ALT),
+            Some(b"\x1B[18;4~".to_vec())
+        );
+    }
+
+    #[test]
+    fn key_table() {
+        let none = ModifiersState::empty();
+        let shift = ModifiersState::SHIFT;
+        let alt = ModifiersState::ALT;
+        let ctrl = ModifiersState::CONTROL;
+        let modes = Modes::default();
+        let application = Modes {
+            cursor_keys: true,
+            ..Modes::default()
+        };
+        let newline = Modes {
+            newline: true,
+            ..Modes::default()
+        };
+        let char = |text: &str| Key::Character(text.into());
+        let named = Key::Named;
+
+        let table: &[(Key, ModifiersState, &Modes, &[u8])] = &[
+            // Text.
+            (char("a"), none, &modes, b"a"),
+            (char("A"), shift, &modes, b"A"),
+            (char("é"), none, &modes, "é".as_bytes()),
+            (char("a"), alt, &modes, b"\x1Ba"),
+            (char("a"), ctrl, &modes, b"\x01"),
+            (char("C"), ctrl | shift, &modes, b"\x03"),
+            (char("z"), ctrl, &modes, b"\x1A"),
+            (char("["), ctrl, &modes, b"\x1B"),
+            (char("@"), ctrl, &modes, b"\x00"),
+            (char("_"), ctrl, &modes, b"\x1F"),
+            (char("?"), ctrl, &modes, b"\x7F"),
+            (char("c"), ctrl | alt, &modes, b"\x1B\x03"),
+            (char("1"), ctrl, &modes, b"1"),
+            // Whitespace and editing.
+            (named(NamedKey::Space), none, &modes, b" "),
+            (named(NamedKey::Space), ctrl, &modes, b"\x00"),
+            (named(NamedKey::Space), alt, &modes, b"\x1B "),
+            (named(NamedKey::Enter), none, &modes, b"\r"),
+            (named(NamedKey::Enter), none, &newline, b"\r\n"),
+            (named(NamedKey::Enter), alt, &modes, b"\x1B\r"),
+            (named(NamedKey::Backspace), none, &modes, b"\x7F"),
+            (named(NamedKey::Backspace), ctrl, &modes, b"\x08"),
+            (named(NamedKey::Backspace), alt, &modes, b"\x1B\x7F"),
+            (named(NamedKey::Tab), none, &modes, b"\t"),
+            (named(NamedKey::Tab), shift, &modes, b"\x1B[Z"),
+            (named(NamedKey::Escape), none, &modes, b"\x1B"),
+            // Cursor keys.
+            (named(NamedKey::ArrowUp), none, &modes, b"\x1B[A"),
+            (named(NamedKey::ArrowDown), none, &modes, b"\x1B[B"),
+            (named(NamedKey::ArrowRight), none, &modes, b"\x1B[C"),
+            (named(NamedKey::ArrowLeft), none, &modes, b"\x1B[D"),
+            (named(NamedKey::Home), none, &modes, b"\x1B[H"),
+            (named(NamedKey::End), none, &modes, b"\x1B[F"),
+            (named(NamedKey::ArrowUp), none, &application, b"\x1BOA"),
+            (named(NamedKey::ArrowLeft), none, &application, b"\x1BOD"),
+            (named(NamedKey::End), none, &application, b"\x1BOF"),
+            (named(NamedKey::ArrowUp), shift, &modes, b"\x1B[1;2A"),
+            (
+                named(NamedKey::ArrowRight),
+                ctrl,
+                &application,
+                b"\x1B[1;5C",
+            ),
+            (named(NamedKey::Home), alt, &modes, b"\x1B[1;3H"),
+            (
+                named(NamedKey::ArrowDown),
+                ctrl | shift,
+                &modes,
+                b"\x1B[1;6B",
+            ),
+            (named(NamedKey::Insert), none, &modes, b"\x1B[2~"),
+            (named(NamedKey::Delete), none, &application, b"\x1B[3~"),
+            (named(NamedKey::PageUp), none, &modes, b"\x1B[5~"),
+            (named(NamedKey::PageDown), ctrl, &modes, b"\x1B[6;5~"),
+            (
+                named(NamedKey::Delete),
+                shift | alt | ctrl,
+                &modes,
+                b"\x1B[3;8~",
+            ),
+            // Function keys.
+            (named(NamedKey::F1), none, &application, b"\x1BOP"),
+            (named(NamedKey::F4), alt, &modes, b"\x1B[1;3S"),
+            (named(NamedKey::F10), none, &modes, b"\x1B[21~"),
+            (named(NamedKey::F11), ctrl, &modes, b"\x1B[23;5~"),
+        ];
+        for (key, modifiers, modes, expected) in table {
+            assert_eq!(
+                encode_key(key, *modifiers, modes).as_deref(),
+                Some(*expected),
+                "{key:?} with {modifiers:?}"
+            );
+        }
+
+        assert_eq!(encode_key(&named(NamedKey::Shift), shift, &modes), None);
+        assert_eq!(encode_key(&named(NamedKey::F13), none, &modes), None);
+        assert_eq!(encode_key(&Key::Dead(Some('^')), none, &modes), None);
+    }
+}
@@ -1,14 +1,149 @@
 use application::Application;
+use bell::BellMode;
+use cell::ResizeMode;
+use cli::{Args, ArgsError, USAGE};
+use wgpu::PresentMode;
 use winit::event_loop::EventLoop;
 
 mod application;
+mod bell;
+mod blink;
 mod cell;
+mod charset;
+mod cli;
+#[cfg(feature = "config")]
+mod config;
+mod export;
+mod headless;
+mod keymap;
+mod offscreen;
+mod pacer;
+mod palette;
+mod png;
 mod pty;
+mod selection;
+mod style_cache;
+mod termcap;
+mod terminal;
+mod url;
 mod window;
 
 fn main() {
+    if let Ok(command) = std::env::var("TTYRANT_HEADLESS") {
+        let format = match std::env::var("TTYRANT_HEADLESS_FORMAT").as_deref() {
+            Ok("html") => headless::Format::Html,
+            Ok("ansi") => headless::Format::Ansi,
+            _ => headless::Format::Text,
+        };
+        if let Err(err) = headless::run(&command, format, &mut std::io::stdout().lock()) {
+            eprintln!("ttyrant: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(ArgsError::Help) => {
+            println!("{USAGE}");
+            return;
+        }
+        Err(err) => {
+            eprintln!("ttyrant: {err}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    let local_echo = cfg!(debug_assertions) && std::env::var_os("TTYRANT_LOCAL_ECHO").is_some();
+    let flow_control = std::env::var_os("TTYRANT_FLOW_CONTROL").is_some();
+    let overstrike = std::env::var_os("TTYRANT_OVERSTRIKE").is_some();
+    let resize_mode = match std::env::var("TTYRANT_RESIZE").as_deref() {
+        Ok("clip") => ResizeMode::Clip,
+        _ => ResizeMode::Reflow,
+    };
+    let bell_mode = match std::env::var("TTYRANT_BELL").as_deref() {
+        Ok("none") => BellMode::None,
+        Ok("audible") => BellMode::Audible,
+        _ => BellMode::Visual,
+    };
+
+    let line_spacing = std::env::var("TTYRANT_LINE_SPACING")
+        .ok()
+        .and_then(|spacing| spacing.parse::<f32>().ok())
+        .filter(|spacing| *spacing > 0.0);
+
+    let present_mode = match std::env::var("TTYRANT_PRESENT_MODE").as_deref() {
+        Ok("mailbox") => PresentMode::Mailbox,
+        Ok("immediate") => PresentMode::Immediate,
+        _ => PresentMode::Fifo,
+    };
+    let read_buffer_size = std::env::var("TTYRANT_READ_BUFFER_SIZE")
+        .ok()
+        .and_then(|size| size.parse::<usize>().ok());
+    let opacity = std::env::var("TTYRANT_OPACITY")
+        .ok()
+        .and_then(|opacity| opacity.parse::<f32>().ok());
+
+    let mut application = Application::new();
+    #[cfg(feature = "config")]
+    if let Some(path) = config_path() {
+        match config::Config::load_from(&path) {
+            Ok(config) => application = application.with_config(config),
+            Err(config::ConfigError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => eprintln!("ttyrant: {}: {err}", path.display()),
+        }
+    }
+    application = application
+        .with_local_echo(local_echo)
+        .with_flow_control(flow_control)
+        .with_overstrike(overstrike)
+        .with_resize_mode(resize_mode)
+        .with_bell_mode(bell_mode)
+        .with_present_mode(present_mode);
+    if let Some(spacing) = line_spacing {
+        application = application.with_line_spacing(spacing);
+    }
+    if let Some(opacity) = opacity {
+        application = application.with_opacity(opacity);
+    }
+    if let Some(size) = read_buffer_size {
+        application = application.with_read_buffer_size(size);
+    }
+    if let Some(size) = args.font_size {
+        application = application.with_font_size(size);
+    }
+    if let Some(title) = args.title {
+        application = application.with_title(title);
+    }
+    if let Some(command) = args.command {
+        application = application.with_command(command);
+    }
+    if let Some(dir) = args.working_directory {
+        application = application.with_working_directory(dir);
+    }
+    if args.cols.is_some() || args.rows.is_some() {
+        application = application.with_grid_size(args.cols.unwrap_or(80), args.rows.unwrap_or(24));
+    }
+    #[cfg(feature = "nfc")]
+    {
+        application = application.with_normalization(std::env::var_os("TTYRANT_NFC").is_some());
+    }
+
     let event_loop = EventLoop::new().expect("create event loop");
     event_loop
-        .run_app(&mut Application::new())
+        .run_app(&mut application)
         .expect("run event loop");
 }
+
+/// `TTYRANT_CONFIG` if set, otherwise `ttyrant/config.toml` in the XDG config directory.
+#[cfg(feature = "config")]
+fn config_path() -> Option<std::path::PathBuf> {
+    if let Some(path) = std::env::var_os("TTYRANT_CONFIG") {
+        return Some(path.into());
+    }
+    let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => dir.into(),
+        _ => std::path::PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("ttyrant/config.toml"))
+}
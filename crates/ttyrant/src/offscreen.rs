@@ -0,0 +1,202 @@
+use unicode_width::UnicodeWidthChar;
+
+use crate::{
+    cell::{Buffer, Cell, CellAttributes},
+    palette::Palette,
+    png,
+};
+
+/// An RGBA image with 8 bits per channel, rows stored top to bottom.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height * 4],
+        }
+    }
+
+    #[cfg(test)]
+    pub fn pixel(&self, x: usize, y: usize) -> Option<(u8, u8, u8, u8)> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let i = (y * self.width + x) * 4;
+        let p = &self.pixels[i..i + 4];
+        Some((p[0], p[1], p[2], p[3]))
+    }
+
+    /// The image encoded as PNG.
+    pub fn to_png(&self) -> Vec<u8> {
+        png::encode_rgba(self.width as u32, self.height as u32, &self.pixels)
+    }
+
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, (r, g, b): (u8, u8, u8)) {
+        for y in y..(y + height).min(self.height) {
+            for x in x..(x + width).min(self.width) {
+                let i = (y * self.width + x) * 4;
+                self.pixels[i..i + 4].copy_from_slice(&[r, g, b, 0xff]);
+            }
+        }
+    }
+}
+
+/// Draws a [`Buffer`] into an [`Image`] without a window or GPU, for snapshot tests.
+///
+/// There is no font rendering: every cell is filled with its background color and non-blank
+/// characters are drawn as a rectangle of their foreground color, inset by one pixel. That keeps
+/// the output deterministic across machines while still showing colors and layout.
+pub struct OffscreenRenderer {
+    cell_width: usize,
+    cell_height: usize,
+    palette: Palette,
+    cursor: bool,
+}
+
+impl OffscreenRenderer {
+    pub fn new(cell_width: usize, cell_height: usize) -> Self {
+        Self {
+            cell_width,
+            cell_height,
+            palette: Palette::default(),
+            cursor: false,
+        }
+    }
+
+    pub fn with_palette(mut self, palette: Palette) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    /// Draw the cursor as a cell filled with the foreground color.
+    pub fn with_cursor(mut self, enabled: bool) -> Self {
+        self.cursor = enabled;
+        self
+    }
+
+    pub fn render(&self, buffer: &Buffer) -> Image {
+        let (cw, ch) = (self.cell_width, self.cell_height);
+        let mut image = Image::new(buffer.width() * cw, buffer.height() * ch);
+
+        for y in 0..buffer.height() {
+            for x in 0..buffer.width() {
+                // The lead of a wide character covers its continuation as well.
+                let Some(cell) = buffer
+                    .cell(x, y)
+                    .filter(|cell| !cell.is_wide_continuation())
+                else {
+                    continue;
+                };
+                let (fg, bg) = self.colors(&cell);
+                let width = cell.ch.width().unwrap_or(1).max(1) * cw;
+                image.fill(x * cw, y * ch, width, ch, bg);
+
+                let hidden = cell.attrs.contains(CellAttributes::HIDDEN);
+                if cell.ch != ' ' && !hidden && cw > 2 && ch > 2 {
+                    image.fill(x * cw + 1, y * ch + 1, width - 2, ch - 2, fg);
+                }
+            }
+        }
+
+        if self.cursor {
+            let cursor = buffer.cursor();
+            image.fill(
+                cursor.x * cw,
+                cursor.y * ch,
+                cw,
+                ch,
+                self.palette.foreground,
+            );
+        }
+
+        image
+    }
+
+    fn colors(&self, cell: &Cell) -> ((u8, u8, u8), (u8, u8, u8)) {
+        let fg = self.palette.foreground(cell.fg);
+        let bg = self.palette.background(cell.bg);
+        if cell.attrs.contains(CellAttributes::INVERSE) {
+            (bg, fg)
+        } else {
+            (fg, bg)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cell::Color, terminal::Terminal};
+
+    #[test]
+    fn render_colored_grid() {
+        let mut terminal = Terminal::new(2, 1);
+        terminal.feed(b"\x1B[31;44mA\x1B[0m");
+
+        let image = OffscreenRenderer::new(3, 3)
+            .with_cursor(true)
+            .render(terminal.buffer());
+
+        const B: [u8; 4] = [0x00, 0x00, 0xee, 0xff];
+        const R: [u8; 4] = [0xcd, 0x00, 0x00, 0xff];
+        const W: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+        let expected = [[B, B, B, W, W, W], [B, R, B, W, W, W], [B, B, B, W, W, W]];
+
+        assert_eq!((image.width, image.height), (6, 3));
+        assert_eq!(image.pixels, expected.concat().concat());
+    }
+
+    #[test]
+    fn render_wide_char() {
+        let mut buffer = Buffer::new(2, 1);
+        let mut cell = Cell::new('中', CellAttributes::default());
+        cell.fg = Color::indexed(1);
+        cell.bg = Color::indexed(4);
+        buffer.print_cell(cell);
+
+        let image = OffscreenRenderer::new(3, 3).render(&buffer);
+
+        const B: [u8; 4] = [0x00, 0x00, 0xee, 0xff];
+        const R: [u8; 4] = [0xcd, 0x00, 0x00, 0xff];
+        let expected = [[B, B, B, B, B, B], [B, R, R, R, R, B], [B, B, B, B, B, B]];
+
+        assert_eq!(image.pixels, expected.concat().concat());
+    }
+
+    #[test]
+    fn render_inverse_and_blank() {
+        let mut terminal = Terminal::new(2, 1);
+        terminal.feed(b"\x1B[7mx");
+
+        let image = OffscreenRenderer::new(3, 3).render(terminal.buffer());
+
+        assert_eq!(image.pixel(0, 0), Some((0xff, 0xff, 0xff, 0xff)));
+        assert_eq!(image.pixel(1, 1), Some((0x00, 0x00, 0x00, 0xff)));
+        assert_eq!(image.pixel(4, 1), Some((0x00, 0x00, 0x00, 0xff)));
+        assert_eq!(image.pixel(6, 0), None);
+    }
+
+    #[test]
+    fn png_of_rendered_grid() {
+        let mut terminal = Terminal::new(2, 1);
+        terminal.feed(b"\x1B[41m ");
+
+        let png = OffscreenRenderer::new(3, 3)
+            .render(terminal.buffer())
+            .to_png();
+
+        // IHDR with the size of the image, RGBA at 8 bits per channel.
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..26], &[0, 0, 0, 6, 0, 0, 0, 3, 8, 6]);
+        // The red background of the first row, after its filter type.
+        let pixels = png.windows(5).position(|w| w == [0, 0xcd, 0, 0, 0xff]);
+        assert!(pixels.is_some());
+    }
+}
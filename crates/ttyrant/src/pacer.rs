@@ -0,0 +1,131 @@
+use std::time::{Duration, Instant};
+
+/// Time between frames at 60 Hz.
+pub const DEFAULT_FRAME_INTERVAL: Duration = Duration::from_micros(16_667);
+
+/// Longest a frame is held back by [`FramePacer::hold`], in case the program never finishes it.
+pub const HOLD_TIMEOUT: Duration = Duration::from_millis(150);
+
+/// Limits how often the window is redrawn.
+///
+/// The reader thread asks for a redraw after every chunk of output, which under bursty output is
+/// much more often than the display refreshes. Requests are coalesced into a pending redraw that
+/// is drawn at most once per frame interval.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FramePacer {
+    interval: Duration,
+    needs_redraw: bool,
+    last_frame: Option<Instant>,
+    /// When the frame being held back started to be, see [`FramePacer::hold`].
+    held_since: Option<Instant>,
+}
+
+impl Default for FramePacer {
+    fn default() -> Self {
+        Self::new(DEFAULT_FRAME_INTERVAL)
+    }
+}
+
+impl FramePacer {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            needs_redraw: false,
+            last_frame: None,
+            held_since: None,
+        }
+    }
+
+    /// Marks the content as changed. Any number of requests before the next frame result in a
+    /// single redraw.
+    pub fn request(&mut self) {
+        self.needs_redraw = true;
+    }
+
+    /// Holds back redraws while `held`, e.g. while the program writes a frame with synchronized
+    /// output. Redraws requested meanwhile are drawn as one once released, or after
+    /// [`HOLD_TIMEOUT`] from `now` if it isn't.
+    pub fn hold(&mut self, held: bool, now: Instant) {
+        if !held {
+            self.held_since = None;
+        } else if self.held_since.is_none() {
+            self.held_since = Some(now);
+        }
+    }
+
+    /// Earliest time the next frame may be drawn.
+    pub fn deadline(&self) -> Option<Instant> {
+        let frame = self.last_frame.map(|last| last + self.interval);
+        let hold = self.held_since.map(|since| since + HOLD_TIMEOUT);
+        frame.max(hold)
+    }
+
+    /// Whether a redraw is pending and a frame interval passed since the last one at `now`.
+    pub fn should_draw(&self, now: Instant) -> bool {
+        self.needs_redraw && self.deadline().is_none_or(|deadline| now >= deadline)
+    }
+
+    /// Records that a frame was drawn at `now`, clearing the pending redraw.
+    pub fn drawn(&mut self, now: Instant) {
+        self.needs_redraw = false;
+        self.last_frame = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_coalesce_into_one_frame() {
+        let mut pacer = FramePacer::new(Duration::from_millis(10));
+        let start = Instant::now();
+        assert!(!pacer.should_draw(start));
+
+        pacer.request();
+        pacer.request();
+        assert!(pacer.should_draw(start));
+        pacer.drawn(start);
+        assert!(!pacer.should_draw(start + Duration::from_secs(1)));
+
+        pacer.request();
+        pacer.request();
+        assert!(!pacer.should_draw(start + Duration::from_millis(5)));
+        assert_eq!(pacer.deadline(), Some(start + Duration::from_millis(10)));
+        assert!(pacer.should_draw(start + Duration::from_millis(10)));
+
+        pacer.drawn(start + Duration::from_millis(12));
+        assert!(!pacer.should_draw(start + Duration::from_millis(30)));
+    }
+
+    #[test]
+    fn held_frames_drawn_once_released() {
+        let mut pacer = FramePacer::new(Duration::from_millis(10));
+        let start = Instant::now();
+        pacer.hold(true, start);
+
+        for ms in [0, 20, 40] {
+            pacer.request();
+            assert!(!pacer.should_draw(start + Duration::from_millis(ms)));
+        }
+
+        pacer.hold(false, start + Duration::from_millis(50));
+        assert!(pacer.should_draw(start + Duration::from_millis(50)));
+        pacer.drawn(start + Duration::from_millis(50));
+        assert!(!pacer.should_draw(start + Duration::from_millis(60)));
+    }
+
+    #[test]
+    fn held_frame_drawn_after_timeout() {
+        let mut pacer = FramePacer::new(Duration::from_millis(10));
+        let start = Instant::now();
+        pacer.hold(true, start);
+        // Holding again doesn't restart the timeout.
+        pacer.hold(true, start + Duration::from_millis(100));
+        pacer.request();
+
+        assert!(!pacer.should_draw(start + HOLD_TIMEOUT - Duration::from_millis(1)));
+        assert_eq!(pacer.deadline(), Some(start + HOLD_TIMEOUT));
+        assert!(pacer.should_draw(start + HOLD_TIMEOUT));
+    }
+}
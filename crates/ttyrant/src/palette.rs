@@ -0,0 +1,83 @@
+use crate::cell::Color;
+
+/// Maps colors to the RGB values they are drawn with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    /// Used for cells with the default foreground color.
+    pub foreground: (u8, u8, u8),
+    /// Used for cells with the default background color.
+    pub background: (u8, u8, u8),
+    colors: [(u8, u8, u8); 256],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            foreground: (0xff, 0xff, 0xff),
+            background: (0x00, 0x00, 0x00),
+            colors: std::array::from_fn(|i| Color::ansi_256_rgb(i as u8)),
+        }
+    }
+}
+
+impl Palette {
+    pub fn get(&self, index: u8) -> (u8, u8, u8) {
+        self.colors[index as usize]
+    }
+
+    #[cfg_attr(
+        not(feature = "config"),
+        allow(dead_code, reason = "only the config file changes indexed colors")
+    )]
+    pub fn set(&mut self, index: u8, rgb: (u8, u8, u8)) {
+        self.colors[index as usize] = rgb;
+    }
+
+    /// RGB value of `color` used as a foreground color.
+    pub fn foreground(&self, color: Color) -> (u8, u8, u8) {
+        self.resolve(color, self.foreground)
+    }
+
+    /// RGB value of `color` used as a background color.
+    pub fn background(&self, color: Color) -> (u8, u8, u8) {
+        self.resolve(color, self.background)
+    }
+
+    fn resolve(&self, color: Color, default: (u8, u8, u8)) -> (u8, u8, u8) {
+        if color.is_default() {
+            default
+        } else if let Some(index) = color.index() {
+            self.get(index)
+        } else {
+            (color.r(), color.g(), color.b())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_palette() {
+        let palette = Palette::default();
+
+        assert_eq!(palette.get(1), (0xcd, 0x00, 0x00));
+        assert_eq!(palette.get(16), (0, 0, 0));
+        assert_eq!(palette.get(196), (255, 0, 0));
+        assert_eq!(palette.get(231), (255, 255, 255));
+        assert_eq!(palette.get(232), (8, 8, 8));
+        assert_eq!(palette.get(255), (238, 238, 238));
+    }
+
+    #[test]
+    fn resolve_colors() {
+        let mut palette = Palette::default();
+        palette.set(4, (1, 2, 3));
+
+        assert_eq!(palette.foreground(Color::DEFAULT), palette.foreground);
+        assert_eq!(palette.background(Color::DEFAULT), palette.background);
+        assert_eq!(palette.foreground(Color::indexed(4)), (1, 2, 3));
+        assert_eq!(palette.background(Color::rgb(9, 8, 7)), (9, 8, 7));
+    }
+}
@@ -0,0 +1,156 @@
+//! Minimal PNG encoder for screenshots.
+//!
+//! Pixels are stored without compression: a grid rendered as solid cells is small enough that
+//! this isn't worth a dependency.
+
+/// PNG file signature.
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+/// Largest amount of data in a stored deflate block.
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/// Encodes an RGBA image of `width` x `height` pixels with 8 bits per channel, rows stored top to
+/// bottom in `pixels`, as PNG.
+///
+/// # Panics
+///
+/// If `pixels` doesn't hold exactly `width * height` pixels.
+pub fn encode_rgba(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+    let stride = width as usize * 4;
+    assert_eq!(pixels.len(), stride * height as usize, "pixel buffer size");
+
+    // Each row starts with its filter type, 0 for none.
+    let mut raw = Vec::with_capacity(pixels.len() + height as usize);
+    for y in 0..height as usize {
+        raw.push(0);
+        raw.extend_from_slice(&pixels[y * stride..(y + 1) * stride]);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // Bit depth 8, color type 6 (RGBA), default compression and filtering, no interlacing.
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wraps `data` in a zlib stream of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let blocks = data.len().div_ceil(MAX_STORED_BLOCK).max(1);
+    let mut out = Vec::with_capacity(data.len() + blocks * 5 + 6);
+    // Deflate with a 32K window, no preset dictionary, checksum bits making it a multiple of 31.
+    out.extend_from_slice(&[0x78, 0x01]);
+
+    let mut chunks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+        let len = chunk.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % MOD;
+        b = (b + a) % MOD;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(adler32(b""), 1);
+    }
+
+    #[test]
+    fn encode_known_pixels() {
+        // Red and transparent green on the first row, blue and white on the second.
+        let pixels = [
+            [0xFF, 0x00, 0x00, 0xFF],
+            [0x00, 0xFF, 0x00, 0x00],
+            [0x00, 0x00, 0xFF, 0xFF],
+            [0xFF, 0xFF, 0xFF, 0xFF],
+        ]
+        .concat();
+        let png = encode_rgba(2, 2, &pixels);
+
+        #[rustfmt::skip]
+        let expected = [
+            0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A,
+            // IHDR
+            0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44, 0x52,
+            0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x02, 0x08, 0x06, 0x00, 0x00, 0x00,
+            0x72, 0xB6, 0x0D, 0x24,
+            // IDAT: zlib header, one stored block of two filtered rows, Adler-32
+            0x00, 0x00, 0x00, 0x1D, 0x49, 0x44, 0x41, 0x54,
+            0x78, 0x01, 0x01, 0x12, 0x00, 0xED, 0xFF,
+            0x00, 0xFF, 0x00, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+            0x3F, 0xD2, 0x08, 0xF8,
+            0x44, 0x62, 0x4B, 0x7C,
+            // IEND
+            0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82,
+        ];
+        assert_eq!(png, expected);
+    }
+
+    #[test]
+    fn large_images_span_blocks() {
+        let (width, height) = (200, 100);
+        let pixels = vec![0x80; width * height * 4];
+        let stream = zlib_stored(&pixels);
+
+        // Two blocks, only the second one final.
+        assert_eq!(&stream[2..7], &[0, 0xFF, 0xFF, 0x00, 0x00]);
+        let second = 7 + MAX_STORED_BLOCK;
+        let rest = (pixels.len() - MAX_STORED_BLOCK) as u16;
+        assert_eq!(stream[second], 1);
+        assert_eq!(&stream[second + 1..second + 3], &rest.to_le_bytes());
+        assert_eq!(stream.len(), 2 + 2 * 5 + pixels.len() + 4);
+    }
+}
@@ -1,33 +1,128 @@
 use std::{
+    ffi::{CString, NulError, OsString},
     fs::File,
     io::Read,
-    os::fd::AsFd,
+    os::{
+        fd::{AsFd, AsRawFd, OwnedFd},
+        unix::ffi::OsStringExt,
+    },
+    path::PathBuf,
     sync::mpsc::{channel, Receiver, Sender},
     thread::{self, JoinHandle},
+    time::Duration,
 };
 
 use nix::{
-    pty::{forkpty, ForkptyResult},
-    sys::select::{select, FdSet},
-    unistd::execvp,
+    errno::Errno,
+    libc,
+    pty::{forkpty, ForkptyResult, Winsize},
+    sys::{
+        select::{select, FdSet},
+        time::{TimeVal, TimeValLike},
+    },
+    unistd::{chdir, execvp, pipe},
 };
 use winit::window::Window;
 
+/// Sent from the reader thread to the application.
+#[derive(Debug, PartialEq)]
+pub enum PtyEvent {
+    /// Raw bytes written by the child, to be passed to
+    /// [`Terminal::feed`](crate::terminal::Terminal::feed). They may end in the middle of an
+    /// escape sequence or UTF-8 character.
+    Output(Vec<u8>),
+    /// No output arrived for the interval set with [`PtyBuilder::tick`].
+    Tick,
+}
+
+const DEFAULT_READ_BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct PtyBuilder {
+    tick: Option<Duration>,
+    buffer_size: usize,
+    cwd: Option<PathBuf>,
+    command: Option<Vec<String>>,
+}
+
+impl Default for PtyBuilder {
+    fn default() -> Self {
+        Self {
+            tick: None,
+            buffer_size: DEFAULT_READ_BUFFER_SIZE,
+            cwd: None,
+            command: None,
+        }
+    }
+}
+
+impl PtyBuilder {
+    /// Send [`PtyEvent::Tick`] whenever the shell stays quiet for `interval`, e.g. to drive a
+    /// blinking cursor.
+    pub fn tick(mut self, interval: Duration) -> Self {
+        self.tick = Some(interval);
+        self
+    }
+
+    /// Maximum number of bytes taken from the PTY per read. Larger buffers need fewer reads and
+    /// events when a program produces a lot of output.
+    pub fn buffer_size(mut self, size: usize) -> Self {
+        self.buffer_size = size.max(1);
+        self
+    }
+
+    /// Working directory of the child. Inherited from ttyrant by default.
+    pub fn cwd(mut self, cwd: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(cwd.into());
+        self
+    }
+
+    /// Program and arguments to run instead of the user's shell.
+    pub fn command(mut self, command: Vec<String>) -> Self {
+        self.command = Some(command);
+        self
+    }
+
+    /// Spawns the child on a new PTY.
+    pub fn build(self, window: std::sync::Weak<Window>) -> Pty {
+        Pty::spawn(window, self)
+    }
+}
+
 pub struct Pty {
     fd: File,
-    output_rx: Receiver<String>,
-    _output_thread: JoinHandle<()>,
+    output_rx: Receiver<PtyEvent>,
+    /// Write end of a pipe watched by the reader thread, closed to make it exit.
+    shutdown: Option<OwnedFd>,
+    output_thread: Option<JoinHandle<()>>,
 }
 
 impl Pty {
-    pub fn new(window: std::sync::Weak<Window>) -> Self {
+    pub fn builder() -> PtyBuilder {
+        PtyBuilder::default()
+    }
+
+    fn spawn(window: std::sync::Weak<Window>, options: PtyBuilder) -> Self {
+        // Allocate before forking, the child should only exec.
+        let args = child_args(options.command.as_deref(), std::env::var_os("SHELL"))
+            .expect("command contains a nul byte");
+        let exec_failed = format!("ttyrant: cannot run {}\n", args[0].to_string_lossy());
+        let chdir_failed = options
+            .cwd
+            .as_ref()
+            .map(|cwd| format!("ttyrant: cannot change directory to {}\n", cwd.display()));
+
         let (fd, _pid) = unsafe {
             let res = forkpty(None, None).expect("fork pty");
             match res {
                 ForkptyResult::Child => {
-                    let shell = c"sh";
-                    execvp(shell, &[shell]).expect("spawn shell");
-                    unreachable!();
+                    if let (Some(cwd), Some(message)) = (&options.cwd, &chdir_failed) {
+                        if chdir(cwd.as_path()).is_err() {
+                            child_exit(message);
+                        }
+                    }
+                    let _ = execvp(&args[0], &args);
+                    child_exit(&exec_failed);
                 }
                 ForkptyResult::Parent { master, child } => (master, child),
             }
@@ -36,35 +131,58 @@ impl Pty {
         let (tx, rx) = channel();
         let file: File = fd.into();
         let read_file = file.try_clone().expect("clone fd");
+        let (shutdown_rx, shutdown) = pipe().expect("create pipe");
 
-        let output_thread = thread::spawn(move || Self::read_output(read_file, tx, window));
+        let output_thread =
+            thread::spawn(move || Self::read_output(read_file, shutdown_rx, tx, window, options));
 
         Self {
             fd: file,
             output_rx: rx,
-            _output_thread: output_thread,
+            shutdown: Some(shutdown),
+            output_thread: Some(output_thread),
         }
     }
 
-    fn read_output(mut file: File, tx: Sender<String>, window: std::sync::Weak<Window>) {
-        let mut buf = [0u8; 1024];
+    /// Forwards output from `file` until it's closed or `shutdown` becomes readable, which
+    /// happens when its write end is closed.
+    fn read_output(
+        mut file: File,
+        shutdown: OwnedFd,
+        tx: Sender<PtyEvent>,
+        window: std::sync::Weak<Window>,
+        options: PtyBuilder,
+    ) {
+        let mut buf = vec![0u8; options.buffer_size];
         loop {
             let mut fd_set = FdSet::new();
             fd_set.insert(file.as_fd());
+            fd_set.insert(shutdown.as_fd());
+            // select may modify the timeout, so it is recreated on every iteration.
+            let mut timeout = options
+                .tick
+                .map(|tick| TimeVal::microseconds(tick.as_micros() as i64));
 
-            match select(None, &mut fd_set, None, None, None) {
+            match select(None, &mut fd_set, None, None, timeout.as_mut()) {
+                Ok(_) if fd_set.contains(shutdown.as_fd()) => break,
+                Ok(0) => {
+                    if tx.send(PtyEvent::Tick).is_err() {
+                        break;
+                    }
+                    if let Some(window) = window.upgrade() {
+                        window.request_redraw();
+                    }
+                }
                 Ok(_) => {
                     match file.read(&mut buf) {
                         Ok(0) => break, // EOF
                         Ok(n) => {
-                            if let Ok(s) = String::from_utf8(buf[..n].to_vec()) {
-                                if tx.send(s).is_err() {
-                                    break;
-                                }
-
-                                if let Some(window) = window.upgrade() {
-                                    window.request_redraw();
-                                }
+                            if tx.send(PtyEvent::Output(buf[..n].to_vec())).is_err() {
+                                break;
+                            }
+
+                            if let Some(window) = window.upgrade() {
+                                window.request_redraw();
                             }
                         }
                         Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
@@ -80,7 +198,220 @@ impl Pty {
         std::io::Write::write(&mut self.fd, data)
     }
 
-    pub fn try_read(&self) -> Option<String> {
+    pub fn try_recv(&self) -> Option<PtyEvent> {
         self.output_rx.try_recv().ok()
     }
+
+    /// Waits for the next event. Returns `None` once the child closed the PTY, usually because
+    /// it exited, and all its output was received.
+    pub fn recv(&self) -> Option<PtyEvent> {
+        self.output_rx.recv().ok()
+    }
+
+    /// Tells the child about the new size of the terminal, which it receives as `SIGWINCH`.
+    pub fn resize(&self, cols: usize, rows: usize) -> nix::Result<()> {
+        set_window_size(&self.fd, cols, rows)
+    }
+}
+
+impl Drop for Pty {
+    fn drop(&mut self) {
+        // Wakes the reader thread even while the child keeps the PTY open.
+        drop(self.shutdown.take());
+        if let Some(thread) = self.output_thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// TIOCSWINSZ on the PTY `fd`.
+fn set_window_size(fd: &impl AsRawFd, cols: usize, rows: usize) -> nix::Result<()> {
+    let size = Winsize {
+        ws_row: rows.min(u16::MAX as usize) as u16,
+        ws_col: cols.min(u16::MAX as usize) as u16,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let res = unsafe { libc::ioctl(fd.as_raw_fd(), libc::TIOCSWINSZ, &size) };
+    Errno::result(res).map(drop)
+}
+
+/// Reports `message` on stderr and exits the forked child with the status shells use for a
+/// command that couldn't be run. Unwinding or running exit handlers isn't safe after forking.
+fn child_exit(message: &str) -> ! {
+    unsafe {
+        libc::write(2, message.as_ptr().cast(), message.len());
+        libc::_exit(127)
+    }
+}
+
+/// Arguments to exec in the child: `command` if given, otherwise `shell` (the value of `$SHELL`)
+/// falling back to `sh`.
+fn child_args(
+    command: Option<&[String]>,
+    shell: Option<OsString>,
+) -> Result<Vec<CString>, NulError> {
+    match command {
+        Some(command) if !command.is_empty() => command
+            .iter()
+            .map(|arg| CString::new(arg.as_bytes()))
+            .collect(),
+        _ => {
+            let shell = shell
+                .filter(|shell| !shell.is_empty())
+                .unwrap_or_else(|| "sh".into());
+            Ok(vec![CString::new(shell.into_vec())?])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Write, sync::Weak};
+
+    struct Reader {
+        write: File,
+        shutdown: OwnedFd,
+        rx: Receiver<PtyEvent>,
+        handle: JoinHandle<()>,
+    }
+
+    fn spawn_reader(options: PtyBuilder) -> Reader {
+        let (read, write) = pipe().expect("create pipe");
+        let (shutdown_rx, shutdown) = pipe().expect("create pipe");
+        let (tx, rx) = channel();
+        let handle = thread::spawn(move || {
+            Pty::read_output(read.into(), shutdown_rx, tx, Weak::new(), options)
+        });
+        Reader {
+            write: write.into(),
+            shutdown,
+            rx,
+            handle,
+        }
+    }
+
+    #[test]
+    fn tick_when_idle() {
+        let reader = spawn_reader(Pty::builder().tick(Duration::from_millis(10)));
+
+        assert_eq!(
+            reader.rx.recv_timeout(Duration::from_secs(5)),
+            Ok(PtyEvent::Tick)
+        );
+
+        drop(reader.write);
+        drop(reader.rx);
+        reader.handle.join().unwrap();
+    }
+
+    #[test]
+    fn output_without_tick() {
+        let mut reader = spawn_reader(Pty::builder());
+        reader.write.write_all(b"hi").unwrap();
+
+        assert_eq!(
+            reader.rx.recv_timeout(Duration::from_secs(5)),
+            Ok(PtyEvent::Output(b"hi".to_vec()))
+        );
+
+        drop(reader.write);
+        reader.handle.join().unwrap();
+        assert!(reader.rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn shutdown_stops_reader() {
+        let reader = spawn_reader(Pty::builder());
+
+        drop(reader.shutdown);
+        reader.handle.join().unwrap();
+        // The output side is still open.
+        drop(reader.write);
+    }
+
+    #[test]
+    fn drop_joins_reader() {
+        let pty = Pty::builder()
+            .command(vec!["sleep".into(), "10".into()])
+            .build(Weak::new());
+
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            drop(pty);
+            tx.send(()).unwrap();
+        });
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(()));
+    }
+
+    /// Number of output events for a burst of 8 KiB already waiting in the pipe.
+    fn reads_for_burst(buffer_size: usize) -> usize {
+        let (read, write) = pipe().expect("create pipe");
+        let mut write = File::from(write);
+        write.write_all(&[b'x'; 8192]).unwrap();
+        drop(write);
+
+        let (tx, rx) = channel();
+        let options = Pty::builder().buffer_size(buffer_size);
+        let (shutdown_rx, _shutdown) = pipe().expect("create pipe");
+        Pty::read_output(read.into(), shutdown_rx, tx, Weak::new(), options);
+
+        let events: Vec<_> = rx.try_iter().collect();
+        let bytes: usize = events
+            .iter()
+            .map(|event| match event {
+                PtyEvent::Output(output) => output.len(),
+                PtyEvent::Tick => 0,
+            })
+            .sum();
+        assert_eq!(bytes, 8192);
+        events.len()
+    }
+
+    #[test]
+    fn larger_buffer_needs_fewer_reads() {
+        assert_eq!(reads_for_burst(1024), 8);
+        assert_eq!(reads_for_burst(DEFAULT_READ_BUFFER_SIZE), 1);
+    }
+
+    #[test]
+    fn child_args_default_to_shell() {
+        assert_eq!(
+            child_args(None, Some("/bin/zsh".into())),
+            Ok(vec![c"/bin/zsh".to_owned()])
+        );
+        assert_eq!(child_args(None, None), Ok(vec![c"sh".to_owned()]));
+        assert_eq!(
+            child_args(Some(&[]), Some("".into())),
+            Ok(vec![c"sh".to_owned()])
+        );
+    }
+
+    #[test]
+    fn child_args_from_command() {
+        let command = ["htop".to_string(), "-d".to_string(), "10".to_string()];
+
+        assert_eq!(
+            child_args(Some(&command), Some("/bin/zsh".into())),
+            Ok(vec![c"htop".to_owned(), c"-d".to_owned(), c"10".to_owned()])
+        );
+        assert!(child_args(Some(&["a\0b".to_string()]), None).is_err());
+    }
+
+    #[test]
+    fn window_size() {
+        let pty = nix::pty::openpty(None, None).expect("open pty");
+        set_window_size(&pty.master, 120, 40).unwrap();
+
+        let mut size = Winsize {
+            ws_row: 0,
+            ws_col: 0,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let res = unsafe { libc::ioctl(pty.slave.as_raw_fd(), libc::TIOCGWINSZ, &mut size) };
+        Errno::result(res).unwrap();
+        assert_eq!((size.ws_col, size.ws_row), (120, 40));
+    }
 }
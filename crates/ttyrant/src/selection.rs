@@ -0,0 +1,207 @@
+use unicode_width::UnicodeWidthChar;
+
+use crate::cell::Buffer;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum SelectionKind {
+    /// Runs from `start` to `end` in reading order, like selecting text in an editor.
+    #[default]
+    Linear,
+    /// Selects the rectangle spanned by `start` and `end`.
+    #[allow(
+        dead_code,
+        reason = "the window has no mouse selection to start one from yet"
+    )]
+    Block,
+}
+
+/// A selected grid region. Points are `(column, row)` and both ends are inclusive.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Selection {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub kind: SelectionKind,
+}
+
+impl Selection {
+    pub fn new(start: (usize, usize), end: (usize, usize), kind: SelectionKind) -> Self {
+        Self { start, end, kind }
+    }
+
+    /// Start and end ordered so that start comes first in reading order.
+    fn ordered(&self) -> ((usize, usize), (usize, usize)) {
+        let (start, end) = (self.start, self.end);
+        if (start.1, start.0) <= (end.1, end.0) {
+            (start, end)
+        } else {
+            (end, start)
+        }
+    }
+
+    /// Extracts the selected text. Trailing blanks are trimmed from each row and rows joined by
+    /// autowrap are not separated by a line break.
+    pub fn text(&self, buffer: &Buffer) -> String {
+        let mut text = String::new();
+        match self.kind {
+            SelectionKind::Linear => {
+                let (start, end) = self.ordered();
+                for y in start.1..=end.1 {
+                    let from = if y == start.1 { start.0 } else { 0 };
+                    let to = if y == end.1 { end.0 + 1 } else { usize::MAX };
+                    let continues =
+                        y < end.1 && buffer.line(y + 1).is_some_and(|line| line.is_wrapped());
+
+                    let row = row_text(buffer, y, from, to);
+                    if continues {
+                        text.push_str(&row);
+                    } else {
+                        text.push_str(row.trim_end_matches(' '));
+                        if y < end.1 {
+                            text.push('\n');
+                        }
+                    }
+                }
+            }
+            SelectionKind::Block => {
+                let (left, right) = (self.start.0.min(self.end.0), self.start.0.max(self.end.0));
+                let (top, bottom) = (self.start.1.min(self.end.1), self.start.1.max(self.end.1));
+                for y in top..=bottom {
+                    text.push_str(row_text(buffer, y, left, right + 1).trim_end_matches(' '));
+                    if y < bottom {
+                        text.push('\n');
+                    }
+                }
+            }
+        }
+        text
+    }
+}
+
+/// Characters that are part of a word in addition to alphanumerics, so that paths and URLs are
+/// selected as a whole.
+pub const DEFAULT_WORD_CHARS: &str = "-_./~";
+
+/// Finds the word around `point` for double-click selection, following rows joined by autowrap.
+///
+/// Returns `None` if `point` is outside the grid or not on a word character.
+pub fn word_at(
+    buffer: &Buffer,
+    point: (usize, usize),
+    word_chars: &str,
+) -> Option<((usize, usize), (usize, usize))> {
+    let is_word = |point: (usize, usize)| {
+        char_at(buffer, point).is_some_and(|ch| ch.is_alphanumeric() || word_chars.contains(ch))
+    };
+    if !is_word(point) {
+        return None;
+    }
+
+    let mut start = point;
+    while let Some(previous) = step_back(buffer, start).filter(|&point| is_word(point)) {
+        start = previous;
+    }
+    let mut end = point;
+    while let Some(next) = step_forward(buffer, end).filter(|&point| is_word(point)) {
+        end = next;
+    }
+
+    // Snap to the lead cell of a wide character and include its placeholder.
+    if char_at(buffer, start) != buffer.cell(start.0, start.1).map(|cell| cell.ch) {
+        start.0 -= 1;
+    }
+    if let Some(ch) = buffer.cell(end.0, end.1).map(|cell| cell.ch) {
+        end.0 = (end.0 + ch.width().unwrap_or(1).max(1) - 1).min(buffer.width() - 1);
+    }
+
+    Some((start, end))
+}
+
+/// Finds the logical line containing `row` for triple-click selection: all rows joined to it by
+/// autowrap, from the first column of the first row to the last column of the last row.
+///
+/// The selection spans the whole width, [`Selection::text`] drops the trailing blanks.
+pub fn line_at(buffer: &Buffer, row: usize) -> ((usize, usize), (usize, usize)) {
+    let row = row.min(buffer.height() - 1);
+    let is_wrapped = |y: usize| buffer.line(y).is_some_and(|line| line.is_wrapped());
+
+    let mut top = row;
+    while top > 0 && is_wrapped(top) {
+        top -= 1;
+    }
+    let mut bottom = row;
+    while is_wrapped(bottom + 1) {
+        bottom += 1;
+    }
+
+    ((0, top), (buffer.width() - 1, bottom))
+}
+
+/// Character shown at `point`, resolving wide character placeholders to their lead character.
+fn char_at(buffer: &Buffer, (x, y): (usize, usize)) -> Option<char> {
+    let cell = buffer.cell(x, y)?;
+    if x > 0 {
+        let previous = buffer.cell(x - 1, y)?.ch;
+        if previous.width() == Some(2) {
+            return Some(previous);
+        }
+    }
+    Some(cell.ch)
+}
+
+fn step_back(buffer: &Buffer, (x, y): (usize, usize)) -> Option<(usize, usize)> {
+    if x > 0 {
+        Some((x - 1, y))
+    } else if y > 0 && buffer.line(y)?.is_wrapped() {
+        Some((buffer.width() - 1, y - 1))
+    } else {
+        None
+    }
+}
+
+fn step_forward(buffer: &Buffer, (x, y): (usize, usize)) -> Option<(usize, usize)> {
+    if x + 1 < buffer.width() {
+        Some((x + 1, y))
+    } else if buffer.line(y + 1)?.is_wrapped() {
+        Some((0, y + 1))
+    } else {
+        None
+    }
+}
+
+/// Text of the cells in `from..to` on row `y`, skipping the placeholders trailing wide characters.
+fn row_text(buffer: &Buffer, y: usize, from: usize, to: usize) -> String {
+    let Some(line) = buffer.line(y) else {
+        return String::new();
+    };
+
+    let mut text = String::new();
+    let mut x = from;
+    let to = to.min(buffer.width());
+    while x < to {
+        let cell = line.get(x as u16);
+        buffer.push_text(&mut text, cell);
+        x += cell.ch.width().unwrap_or(1).max(1);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cell::CellAttributes;
+
+    #[test]
+    fn word_at_wide_chars() {
+        let mut buffer = Buffer::new(10, 1);
+        buffer.write_str(0, 0, "x 漢字 y", CellAttributes::default());
+
+        assert_eq!(
+            word_at(&buffer, (3, 0), DEFAULT_WORD_CHARS),
+            Some(((2, 0), (5, 0)))
+        );
+        assert_eq!(
+            word_at(&buffer, (5, 0), DEFAULT_WORD_CHARS),
+            Some(((2, 0), (5, 0)))
+        );
+    }
+}
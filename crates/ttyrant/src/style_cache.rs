@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+
+use glyphon::{Attrs, AttrsList, Family, Style, Weight};
+use unicode_width::UnicodeWidthChar;
+
+use crate::{
+    cell::{Buffer, Cell, CellAttributes, Color},
+    palette::Palette,
+};
+
+/// Attributes that change how the text of a cell is drawn.
+const DRAWN_ATTRIBUTES: [CellAttributes; 5] = [
+    CellAttributes::BOLD,
+    CellAttributes::FAINT,
+    CellAttributes::ITALIC,
+    CellAttributes::INVERSE,
+    CellAttributes::HIDDEN,
+];
+
+/// The part of a cell its text attributes depend on. The character doesn't, so cells differing
+/// only in it, or in attributes that aren't drawn such as protection, share an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct CellStyle {
+    fg: Color,
+    bg: Color,
+    attrs: CellAttributes,
+}
+
+impl CellStyle {
+    fn of(cell: &Cell) -> Self {
+        let mut attrs = CellAttributes::default();
+        for attr in DRAWN_ATTRIBUTES {
+            attrs.set(attr, cell.attrs.contains(attr));
+        }
+        Self {
+            fg: cell.fg,
+            bg: cell.bg,
+            attrs,
+        }
+    }
+}
+
+/// Text attributes of cells resolved with a [`Palette`], computed once per distinct style.
+#[derive(Debug, Default)]
+pub struct StyleCache {
+    palette: Palette,
+    entries: HashMap<CellStyle, Attrs<'static>>,
+    hits: usize,
+    misses: usize,
+}
+
+impl StyleCache {
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Replaces the palette, dropping the cached attributes if it changed. Returns whether it
+    /// did, in which case all text has to be styled again.
+    #[cfg_attr(
+        not(feature = "config"),
+        allow(dead_code, reason = "only the config file changes the palette")
+    )]
+    pub fn set_palette(&mut self, palette: Palette) -> bool {
+        if palette == self.palette {
+            return false;
+        }
+        self.palette = palette;
+        self.entries.clear();
+        true
+    }
+
+    /// Lookups answered from the cache.
+    #[allow(dead_code, reason = "cache accounting, only checked by tests so far")]
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Lookups that had to compute the attributes.
+    #[allow(dead_code, reason = "cache accounting, only checked by tests so far")]
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    /// Attributes the text of `cell` is drawn with.
+    pub fn attrs(&mut self, cell: &Cell) -> Attrs<'static> {
+        let style = CellStyle::of(cell);
+        if let Some(attrs) = self.entries.get(&style) {
+            self.hits += 1;
+            return *attrs;
+        }
+        self.misses += 1;
+        let attrs = self.compute(style);
+        self.entries.insert(style, attrs);
+        attrs
+    }
+
+    /// Attributes of the text of row `y` of `buffer`, given as `text` as written by its
+    /// [`Display`](std::fmt::Display) implementation. Characters past the cells written there,
+    /// such as padding for the cursor, take the default attributes.
+    pub fn row_attrs(&mut self, buffer: &Buffer, y: usize, text: &str) -> AttrsList {
+        let default = self.attrs(&Cell::default());
+        let mut list = AttrsList::new(default);
+        let mut chars = text.char_indices().peekable();
+        let mut cluster = String::new();
+        let mut x = 0;
+
+        while let (Some(&(start, _)), Some(cell)) = (chars.peek(), buffer.cell(x, y)) {
+            cluster.clear();
+            buffer.push_text(&mut cluster, cell);
+            let end = chars
+                .by_ref()
+                .take(cluster.chars().count())
+                .last()
+                .map_or(start, |(i, ch)| i + ch.len_utf8());
+
+            let attrs = self.attrs(&cell);
+            if attrs != default {
+                list.add_span(start..end, attrs);
+            }
+            // Skip the placeholder trailing a wide character, like the text does.
+            x += cell.ch.width().unwrap_or(1).max(1);
+        }
+        list
+    }
+
+    fn compute(&self, style: CellStyle) -> Attrs<'static> {
+        let mut fg = self.palette.foreground(style.fg);
+        let mut bg = self.palette.background(style.bg);
+        if style.attrs.contains(CellAttributes::INVERSE) {
+            (fg, bg) = (bg, fg);
+        }
+        if style.attrs.contains(CellAttributes::FAINT) {
+            fg = mix(fg, bg);
+        }
+        let alpha = if style.attrs.contains(CellAttributes::HIDDEN) {
+            0
+        } else {
+            0xff
+        };
+
+        let (r, g, b) = fg;
+        Attrs::new()
+            .family(Family::Monospace)
+            .color(glyphon::Color::rgba(r, g, b, alpha))
+            .weight(if style.attrs.contains(CellAttributes::BOLD) {
+                Weight::BOLD
+            } else {
+                Weight::NORMAL
+            })
+            .style(if style.attrs.contains(CellAttributes::ITALIC) {
+                Style::Italic
+            } else {
+                Style::Normal
+            })
+    }
+}
+
+/// Halfway between `a` and `b`.
+fn mix(a: (u8, u8, u8), b: (u8, u8, u8)) -> (u8, u8, u8) {
+    let mid = |a: u8, b: u8| ((a as u16 + b as u16) / 2) as u8;
+    (mid(a.0, b.0), mid(a.1, b.1), mid(a.2, b.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_styles_hit_the_cache() {
+        let mut cache = StyleCache::default();
+        let plain = Cell::default();
+        let mut red = Cell::new('a', CellAttributes::BOLD);
+        red.fg = Color::indexed(1);
+
+        cache.attrs(&plain);
+        cache.attrs(&red);
+        assert_eq!((cache.hits(), cache.misses()), (0, 2));
+
+        // Only the style counts, not the character or protection.
+        let mut other = red;
+        other.ch = 'b';
+        other.attrs.set(CellAttributes::PROTECTED, true);
+        assert_eq!(cache.attrs(&other), cache.attrs(&red));
+        cache.attrs(&Cell::new('c', CellAttributes::default()));
+        assert_eq!((cache.hits(), cache.misses()), (3, 2));
+
+        let attrs = cache.attrs(&red);
+        assert_eq!(attrs.weight, Weight::BOLD);
+        assert_eq!(attrs.color_opt, Some(glyphon::Color::rgb(0xcd, 0, 0)));
+    }
+
+    #[test]
+    fn palette_change_invalidates() {
+        let mut cache = StyleCache::default();
+        let cell = Cell::default();
+        cache.attrs(&cell);
+
+        assert!(!cache.set_palette(Palette::default()));
+        cache.attrs(&cell);
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        let mut palette = Palette::default();
+        palette.foreground = (1, 2, 3);
+        assert!(cache.set_palette(palette));
+        let attrs = cache.attrs(&cell);
+        assert_eq!((cache.hits(), cache.misses()), (1, 2));
+        assert_eq!(attrs.color_opt, Some(glyphon::Color::rgb(1, 2, 3)));
+    }
+
+    #[test]
+    fn inverse_faint_and_hidden_colors() {
+        let mut cache = StyleCache::default();
+        let color = |cache: &mut StyleCache, attrs| cache.attrs(&Cell::new('a', attrs)).color_opt;
+
+        assert_eq!(
+            color(&mut cache, CellAttributes::INVERSE),
+            Some(glyphon::Color::rgb(0, 0, 0))
+        );
+        assert_eq!(
+            color(&mut cache, CellAttributes::FAINT),
+            Some(glyphon::Color::rgb(0x7f, 0x7f, 0x7f))
+        );
+        assert_eq!(
+            color(&mut cache, CellAttributes::HIDDEN),
+            Some(glyphon::Color::rgba(0xff, 0xff, 0xff, 0))
+        );
+    }
+
+    #[test]
+    fn row_spans_follow_cells() {
+        let mut cache = StyleCache::default();
+        let red = |attrs| {
+            let mut cell = Cell::new('a', attrs);
+            cell.fg = Color::indexed(1);
+            cell
+        };
+        let (plain, red, bold) = (
+            Cell::default(),
+            red(CellAttributes::default()),
+            red(CellAttributes::BOLD),
+        );
+        let mut buffer = Buffer::new(10, 1);
+        for (ch, style) in [
+            ('a', plain),
+            ('中', red),
+            ('b', red),
+            ('e', bold),
+            ('\u{301}', bold),
+            ('c', plain),
+        ] {
+            let mut cell = style;
+            cell.ch = ch;
+            buffer.print_cell(cell);
+        }
+        let text = format!("{buffer} ");
+
+        let list = cache.row_attrs(&buffer, 0, &text);
+        assert_eq!(list.get_span(0), list.defaults());
+        assert_eq!(list.get_span(1), cache.attrs(&red));
+        assert_eq!(list.get_span(4), cache.attrs(&red));
+        // Both characters of the cluster.
+        assert_eq!(list.get_span(5), cache.attrs(&bold));
+        assert_eq!(list.get_span(6), cache.attrs(&bold));
+        assert_eq!(list.get_span(8), list.defaults());
+        assert_eq!(list.get_span(9), list.defaults());
+    }
+}
@@ -0,0 +1,71 @@
+/// Terminal type reported as the `TN` and `name` capabilities. The sequences ttyrant understands
+/// and sends are xterm's.
+pub const TERMINAL_NAME: &str = "xterm-256color";
+
+/// Value of the terminfo or termcap capability `name` reported to XTGETTCAP, `None` if it isn't
+/// known. Key capabilities give the bytes the key sends.
+pub fn capability(name: &str) -> Option<&'static [u8]> {
+    let value: &[u8] = match name {
+        "TN" | "name" => TERMINAL_NAME.as_bytes(),
+        "Co" | "colors" => b"256",
+        "kb" | "kbs" => b"\x7f",
+        // Cursor keys in application mode, which programs using terminfo switch to.
+        "ku" | "kcuu1" => b"\x1BOA",
+        "kd" | "kcud1" => b"\x1BOB",
+        "kr" | "kcuf1" => b"\x1BOC",
+        "kl" | "kcub1" => b"\x1BOD",
+        "k1" | "kf1" => b"\x1BOP",
+        "k2" | "kf2" => b"\x1BOQ",
+        "k3" | "kf3" => b"\x1BOR",
+        "k4" | "kf4" => b"\x1BOS",
+        "k5" | "kf5" => b"\x1B[15~",
+        "k6" | "kf6" => b"\x1B[17~",
+        "k7" | "kf7" => b"\x1B[18~",
+        "k8" | "kf8" => b"\x1B[19~",
+        "k9" | "kf9" => b"\x1B[20~",
+        "k;" | "kf10" => b"\x1B[21~",
+        "F1" | "kf11" => b"\x1B[23~",
+        "F2" | "kf12" => b"\x1B[24~",
+        _ => return None,
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{keymap::encode_key, terminal::Modes};
+    use winit::keyboard::{Key, ModifiersState, NamedKey};
+
+    #[test]
+    fn function_keys_match_input() {
+        let keys = [
+            NamedKey::F1,
+            NamedKey::F2,
+            NamedKey::F3,
+            NamedKey::F4,
+            NamedKey::F5,
+            NamedKey::F6,
+            NamedKey::F7,
+            NamedKey::F8,
+            NamedKey::F9,
+            NamedKey::F10,
+            NamedKey::F11,
+            NamedKey::F12,
+        ];
+        for (i, key) in keys.into_iter().enumerate() {
+            assert_eq!(
+                capability(&format!("kf{}", i + 1)),
+                encode_key(&Key::Named(key), ModifiersState::empty(), &Modes::default()).as_deref()
+            );
+        }
+    }
+
+    #[test]
+    fn termcap_and_terminfo_names() {
+        assert_eq!(capability("Co"), capability("colors"));
+        assert_eq!(capability("k;"), capability("kf10"));
+        assert_eq!(capability("TN"), Some(&b"xterm-256color"[..]));
+        assert_eq!(capability("smkx"), None);
+    }
+}
@@ -0,0 +1,1612 @@
+use std::{
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+use ansi::{
+    ansi::{AnsiCommand, AnsiParser, BlinkInterval, PrivateMode, Sgr, UnderlineStyle},
+    dcs::encode_hex,
+};
+
+use crate::{
+    cell::{Buffer, Cell, CellAttributes, Cursor, ResizeMode},
+    charset::Charset,
+    selection::{self, Selection, SelectionKind, DEFAULT_WORD_CHARS},
+    termcap, url,
+};
+
+/// Number of titles kept by XTWINOPS 22, older ones are dropped. Same as xterm.
+const TITLE_STACK_LIMIT: usize = 10;
+
+/// Modes toggled by the running program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Modes {
+    /// LNM: line feeds also return the cursor to the first column, and Enter sends CR LF.
+    pub newline: bool,
+    /// IRM: printed characters are inserted, shifting the rest of the line right.
+    pub insert: bool,
+    /// DECCKM: cursor keys send application sequences.
+    pub cursor_keys: bool,
+    /// DECKPAM and DECKPNM: the keypad sends application sequences.
+    pub keypad_application: bool,
+    /// DECOM: cursor addressing is relative to the scroll region.
+    pub origin: bool,
+    /// DECAWM: printing past the last column wraps onto the next line.
+    pub autowrap: bool,
+    /// DECTCEM: the cursor is drawn.
+    pub show_cursor: bool,
+    /// Mode 1000: mouse buttons are reported to the child.
+    pub mouse_tracking: bool,
+    /// Mode 1006: mouse reports use the SGR encoding.
+    pub sgr_mouse: bool,
+    /// Mode 1007: the mouse wheel sends cursor keys, see [`Terminal::wheel`].
+    pub alternate_scroll: bool,
+    /// Modes 1047 and 1049: the alternate screen is shown instead of the normal one.
+    pub alternate_screen: bool,
+    /// Mode 2026: the child is writing a frame and the screen shouldn't be drawn until it resets
+    /// the mode.
+    pub synchronized_output: bool,
+}
+
+impl Default for Modes {
+    fn default() -> Self {
+        Self {
+            newline: false,
+            insert: false,
+            cursor_keys: false,
+            keypad_application: false,
+            origin: false,
+            autowrap: true,
+            show_cursor: true,
+            mouse_tracking: false,
+            sgr_mouse: false,
+            alternate_scroll: false,
+            alternate_screen: false,
+            synchronized_output: false,
+        }
+    }
+}
+
+/// Cursor state saved by DECSC or mode 1048, restored by DECRC or resetting the mode.
+#[derive(Debug, Clone, Copy)]
+struct SavedCursor {
+    cursor: Cursor,
+    pen: Cell,
+    charsets: [Charset; 4],
+    active_charset: usize,
+    origin: bool,
+}
+
+/// Terminal model: the grid and the parser feeding it, independent of any window or PTY.
+///
+/// Output of the child is passed to [`Terminal::feed`] in chunks as it arrives, so the model can
+/// just as well be driven by a test or another byte source.
+pub struct Terminal {
+    buffer: Buffer,
+    /// Cursor saved on the screen shown, each screen has its own.
+    saved_cursor: Option<SavedCursor>,
+    /// The normal screen and its saved cursor while the alternate screen is shown in its place.
+    normal_screen: Option<(Buffer, Option<SavedCursor>)>,
+    parser: AnsiParser,
+    modes: Modes,
+    /// Attributes and colors given to printed characters, updated by SGR.
+    pen: Cell,
+    /// Character sets designated into G0 to G3.
+    charsets: [Charset; 4],
+    /// G-set printed characters are taken from, G0 or G1 as selected by SI and SO.
+    active_charset: usize,
+    /// G-set selected by SS2 or SS3 for the next printed character only.
+    single_shift: Option<usize>,
+    selection: Option<Selection>,
+    /// Non-alphanumeric characters treated as part of a word by [`Terminal::word_at`].
+    word_chars: String,
+    /// Reused between `feed` calls so parsing doesn't allocate.
+    commands: Vec<AnsiCommand>,
+    /// Honor XON/XOFF from the child. Off by default like in most terminals, as nothing relies on
+    /// it anymore and a stray XOFF would otherwise swallow all input.
+    flow_control: bool,
+    /// Set by XOFF while `flow_control` is enabled: input should be held back until XON.
+    transmit_paused: bool,
+    /// Replies to queries from the child, waiting to be written back to it.
+    responses: Vec<u8>,
+    /// Width and height of the text area in pixels, reported by XTWINOPS.
+    pixel_size: (u32, u32),
+    /// Compose combining marks with the character before them, see
+    /// [`Terminal::set_normalization`].
+    #[cfg(feature = "nfc")]
+    normalization: bool,
+    /// Set by BEL until taken with [`Terminal::take_bell`].
+    bell: bool,
+    /// Window title set by the child, empty until it sets one.
+    title: String,
+    /// Titles saved with XTWINOPS 22, the last one on top.
+    title_stack: Vec<String>,
+    /// Last directory reported by the shell with OSC 7.
+    working_directory: Option<PathBuf>,
+    /// Turn characters struck over each other with backspace into bold and underlined text, see
+    /// [`Terminal::set_overstrike`].
+    overstrike: bool,
+    /// Whether the last command was a backspace, so that a printed character strikes over the
+    /// cell under the cursor.
+    after_backspace: bool,
+    /// How existing content is carried over when the grid is resized.
+    resize_mode: ResizeMode,
+    /// Debugging aid: also run typed input through the parser. Real terminals rely on the
+    /// child (or the tty line discipline) to echo input, so this is off by default.
+    local_echo: bool,
+}
+
+impl Default for Terminal {
+    fn default() -> Self {
+        Self::new(80, 24)
+    }
+}
+
+impl Terminal {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        Self {
+            buffer: Buffer::new(cols, rows),
+            saved_cursor: None,
+            normal_screen: None,
+            parser: AnsiParser::new(),
+            modes: Modes::default(),
+            pen: Cell::default(),
+            charsets: [Charset::Ascii; 4],
+            active_charset: 0,
+            single_shift: None,
+            selection: None,
+            word_chars: DEFAULT_WORD_CHARS.to_string(),
+            commands: Vec::new(),
+            flow_control: false,
+            transmit_paused: false,
+            responses: Vec::new(),
+            pixel_size: (0, 0),
+            #[cfg(feature = "nfc")]
+            normalization: false,
+            bell: false,
+            title: String::new(),
+            title_stack: Vec::new(),
+            working_directory: None,
+            overstrike: false,
+            after_backspace: false,
+            resize_mode: ResizeMode::default(),
+            local_echo: false,
+        }
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    pub fn modes(&self) -> &Modes {
+        &self.modes
+    }
+
+    /// Window title requested by the child, empty if it didn't set one.
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Working directory of the shell as last reported with OSC 7, `None` if it never did.
+    #[allow(dead_code, reason = "no new shell is started in it yet")]
+    pub fn working_directory(&self) -> Option<&Path> {
+        self.working_directory.as_deref()
+    }
+
+    pub fn set_local_echo(&mut self, enabled: bool) {
+        self.local_echo = enabled;
+    }
+
+    /// Renders overstrike as used by `man` and other formatters for printers: a character struck
+    /// over itself (`a\ba`) becomes bold, one struck over `_` or the other way round (`_\ba`)
+    /// becomes underlined. Off by default, when the last character simply replaces the first.
+    pub fn set_overstrike(&mut self, enabled: bool) {
+        self.overstrike = enabled;
+    }
+
+    /// Whether [`Terminal::resize`] reflows wrapped lines, the default, or clips rows.
+    pub fn set_resize_mode(&mut self, mode: ResizeMode) {
+        self.resize_mode = mode;
+    }
+
+    /// See [`Buffer::set_scrollback_limit`]. Only applies to the normal screen, the alternate
+    /// one never has scrollback.
+    #[cfg_attr(
+        not(feature = "config"),
+        allow(dead_code, reason = "only set from a config file")
+    )]
+    pub fn set_scrollback_limit(&mut self, limit: usize) {
+        match &mut self.normal_screen {
+            Some((buffer, _)) => buffer.set_scrollback_limit(limit),
+            None => self.buffer.set_scrollback_limit(limit),
+        }
+    }
+
+    pub fn set_flow_control(&mut self, enabled: bool) {
+        self.flow_control = enabled;
+        if !enabled {
+            self.transmit_paused = false;
+        }
+    }
+
+    /// Composes combining marks with the character printed before them into a precomposed one
+    /// where Unicode defines it, as in NFC. Decomposed text, e.g. `e` followed by U+0301, then
+    /// takes up a single cell. Off by default so the output is kept as sent.
+    #[cfg(feature = "nfc")]
+    pub fn set_normalization(&mut self, enabled: bool) {
+        self.normalization = enabled;
+    }
+
+    /// Whether the child sent XOFF and input should be held back until it sends XON. Always
+    /// `false` unless flow control is enabled.
+    pub fn transmit_paused(&self) -> bool {
+        self.transmit_paused
+    }
+
+    /// Resizes the grid to `cols` x `rows` (at least 1x1) according to the
+    /// [resize mode](Terminal::set_resize_mode) and resets the scroll region. The cursor is kept
+    /// on the grid.
+    ///
+    /// Returns the size actually applied, which is what the PTY should be told about.
+    pub fn resize(&mut self, cols: usize, rows: usize) -> (usize, usize) {
+        let (cols, rows) = (cols.max(1), rows.max(1));
+        self.buffer.resize(cols, rows, self.resize_mode);
+        if let Some((buffer, _)) = &mut self.normal_screen {
+            buffer.resize(cols, rows, self.resize_mode);
+        }
+        // Selected points may no longer exist or refer to other content.
+        self.selection = None;
+        (cols, rows)
+    }
+
+    /// Whether the child rang the bell since the last call.
+    pub fn take_bell(&mut self) -> bool {
+        std::mem::take(&mut self.bell)
+    }
+
+    /// Rows changed since the last call, see [`Buffer::take_damage`].
+    pub fn take_damage(&mut self) -> Option<(usize, usize)> {
+        self.buffer.take_damage()
+    }
+
+    /// Sets the size in pixels of the area the grid is drawn in, reported to programs asking for
+    /// it with XTWINOPS 14.
+    pub fn set_pixel_size(&mut self, width: u32, height: u32) {
+        self.pixel_size = (width, height);
+    }
+
+    /// Takes the bytes the terminal wants to send to the child in response to queries such as
+    /// DSR. The caller writes them to the PTY, keeping the model independent of it.
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.responses)
+    }
+
+    /// Parses output of the child process and applies it to the grid. Sequences may be split
+    /// across calls.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        let mut commands = std::mem::take(&mut self.commands);
+        self.parser.parse(bytes, |command| commands.push(command));
+        for command in commands.drain(..) {
+            self.perform(command);
+        }
+        self.commands = commands;
+    }
+
+    /// Called with bytes typed by the user before they are sent to the child.
+    pub fn input(&mut self, bytes: &[u8]) {
+        if self.local_echo {
+            self.feed(bytes);
+        }
+    }
+
+    /// Bytes to send for the mouse wheel turned by `lines` rows, positive towards the top, or
+    /// `None` if the wheel isn't passed on to the child. On the alternate screen, with alternate
+    /// scroll mode (1007) set and mouse tracking off, every row is sent as an up or down cursor
+    /// key.
+    pub fn wheel(&self, lines: i32) -> Option<Vec<u8>> {
+        if !self.modes.alternate_scroll
+            || !self.modes.alternate_screen
+            || self.modes.mouse_tracking
+            || lines == 0
+        {
+            return None;
+        }
+
+        let key: &[u8] = match (lines > 0, self.modes.cursor_keys) {
+            (true, false) => b"\x1B[A",
+            (false, false) => b"\x1B[B",
+            (true, true) => b"\x1BOA",
+            (false, true) => b"\x1BOB",
+        };
+        Some(key.repeat(lines.unsigned_abs() as usize))
+    }
+
+    fn perform(&mut self, command: AnsiCommand) {
+        use AnsiCommand::*;
+
+        let after_backspace = std::mem::take(&mut self.after_backspace);
+        match command {
+            Print(ch) => {
+                let charset =
+                    self.charsets[self.single_shift.take().unwrap_or(self.active_charset)];
+                let ch = charset.map(ch);
+                #[cfg(feature = "nfc")]
+                if self.normalization && self.compose(ch) {
+                    return;
+                }
+                let mut cell = self.pen;
+                cell.ch = ch;
+                if after_backspace {
+                    self.overstrike(&mut cell);
+                }
+                self.buffer.print_cell(cell);
+            }
+            Bell => self.bell = true,
+            Backspace => {
+                self.buffer.backspace();
+                self.snap_cursor(true);
+                self.after_backspace = self.overstrike;
+            }
+            HorizontalTab => self.buffer.write_tab(self.pen.attrs),
+            LineFeed => {
+                self.buffer.linefeed();
+                if self.modes.newline {
+                    self.buffer.carriage_return();
+                }
+            }
+            CarriageReturn => self.buffer.carriage_return(),
+            Index => self.buffer.linefeed(),
+            ReverseIndex => self.buffer.reverse_index(),
+            NextLine => {
+                self.buffer.linefeed();
+                self.buffer.carriage_return();
+            }
+            SaveCursor => self.save_cursor(),
+            RestoreCursor => self.restore_cursor(),
+            KeypadApplicationMode => self.modes.keypad_application = true,
+            KeypadNumericMode => self.modes.keypad_application = false,
+            TransmitOff => self.transmit_paused = self.flow_control,
+            TransmitOn => self.transmit_paused = false,
+            CursorUp(n) => self.buffer.cursor_up(n),
+            CursorDown(n) => self.buffer.cursor_down(n),
+            CursorForward(n) => {
+                self.buffer.cursor_forward(n);
+                self.snap_cursor(false);
+            }
+            CursorBackward(n) => {
+                self.buffer.cursor_backward(n);
+                self.snap_cursor(true);
+            }
+            CursorPosition(row, col) => self.buffer.cursor_position(row, col),
+            EraseInDisplay(mode) => self.buffer.erase_in_display(mode, false, self.pen.bg),
+            EraseInLine(mode) => self.buffer.erase_in_line(mode, false, self.pen.bg),
+            InsertLines(n) => self.buffer.insert_blank_lines(n),
+            DeleteLines(n) => self.buffer.delete_lines(n),
+            SelectiveEraseInDisplay(mode) => self.buffer.erase_in_display(mode, true, self.pen.bg),
+            SelectiveEraseInLine(mode) => self.buffer.erase_in_line(mode, true, self.pen.bg),
+            DesignateCharset(set, charset) => {
+                if let Some(charset) = Charset::from_final(charset) {
+                    self.charsets[set as usize] = charset;
+                }
+            }
+            ShiftOut => self.active_charset = 1,
+            ShiftIn => self.active_charset = 0,
+            SingleShift2 => self.single_shift = Some(2),
+            SingleShift3 => self.single_shift = Some(3),
+            ResetToInitialState => self.reset(),
+            FillRectangularArea(ch, area) => {
+                let mut cell = self.pen;
+                cell.ch = ch;
+                self.buffer.fill_rectangle(area, cell);
+            }
+            EraseRectangularArea(area) => self.buffer.erase_rectangle(area),
+            SetScrollRegion(top, bottom) => self.buffer.set_scroll_region(top, bottom),
+            DeviceStatusReport(5) => self.responses.extend_from_slice(b"\x1B[0n"),
+            DeviceStatusReport(6) => {
+                let cursor = self.buffer.cursor();
+                let report = format!("\x1B[{};{}R", cursor.y + 1, cursor.x + 1);
+                self.responses.extend_from_slice(report.as_bytes());
+            }
+            SetTitle(title) => self.title = title,
+            SetWorkingDirectory(path) => self.working_directory = Some(path),
+            PushTitle => {
+                if self.title_stack.len() == TITLE_STACK_LIMIT {
+                    self.title_stack.remove(0);
+                }
+                self.title_stack.push(self.title.clone());
+            }
+            PopTitle => {
+                if let Some(title) = self.title_stack.pop() {
+                    self.title = title;
+                }
+            }
+            WindowOperation(14) => {
+                let (width, height) = self.pixel_size;
+                let report = format!("\x1B[4;{height};{width}t");
+                self.responses.extend_from_slice(report.as_bytes());
+            }
+            WindowOperation(18) => {
+                let (cols, rows) = (self.buffer.width(), self.buffer.height());
+                let report = format!("\x1B[8;{rows};{cols}t");
+                self.responses.extend_from_slice(report.as_bytes());
+            }
+            // Each capability gets its own reply, `1` with the value if it is known, `0` if not.
+            RequestCapabilities(names) => {
+                for name in names {
+                    let hex = encode_hex(name.as_bytes());
+                    let report = match termcap::capability(&name) {
+                        Some(value) => format!("\x1BP1+r{hex}={}\x1B\\", encode_hex(value)),
+                        None => format!("\x1BP0+r{hex}\x1B\\"),
+                    };
+                    self.responses.extend_from_slice(report.as_bytes());
+                }
+            }
+            SetCharacterProtection(protect) => {
+                self.pen.attrs.set(CellAttributes::PROTECTED, protect)
+            }
+            StartProtectedArea => self.pen.attrs.set(CellAttributes::PROTECTED, true),
+            EndProtectedArea => self.pen.attrs.set(CellAttributes::PROTECTED, false),
+            Sgr(Some(sgr)) => self.apply_sgr(sgr),
+            RequestMode(mode) => {
+                let report = format!("\x1B[{mode};{}$y", mode_status(self.mode(mode)));
+                self.responses.extend_from_slice(report.as_bytes());
+            }
+            DecRequestMode(mode) => {
+                let status = mode_status(self.private_mode(mode));
+                let report = format!("\x1B[?{};{status}$y", u16::from(mode));
+                self.responses.extend_from_slice(report.as_bytes());
+            }
+            SetMode(mode) => self.set_mode(mode, true),
+            ResetMode(mode) => self.set_mode(mode, false),
+            DecSetMode(mode) => self.set_private_mode(mode, true),
+            DecResetMode(mode) => self.set_private_mode(mode, false),
+            _ => {}
+        }
+    }
+
+    /// RIS: blanks the screen and restores the modes, pen and character sets. The scrollback and
+    /// the size of the grid are kept.
+    fn reset(&mut self) {
+        self.switch_screen(false);
+        self.saved_cursor = None;
+        self.buffer.clear();
+        self.buffer.set_autowrap(true);
+        self.buffer.set_insert(false);
+        self.modes = Modes::default();
+        self.pen = Cell::default();
+        self.charsets = [Charset::Ascii; 4];
+        self.active_charset = 0;
+        self.single_shift = None;
+        self.selection = None;
+    }
+
+    /// DECSC: saves the cursor position, pen and character set state for
+    /// [`Terminal::restore_cursor`].
+    fn save_cursor(&mut self) {
+        self.saved_cursor = Some(SavedCursor {
+            cursor: self.buffer.cursor(),
+            pen: self.pen,
+            charsets: self.charsets,
+            active_charset: self.active_charset,
+            origin: self.modes.origin,
+        });
+    }
+
+    /// DECRC: restores the state saved by [`Terminal::save_cursor`]. Without one, the cursor moves
+    /// home and the pen and character sets are reset, as in xterm.
+    fn restore_cursor(&mut self) {
+        let saved = self.saved_cursor.unwrap_or(SavedCursor {
+            cursor: Cursor::default(),
+            pen: Cell::default(),
+            charsets: [Charset::Ascii; 4],
+            active_charset: 0,
+            origin: false,
+        });
+        let Cursor { x, y } = saved.cursor;
+        self.buffer.cursor_position(y as u16 + 1, x as u16 + 1);
+        self.pen = saved.pen;
+        self.charsets = saved.charsets;
+        self.active_charset = saved.active_charset;
+        self.modes.origin = saved.origin;
+    }
+
+    /// Shows the alternate screen in place of the normal one or the other way round. The
+    /// alternate screen starts out blank and is discarded when left, the cursor keeps its position
+    /// across the switch.
+    fn switch_screen(&mut self, alternate: bool) {
+        if alternate == self.normal_screen.is_some() {
+            return;
+        }
+        let cursor = self.buffer.cursor();
+        let (buffer, saved_cursor) = match self.normal_screen.take() {
+            Some(normal) => normal,
+            None => {
+                let mut buffer = Buffer::new(self.buffer.width(), self.buffer.height());
+                buffer.set_scrollback_limit(0);
+                (buffer, None)
+            }
+        };
+        let buffer = std::mem::replace(&mut self.buffer, buffer);
+        let saved_cursor = std::mem::replace(&mut self.saved_cursor, saved_cursor);
+        if alternate {
+            self.normal_screen = Some((buffer, saved_cursor));
+        }
+
+        self.buffer.set_autowrap(self.modes.autowrap);
+        self.buffer.set_insert(self.modes.insert);
+        self.buffer
+            .cursor_position(cursor.y as u16 + 1, cursor.x as u16 + 1);
+        self.buffer.damage_all();
+        self.modes.alternate_screen = alternate;
+        self.selection = None;
+    }
+
+    /// Merges the cell under the cursor into `cell` about to be printed over it, see
+    /// [`Terminal::set_overstrike`].
+    fn overstrike(&self, cell: &mut Cell) {
+        let cursor = self.buffer.cursor();
+        let Some(under) = self.buffer.cell(cursor.x, cursor.y) else {
+            return;
+        };
+        // Keep emphasis from earlier strikes, as in `_\ba\ba` for bold and underlined.
+        let mut attrs = under.attrs;
+        if under.ch == cell.ch {
+            attrs.set(CellAttributes::BOLD, true);
+        } else if under.ch == '_' {
+            attrs.set_underline(UnderlineStyle::Straight);
+        } else if cell.ch == '_' && under.ch != ' ' {
+            cell.ch = under.ch;
+            attrs.set_underline(UnderlineStyle::Straight);
+        } else {
+            return;
+        }
+        cell.attrs = attrs;
+    }
+
+    /// Replaces the character printed last with its composition with `mark`. Returns whether
+    /// there was one.
+    #[cfg(feature = "nfc")]
+    fn compose(&mut self, mark: char) -> bool {
+        let Some((x, y)) = self.buffer.previous_cell() else {
+            return false;
+        };
+        let Some(mut cell) = self.buffer.cell(x, y).filter(|cell| !cell.is_cluster()) else {
+            return false;
+        };
+        match unicode_normalization::char::compose(cell.ch, mark) {
+            Some(composed) => {
+                cell.ch = composed;
+                self.buffer.set_cell(x, y, cell);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves the cursor off the trailing half of a wide character it was moved onto: back to the
+    /// lead when moving `backward`, past the character otherwise. The cursor thereby always stays
+    /// on a cell boundary.
+    fn snap_cursor(&mut self, backward: bool) {
+        let on_continuation = |buffer: &Buffer| {
+            let cursor = buffer.cursor();
+            buffer
+                .cell(cursor.x, cursor.y)
+                .is_some_and(Cell::is_wide_continuation)
+        };
+        if !on_continuation(&self.buffer) {
+            return;
+        }
+        if !backward {
+            self.buffer.cursor_forward(1);
+        }
+        // Moving forward is not possible if the character ends in the last column.
+        if on_continuation(&self.buffer) {
+            self.buffer.cursor_backward(1);
+        }
+    }
+
+    fn apply_sgr(&mut self, sgr: Sgr) {
+        let pen = &mut self.pen.attrs;
+        match sgr {
+            // Protection is not a rendition, it is only changed by DECSCA and SPA/EPA.
+            Sgr::Reset => {
+                let protected = pen.contains(CellAttributes::PROTECTED);
+                self.pen = Cell::default();
+                self.pen.attrs.set(CellAttributes::PROTECTED, protected);
+            }
+            Sgr::Bold => pen.set(CellAttributes::BOLD, true),
+            Sgr::Faint => pen.set(CellAttributes::FAINT, true),
+            Sgr::Regular => pen.set(CellAttributes::BOLD | CellAttributes::FAINT, false),
+            Sgr::Italic => pen.set(CellAttributes::ITALIC, true),
+            Sgr::NeitherItalicNorBlackletter => pen.set(CellAttributes::ITALIC, false),
+            Sgr::Underlined(style) => pen.set_underline(style),
+            Sgr::Blink(interval) => {
+                pen.set(CellAttributes::BLINK, interval != BlinkInterval::Static)
+            }
+            Sgr::Inverted(enabled) => pen.set(CellAttributes::INVERSE, enabled),
+            Sgr::Conceal(enabled) => pen.set(CellAttributes::HIDDEN, enabled),
+            Sgr::CrossedOut(enabled) => pen.set(CellAttributes::STRIKETHROUGH, enabled),
+            Sgr::ForegroundColor(color) => self.pen.fg = color.into(),
+            Sgr::BackgroundColor(color) => self.pen.bg = color.into(),
+            _ => {}
+        }
+    }
+
+    /// State of an ANSI mode, `None` if it isn't supported.
+    fn mode(&self, mode: u16) -> Option<bool> {
+        match mode {
+            4 => Some(self.modes.insert),
+            20 => Some(self.modes.newline),
+            _ => None,
+        }
+    }
+
+    fn set_mode(&mut self, mode: u16, enabled: bool) {
+        match mode {
+            4 => {
+                self.modes.insert = enabled;
+                self.buffer.set_insert(enabled);
+            }
+            20 => self.modes.newline = enabled,
+            _ => {}
+        }
+    }
+
+    /// State of a DEC private mode, `None` if it isn't supported.
+    fn private_mode(&self, mode: PrivateMode) -> Option<bool> {
+        match mode {
+            PrivateMode::CursorKeys => Some(self.modes.cursor_keys),
+            PrivateMode::Origin => Some(self.modes.origin),
+            PrivateMode::Autowrap => Some(self.modes.autowrap),
+            PrivateMode::ShowCursor => Some(self.modes.show_cursor),
+            PrivateMode::MouseTracking => Some(self.modes.mouse_tracking),
+            PrivateMode::SgrMouse => Some(self.modes.sgr_mouse),
+            PrivateMode::AlternateScroll => Some(self.modes.alternate_scroll),
+            PrivateMode::AlternateScreen | PrivateMode::AlternateScreenSaveCursor => {
+                Some(self.modes.alternate_screen)
+            }
+            // Reported as set while a cursor is saved, like xterm does.
+            PrivateMode::SaveCursor => Some(self.saved_cursor.is_some()),
+            PrivateMode::SynchronizedOutput => Some(self.modes.synchronized_output),
+            PrivateMode::Other(_) => None,
+        }
+    }
+
+    fn set_private_mode(&mut self, mode: PrivateMode, enabled: bool) {
+        match mode {
+            PrivateMode::CursorKeys => self.modes.cursor_keys = enabled,
+            PrivateMode::Origin => self.modes.origin = enabled,
+            PrivateMode::Autowrap => {
+                self.modes.autowrap = enabled;
+                self.buffer.set_autowrap(enabled);
+            }
+            PrivateMode::ShowCursor => self.modes.show_cursor = enabled,
+            PrivateMode::MouseTracking => self.modes.mouse_tracking = enabled,
+            PrivateMode::SgrMouse => self.modes.sgr_mouse = enabled,
+            PrivateMode::AlternateScroll => self.modes.alternate_scroll = enabled,
+            PrivateMode::AlternateScreen => self.switch_screen(enabled),
+            PrivateMode::SaveCursor if enabled => self.save_cursor(),
+            PrivateMode::SaveCursor => self.restore_cursor(),
+            PrivateMode::AlternateScreenSaveCursor if enabled => {
+                self.save_cursor();
+                self.switch_screen(true);
+            }
+            PrivateMode::AlternateScreenSaveCursor => {
+                self.switch_screen(false);
+                self.restore_cursor();
+            }
+            PrivateMode::SynchronizedOutput => self.modes.synchronized_output = enabled,
+            PrivateMode::Other(_) => {}
+        }
+    }
+}
+
+#[allow(dead_code, reason = "the window has no selection or URL UI yet")]
+impl Terminal {
+    pub fn selection(&self) -> Option<&Selection> {
+        self.selection.as_ref()
+    }
+
+    /// Selects the region between the `(column, row)` points `start` and `end`, inclusive.
+    pub fn select(&mut self, start: (usize, usize), end: (usize, usize), kind: SelectionKind) {
+        self.selection = Some(Selection::new(start, end, kind));
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection = None;
+    }
+
+    /// Text of the current selection, empty if nothing is selected.
+    pub fn selected_text(&self) -> String {
+        self.selection
+            .map(|selection| selection.text(&self.buffer))
+            .unwrap_or_default()
+    }
+
+    pub fn set_word_chars(&mut self, word_chars: impl Into<String>) {
+        self.word_chars = word_chars.into();
+    }
+
+    /// Start and end `(column, row)` of the word under `(col, row)`, for double-click selection.
+    pub fn word_at(&self, col: usize, row: usize) -> Option<((usize, usize), (usize, usize))> {
+        selection::word_at(&self.buffer, (col, row), &self.word_chars)
+    }
+
+    /// Start and end `(column, row)` of the logical line through `row`, for triple-click selection.
+    pub fn line_at(&self, row: usize) -> ((usize, usize), (usize, usize)) {
+        selection::line_at(&self.buffer, row)
+    }
+
+    /// URLs in the grid with the `(column, row)` cells they cover, so they can be made clickable
+    /// even when the program doesn't mark them up as hyperlinks.
+    pub fn find_urls(&self) -> Vec<(RangeInclusive<(usize, usize)>, String)> {
+        url::find_urls(&self.buffer)
+    }
+}
+
+/// Pm of a DECRPM report: 0 for unknown modes, 1 if the mode is set and 2 if it is reset.
+fn mode_status(state: Option<bool>) -> u8 {
+    match state {
+        None => 0,
+        Some(true) => 1,
+        Some(false) => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cell::Color;
+    use ansi::ansi::UnderlineStyle;
+
+    #[test]
+    fn feed_without_pty() {
+        let mut terminal = Terminal::new(10, 3);
+        terminal.feed(b"one\r\n\x1B[3;5Hx\x1B[1;2Hy");
+
+        assert_eq!(terminal.buffer().to_string(), "oye\n\n    x");
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 2, y: 0 });
+    }
+
+    #[test]
+    fn feed_split_sequence() {
+        let mut terminal = Terminal::new(10, 3);
+        for chunk in [&b"a\x1B"[..], b"[2", b";3", b"Hb"] {
+            terminal.feed(chunk);
+        }
+
+        assert_eq!(terminal.buffer().cell(0, 0).unwrap().ch, 'a');
+        assert_eq!(terminal.buffer().cell(2, 1).unwrap().ch, 'b');
+    }
+
+    #[test]
+    fn input_without_local_echo() {
+        let mut terminal = Terminal::new(10, 2);
+        terminal.input(b"a");
+
+        assert_eq!(terminal.buffer().cell(0, 0).unwrap().ch, ' ');
+    }
+
+    #[test]
+    fn input_with_local_echo() {
+        let mut terminal = Terminal::new(10, 2);
+        terminal.set_local_echo(true);
+        terminal.input(b"a");
+
+        assert_eq!(terminal.buffer().cell(0, 0).unwrap().ch, 'a');
+        assert_eq!(terminal.buffer().cursor().x, 1);
+    }
+
+    #[test]
+    fn modes_default() {
+        let terminal = Terminal::new(10, 2);
+
+        assert_eq!(terminal.modes(), &Modes::default());
+        assert!(terminal.modes().autowrap);
+        assert!(terminal.modes().show_cursor);
+    }
+
+    #[test]
+    fn modes_set_and_reset() {
+        let mut terminal = Terminal::new(10, 2);
+        terminal.feed(b"\x1B[?1h\x1B[?6h\x1B[?7l\x1B[?25l");
+
+        assert_eq!(
+            terminal.modes(),
+            &Modes {
+                newline: false,
+                insert: false,
+                cursor_keys: true,
+                keypad_application: false,
+                origin: true,
+                autowrap: false,
+                show_cursor: false,
+                mouse_tracking: false,
+                sgr_mouse: false,
+                alternate_scroll: false,
+                alternate_screen: false,
+                synchronized_output: false,
+            }
+        );
+
+        terminal.feed(b"\x1B[?1l\x1B[?7h");
+
+        assert!(!terminal.modes().cursor_keys);
+        assert!(terminal.modes().autowrap);
+    }
+
+    #[test]
+    fn combined_modes() {
+        let mut terminal = Terminal::new(10, 2);
+        terminal.feed(b"\x1B[?1000;1006h");
+
+        assert!(terminal.modes().mouse_tracking);
+        assert!(terminal.modes().sgr_mouse);
+
+        terminal.feed(b"\x1B[?1006;1000l");
+
+        assert_eq!(terminal.modes(), &Modes::default());
+    }
+
+    #[test]
+    fn wheel_sends_cursor_keys_in_alternate_scroll_mode() {
+        let mut terminal = Terminal::new(10, 2);
+        terminal.feed(b"\x1B[?1049h");
+        assert_eq!(terminal.wheel(1), None);
+
+        terminal.feed(b"\x1B[?1007h");
+        assert_eq!(terminal.wheel(2), Some(b"\x1B[A\x1B[A".to_vec()));
+        assert_eq!(terminal.wheel(-1), Some(b"\x1B[B".to_vec()));
+        assert_eq!(terminal.wheel(0), None);
+
+        terminal.feed(b"\x1B[?1h");
+        assert_eq!(terminal.wheel(-2), Some(b"\x1BOB\x1BOB".to_vec()));
+
+        // Reported as mouse events instead while tracking.
+        terminal.feed(b"\x1B[?1000h");
+        assert_eq!(terminal.wheel(1), None);
+
+        terminal.feed(b"\x1B[?1000l\x1B[?1007l");
+        assert_eq!(terminal.wheel(1), None);
+    }
+
+    #[test]
+    fn wheel_scrolls_normal_screen_in_alternate_scroll_mode() {
+        let mut terminal = Terminal::new(10, 2);
+        terminal.feed(b"\x1B[?1007h");
+        assert_eq!(terminal.wheel(1), None);
+
+        terminal.feed(b"\x1B[?1049h");
+        assert_eq!(terminal.wheel(1), Some(b"\x1B[A".to_vec()));
+
+        terminal.feed(b"\x1B[?1049l");
+        assert_eq!(terminal.wheel(1), None);
+    }
+
+    #[test]
+    fn working_directory_reported_by_shell() {
+        let mut terminal = Terminal::new(10, 2);
+        assert_eq!(terminal.working_directory(), None);
+
+        terminal.feed(b"\x1B]7;file://host/tmp/a%20b\x07");
+        assert_eq!(terminal.working_directory(), Some(Path::new("/tmp/a b")));
+
+        // A malformed URL keeps the last known directory.
+        terminal.feed(b"\x1B]7;/home\x07");
+        assert_eq!(terminal.working_directory(), Some(Path::new("/tmp/a b")));
+    }
+
+    #[test]
+    fn autowrap_mode_applies_to_grid() {
+        let mut terminal = Terminal::new(3, 2);
+        terminal.feed(b"\x1B[?7labcd");
+
+        assert_eq!(terminal.buffer().to_string(), "abd\n");
+    }
+
+    #[test]
+    fn selected_text_without_selection() {
+        let mut terminal = Terminal::new(10, 3);
+        terminal.feed(b"abc");
+
+        assert_eq!(terminal.selected_text(), "");
+    }
+
+    #[test]
+    fn selected_text_spanning_lines() {
+        let mut terminal = Terminal::new(10, 3);
+        terminal.feed(b"one two\r\nthree\r\nfour");
+        terminal.select((4, 0), (1, 2), SelectionKind::Linear);
+
+        assert_eq!(terminal.selected_text(), "two\nthree\nfo");
+    }
+
+    #[test]
+    fn selected_text_reversed_points() {
+        let mut terminal = Terminal::new(10, 3);
+        terminal.feed(b"one two\r\nthree");
+        terminal.select((2, 1), (4, 0), SelectionKind::Linear);
+
+        assert_eq!(terminal.selected_text(), "two\nthr");
+    }
+
+    #[test]
+    fn selected_text_joins_wrapped_rows() {
+        let mut terminal = Terminal::new(4, 4);
+        terminal.feed(b"abcdef gh\r\nij");
+        terminal.select((0, 0), (3, 3), SelectionKind::Linear);
+
+        assert_eq!(terminal.selected_text(), "abcdef gh\nij");
+    }
+
+    #[test]
+    fn selected_text_block() {
+        let mut terminal = Terminal::new(10, 3);
+        terminal.feed(b"abcdef\r\nghijkl\r\nmn");
+        terminal.select((4, 2), (1, 0), SelectionKind::Block);
+
+        assert_eq!(terminal.selected_text(), "bcde\nhijk\nn");
+    }
+
+    #[test]
+    fn clear_selection() {
+        let mut terminal = Terminal::new(10, 3);
+        terminal.feed(b"abc");
+        terminal.select((0, 0), (2, 0), SelectionKind::Linear);
+        terminal.clear_selection();
+
+        assert_eq!(terminal.selection(), None);
+        assert_eq!(terminal.selected_text(), "");
+    }
+
+    #[test]
+    fn word_at_mid_line() {
+        let mut terminal = Terminal::new(20, 2);
+        terminal.feed(b"cd ~/src/ttyrant; ls");
+
+        assert_eq!(terminal.word_at(5, 0), Some(((3, 0), (15, 0))));
+        assert_eq!(terminal.word_at(0, 0), Some(((0, 0), (1, 0))));
+    }
+
+    #[test]
+    fn word_at_boundaries() {
+        let mut terminal = Terminal::new(20, 2);
+        terminal.feed(b"foo bar");
+
+        assert_eq!(terminal.word_at(3, 0), None);
+        assert_eq!(terminal.word_at(6, 0), Some(((4, 0), (6, 0))));
+        assert_eq!(terminal.word_at(10, 0), None);
+        assert_eq!(terminal.word_at(20, 0), None);
+    }
+
+    #[test]
+    fn word_at_custom_word_chars() {
+        let mut terminal = Terminal::new(20, 2);
+        terminal.feed(b"a/b:c");
+
+        assert_eq!(terminal.word_at(0, 0), Some(((0, 0), (2, 0))));
+
+        terminal.set_word_chars(":");
+
+        assert_eq!(terminal.word_at(0, 0), Some(((0, 0), (0, 0))));
+        assert_eq!(terminal.word_at(3, 0), Some(((2, 0), (4, 0))));
+    }
+
+    #[test]
+    fn word_at_follows_wrapped_rows() {
+        let mut terminal = Terminal::new(4, 3);
+        terminal.feed(b"ab cdefg h");
+
+        assert_eq!(terminal.word_at(0, 1), Some(((3, 0), (3, 1))));
+    }
+
+    #[test]
+    fn line_at_single_row() {
+        let mut terminal = Terminal::new(10, 3);
+        terminal.feed(b"one\r\ntwo");
+
+        assert_eq!(terminal.line_at(1), ((0, 1), (9, 1)));
+    }
+
+    #[test]
+    fn line_at_wrapped_rows() {
+        let mut terminal = Terminal::new(4, 5);
+        terminal.feed(b"top\r\nabcdefghij\r\nend");
+
+        for row in 1..=3 {
+            assert_eq!(terminal.line_at(row), ((0, 1), (3, 3)));
+        }
+        assert_eq!(terminal.line_at(0), ((0, 0), (3, 0)));
+        assert_eq!(terminal.line_at(4), ((0, 4), (3, 4)));
+
+        let (start, end) = terminal.line_at(2);
+        terminal.select(start, end, SelectionKind::Linear);
+
+        assert_eq!(terminal.selected_text(), "abcdefghij");
+    }
+
+    #[test]
+    fn find_urls_mid_line() {
+        let mut terminal = Terminal::new(40, 2);
+        terminal.feed(b"open https://example.com/x now");
+
+        assert_eq!(
+            terminal.find_urls(),
+            [((5, 0)..=(25, 0), "https://example.com/x".to_string())]
+        );
+    }
+
+    #[test]
+    fn find_urls_across_wrap() {
+        let mut terminal = Terminal::new(10, 4);
+        terminal.feed(b"go http://example.org/path\r\nhttp://x.y");
+
+        assert_eq!(
+            terminal.find_urls(),
+            [
+                ((3, 0)..=(5, 2), "http://example.org/path".to_string()),
+                ((0, 3)..=(9, 3), "http://x.y".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn sgr_underline_style() {
+        let mut terminal = Terminal::new(10, 2);
+        terminal.feed(b"\x1B[1;4:3ma\x1B[4:0mb\x1B[0mc");
+
+        let attrs = |x| terminal.buffer().cell(x, 0).unwrap().attrs;
+        assert_eq!(attrs(0).underline(), UnderlineStyle::Curly);
+        assert!(attrs(0).contains(CellAttributes::BOLD));
+        assert_eq!(attrs(1).underline(), UnderlineStyle::None);
+        assert!(attrs(1).contains(CellAttributes::BOLD));
+        assert_eq!(attrs(2), CellAttributes::default());
+    }
+
+    #[test]
+    fn sgr_colors() {
+        let mut terminal = Terminal::new(10, 2);
+        terminal.feed(b"\x1B[31;48;2;1;2;3ma\x1B[39mb\x1B[0mc");
+
+        let cell = |x| terminal.buffer().cell(x, 0).unwrap();
+        assert_eq!(cell(0).fg, Color::indexed(1));
+        assert_eq!(cell(0).bg, Color::rgb(1, 2, 3));
+        assert_eq!(cell(1).fg, Color::DEFAULT);
+        assert_eq!(cell(1).bg, Color::rgb(1, 2, 3));
+        assert_eq!(cell(2), Cell::new('c', CellAttributes::default()));
+    }
+
+    #[test]
+    fn sgr_resets_only_their_attribute() {
+        let mut terminal = Terminal::new(10, 2);
+        terminal.feed(b"\x1B[1;4;31ma\x1B[24mb");
+
+        let cell = |x| terminal.buffer().cell(x, 0).unwrap();
+        assert_eq!(cell(0).attrs.underline(), UnderlineStyle::Straight);
+        assert_eq!(cell(1).attrs.underline(), UnderlineStyle::None);
+        assert!(cell(1).attrs.contains(CellAttributes::BOLD));
+        assert_eq!(cell(1).fg, Color::indexed(1));
+
+        let all = CellAttributes::ITALIC
+            | CellAttributes::BLINK
+            | CellAttributes::INVERSE
+            | CellAttributes::HIDDEN
+            | CellAttributes::STRIKETHROUGH;
+        for (reset, attr) in [
+            (&b"23"[..], CellAttributes::ITALIC),
+            (b"25", CellAttributes::BLINK),
+            (b"27", CellAttributes::INVERSE),
+            (b"28", CellAttributes::HIDDEN),
+            (b"29", CellAttributes::STRIKETHROUGH),
+        ] {
+            let mut terminal = Terminal::new(10, 2);
+            terminal.feed(b"\x1B[1;3;5;7;8;9;32;44m\x1B[");
+            terminal.feed(reset);
+            terminal.feed(b"ma");
+
+            let mut expected = all | CellAttributes::BOLD;
+            expected.set(attr, false);
+            let cell = terminal.buffer().cell(0, 0).unwrap();
+            assert_eq!(cell.attrs, expected);
+            assert_eq!((cell.fg, cell.bg), (Color::indexed(2), Color::indexed(4)));
+        }
+    }
+
+    #[test]
+    fn flow_control_disabled_by_default() {
+        let mut terminal = Terminal::new(10, 2);
+        terminal.feed(b"\x13");
+
+        assert!(!terminal.transmit_paused());
+    }
+
+    #[test]
+    fn overstrike_bold_and_underline() {
+        let mut terminal = Terminal::new(10, 1);
+        terminal.set_overstrike(true);
+        terminal.feed(b"a\x08a_\x08bc\x08_d\x08x_\x08z\x08z");
+
+        assert_eq!(terminal.buffer().to_string(), "abcxz");
+        let attrs = |x| terminal.buffer().cell(x, 0).unwrap().attrs;
+        assert!(attrs(0).contains(CellAttributes::BOLD));
+        assert_eq!(attrs(0).underline(), UnderlineStyle::None);
+        assert_eq!(attrs(1).underline(), UnderlineStyle::Straight);
+        assert!(!attrs(1).contains(CellAttributes::BOLD));
+        assert_eq!(attrs(2).underline(), UnderlineStyle::Straight);
+        assert_eq!(attrs(3), CellAttributes::default());
+        assert!(attrs(4).contains(CellAttributes::BOLD));
+        assert_eq!(attrs(4).underline(), UnderlineStyle::Straight);
+    }
+
+    #[test]
+    fn overstrike_disabled_overwrites() {
+        let mut terminal = Terminal::new(10, 1);
+        terminal.feed(b"a\x08a_\x08b");
+
+        assert_eq!(terminal.buffer().to_string(), "ab");
+        assert_eq!(
+            terminal.buffer().cell(0, 0).unwrap().attrs,
+            CellAttributes::default()
+        );
+        assert_eq!(
+            terminal.buffer().cell(1, 0).unwrap().attrs,
+            CellAttributes::default()
+        );
+    }
+
+    #[test]
+    fn flow_control_pause_and_resume() {
+        let mut terminal = Terminal::new(10, 2);
+        terminal.set_flow_control(true);
+        terminal.feed(b"a\x13b");
+
+        assert!(terminal.transmit_paused());
+        assert_eq!(terminal.buffer().to_string(), "ab\n");
+
+        terminal.feed(b"\x11");
+
+        assert!(!terminal.transmit_paused());
+
+        terminal.feed(b"\x13");
+        terminal.set_flow_control(false);
+
+        assert!(!terminal.transmit_paused());
+    }
+
+    #[test]
+    fn erase_uses_current_background() {
+        let mut terminal = Terminal::new(4, 2);
+        terminal.feed(b"abcd\r\n\x1B[42mx\x1B[K\x1B[0m\x1B[1;3H\x1B[1K");
+
+        let bg = |x, y| terminal.buffer().cell(x, y).unwrap().bg;
+        assert_eq!(bg(0, 1), Color::indexed(2));
+        assert_eq!(bg(3, 1), Color::indexed(2));
+        assert_eq!(bg(2, 0), Color::DEFAULT);
+        assert_eq!(terminal.buffer().cell(3, 0).unwrap().ch, 'd');
+    }
+
+    #[test]
+    fn insert_and_delete_lines() {
+        let mut terminal = Terminal::new(3, 4);
+        terminal.feed(b"a\r\nb\r\nc\r\nd\x1B[2;3r\x1B[2;2H\x1B[L");
+        assert_eq!(terminal.buffer().to_string(), "a\n\nb\nd");
+
+        terminal.feed(b"\x1B[2M");
+        assert_eq!(terminal.buffer().to_string(), "a\n\n\nd");
+    }
+
+    #[test]
+    fn horizontal_tab_moves_to_tab_stop() {
+        let mut terminal = Terminal::new(20, 1);
+        terminal.feed(b"a\tb\x1B[3\tDc");
+        assert_eq!(terminal.buffer().to_string(), "a       b    c");
+    }
+
+    #[test]
+    fn selective_erase_skips_protected_cells() {
+        let mut terminal = Terminal::new(6, 2);
+        terminal.feed(b"a\x1B[1\"qbc\x1B[0md\x1B[0\"qe\r\nf\x1BVg\x1BWh");
+
+        terminal.feed(b"\x1B[1;1H\x1B[?K");
+        assert_eq!(terminal.buffer().to_string(), " bcd\nfgh");
+        terminal.feed(b"\x1B[?J");
+        assert_eq!(terminal.buffer().to_string(), " bcd\n g");
+
+        terminal.feed(b"\x1B[K");
+        assert_eq!(terminal.buffer().to_string(), "\n g");
+        terminal.feed(b"\x1B[J");
+        assert_eq!(terminal.buffer().to_string(), "\n");
+    }
+
+    #[test]
+    fn device_status_report_queues_response() {
+        let mut terminal = Terminal::new(10, 5);
+        terminal.feed(b"\x1B[3;4H\x1B[6n");
+
+        assert_eq!(terminal.take_responses(), b"\x1B[3;4R");
+        assert!(terminal.take_responses().is_empty());
+
+        terminal.feed(b"\x1B[5n");
+        assert_eq!(terminal.take_responses(), b"\x1B[0n");
+    }
+
+    #[test]
+    fn newline_mode() {
+        let mut terminal = Terminal::new(4, 3);
+        terminal.feed(b"ab\n");
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 2, y: 1 });
+
+        terminal.feed(b"\x1B[20h\n");
+        assert!(terminal.modes().newline);
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 0, y: 2 });
+
+        terminal.feed(b"\x1B[20lab\x0B");
+        assert!(!terminal.modes().newline);
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 2, y: 2 });
+    }
+
+    #[test]
+    fn insert_mode_is_not_a_private_mode() {
+        let mut terminal = Terminal::new(6, 1);
+        terminal.feed(b"abc\r\x1B[?4hx");
+        assert!(!terminal.modes().insert);
+        assert_eq!(terminal.buffer().to_string(), "xbc");
+
+        terminal.feed(b"\x1B[4hy");
+        assert!(terminal.modes().insert);
+        assert_eq!(terminal.buffer().to_string(), "xybc");
+
+        terminal.feed(b"\x1B[4lz\x1B[4$p");
+        assert!(!terminal.modes().insert);
+        assert_eq!(terminal.buffer().to_string(), "xyzc");
+        assert_eq!(terminal.take_responses(), b"\x1B[4;2$y");
+    }
+
+    fn print(terminal: &mut Terminal, text: &str) {
+        for ch in text.chars() {
+            terminal.perform(AnsiCommand::Print(ch));
+        }
+    }
+
+    #[test]
+    fn cursor_steps_over_wide_char() {
+        let mut terminal = Terminal::new(6, 1);
+        print(&mut terminal, "a漢b");
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 4, y: 0 });
+
+        terminal.feed(b"\x1B[2D");
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 1, y: 0 });
+        terminal.feed(b"\x1B[C");
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 3, y: 0 });
+        terminal.feed(b"\x08");
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 1, y: 0 });
+
+        let mut terminal = Terminal::new(4, 1);
+        print(&mut terminal, "ab漢");
+        terminal.feed(b"\r\x1B[3C");
+        assert_eq!(
+            terminal.buffer().cursor(),
+            Cursor { x: 2, y: 0 },
+            "last column"
+        );
+    }
+
+    #[test]
+    fn cursor_steps_over_combined_cell() {
+        let mut terminal = Terminal::new(6, 1);
+        print(&mut terminal, "e\u{301}x");
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 2, y: 0 });
+
+        terminal.feed(b"\x1B[D\x1B[D");
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 0, y: 0 });
+        terminal.feed(b"\x1B[C");
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 1, y: 0 });
+    }
+
+    #[test]
+    fn rectangular_area_operations() {
+        let mut terminal = Terminal::new(4, 3);
+        terminal.feed(b"\x1B[1m\x1B[42;1;2;2;3$x");
+        assert_eq!(terminal.buffer().to_string(), " **\n **\n");
+        assert!(terminal
+            .buffer()
+            .cell(1, 0)
+            .unwrap()
+            .attrs
+            .contains(CellAttributes::BOLD));
+
+        terminal.feed(b"\x1B[2;1;2;2$z");
+        assert_eq!(terminal.buffer().to_string(), " **\n  *\n");
+    }
+
+    #[cfg(feature = "nfc")]
+    #[test]
+    fn normalization_composes_marks() {
+        let mut terminal = Terminal::new(4, 1);
+        print(&mut terminal, "e\u{301}");
+        assert_eq!(
+            terminal.buffer().cell(0, 0).unwrap().ch,
+            'e',
+            "off by default"
+        );
+
+        let mut terminal = Terminal::new(4, 1);
+        terminal.set_normalization(true);
+        print(&mut terminal, "e\u{301}a\u{308}\u{301}x");
+        // Marks without a precomposed form stay in the cell.
+        assert_eq!(terminal.buffer().to_string(), "éä\u{301}x");
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 3, y: 0 });
+    }
+
+    #[test]
+    fn bell_is_taken_once() {
+        let mut terminal = Terminal::new(10, 1);
+        assert!(!terminal.take_bell());
+
+        terminal.feed(b"\x07\x07");
+        assert!(terminal.take_bell());
+        assert!(!terminal.take_bell());
+    }
+
+    #[test]
+    fn title_stack_restores_title() {
+        let mut terminal = Terminal::new(10, 1);
+        terminal.feed(b"\x1B]2;shell\x07\x1B[22;0t\x1B]0;vim\x07");
+        assert_eq!(terminal.title(), "vim");
+
+        terminal.feed(b"\x1B[22;0t\x1B]2;help\x07\x1B[23;0t");
+        assert_eq!(terminal.title(), "vim");
+        terminal.feed(b"\x1B[23;0t");
+        assert_eq!(terminal.title(), "shell");
+
+        terminal.feed(b"\x1B[23;0t");
+        assert_eq!(terminal.title(), "shell", "empty stack");
+    }
+
+    #[test]
+    fn request_mode_reports_state() {
+        let mut terminal = Terminal::new(10, 1);
+        terminal.feed(b"\x1B[?25$p\x1B[?1$p\x1B[?2004$p");
+        assert_eq!(
+            terminal.take_responses(),
+            b"\x1B[?25;1$y\x1B[?1;2$y\x1B[?2004;0$y"
+        );
+
+        terminal.feed(b"\x1B[?25l\x1B[20h\x1B[?25$p\x1B[20$p\x1B[4$p");
+        assert_eq!(
+            terminal.take_responses(),
+            b"\x1B[?25;2$y\x1B[20;1$y\x1B[4;2$y"
+        );
+    }
+
+    #[test]
+    fn synchronized_output_mode() {
+        let mut terminal = Terminal::new(10, 1);
+        terminal.feed(b"\x1B[?2026$p\x1B[?2026ha");
+        assert!(terminal.modes().synchronized_output);
+        terminal.feed(b"\x1B[?2026$p\x1B[?2026l");
+        assert!(!terminal.modes().synchronized_output);
+
+        // Programs check the mode is supported before using it.
+        assert_eq!(terminal.take_responses(), b"\x1B[?2026;2$y\x1B[?2026;1$y");
+        assert_eq!(terminal.buffer().to_string(), "a");
+    }
+
+    #[test]
+    fn save_cursor_mode() {
+        let mut terminal = Terminal::new(10, 3);
+        terminal.feed(b"\x1B[2;3H\x1B[1m\x1B(0\x1B[?1048h");
+        terminal.feed(b"\x1B[m\x1B(B\x1B[3;1Hx\x1B[?1048l");
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 2, y: 1 });
+
+        // Pen and character sets come back too, the screen is left alone.
+        terminal.feed(b"q");
+        let cell = terminal.buffer().cell(2, 1).unwrap();
+        assert_eq!(cell.ch, '─');
+        assert!(cell.attrs.contains(CellAttributes::BOLD));
+        assert_eq!(terminal.buffer().to_string(), "\n  ─\nx");
+
+        terminal.feed(b"\x1B[?1048$p");
+        assert_eq!(terminal.take_responses(), b"\x1B[?1048;1$y");
+    }
+
+    #[test]
+    fn save_and_restore_cursor() {
+        let mut terminal = Terminal::new(10, 3);
+        terminal.feed(b"\x1B[2;3H\x1B[4m\x1B7\x1B[m\x1B[Hx\x1B8y");
+
+        assert_eq!(terminal.buffer().to_string(), "x\n  y\n");
+        let cell = terminal.buffer().cell(2, 1).unwrap();
+        assert_eq!(cell.attrs.underline(), UnderlineStyle::Straight);
+        // ESC 7 and mode 1048 share the saved cursor.
+        terminal.feed(b"\x1B[3;1H\x1B[?1048h\x1B[H\x1B8");
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 0, y: 2 });
+    }
+
+    #[test]
+    fn index_reverse_index_and_next_line() {
+        let mut terminal = Terminal::new(10, 3);
+        terminal.feed(b"\x1B[20ha\x1BDb\x1BEc");
+        // IND ignores LNM, NEL always returns to the first column.
+        assert_eq!(terminal.buffer().to_string(), "a\n b\nc");
+
+        terminal.feed(b"\x1BD");
+        assert_eq!(terminal.buffer().to_string(), " b\nc\n");
+        terminal.feed(b"\x1B[H\x1BMd");
+        assert_eq!(terminal.buffer().to_string(), "d\n b\nc");
+    }
+
+    #[test]
+    fn keypad_modes() {
+        let mut terminal = Terminal::new(10, 1);
+        terminal.feed(b"\x1B=");
+        assert!(terminal.modes().keypad_application);
+        terminal.feed(b"\x1B>");
+        assert!(!terminal.modes().keypad_application);
+    }
+
+    #[test]
+    fn alternate_screen_mode() {
+        let mut terminal = Terminal::new(10, 2);
+        terminal.feed(b"one\r\ntwo\x1B[?1047h");
+        assert!(terminal.modes().alternate_screen);
+        assert_eq!(terminal.buffer().to_string(), "\n");
+        // The cursor stays where it was on the normal screen.
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 3, y: 1 });
+
+        // Nothing scrolls into the scrollback of the alternate screen.
+        terminal.feed(b"\r\nalt\x1B[1;2H\x1B[?1047l");
+        assert!(!terminal.modes().alternate_screen);
+        assert_eq!(terminal.buffer().to_string(), "one\ntwo");
+        assert_eq!(terminal.buffer().scrollback_len(), 0);
+        // Mode 1047 doesn't restore the cursor.
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 1, y: 0 });
+
+        terminal.feed(b"\x1B[?1047h");
+        assert_eq!(terminal.buffer().to_string(), "\n", "cleared when left");
+        terminal.feed(b"\x1B[?1047$p\x1B[?1049$p");
+        assert_eq!(terminal.take_responses(), b"\x1B[?1047;1$y\x1B[?1049;1$y");
+    }
+
+    #[test]
+    fn alternate_screen_save_cursor_mode() {
+        let mut terminal = Terminal::new(10, 2);
+        terminal.feed(b"one\x1B[?1049h");
+        assert_eq!(terminal.buffer().to_string(), "\n");
+        terminal.feed(b"\x1B[2;5Halt\x1B[?1049l");
+
+        assert_eq!(terminal.buffer().to_string(), "one\n");
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 3, y: 0 });
+    }
+
+    #[test]
+    fn alternate_screen_has_own_saved_cursor() {
+        let mut terminal = Terminal::new(10, 3);
+        terminal.feed(b"\x1B[2;2H\x1B[?1049h\x1B[3;3H\x1B[?1048h\x1B[?1049l");
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 1, y: 1 });
+
+        // Both screens are resized, and reset returns to the normal one.
+        terminal.feed(b"\x1B[?1047h");
+        terminal.resize(5, 2);
+        terminal.feed(b"\x1Bc");
+        assert!(!terminal.modes().alternate_screen);
+        assert_eq!(terminal.buffer().width(), 5);
+    }
+
+    #[test]
+    fn capability_requests() {
+        let mut terminal = Terminal::new(10, 1);
+        // Co, then an unknown name and an invalid one.
+        terminal.feed(b"\x1BP+q436F\x1B\\\x1BP+q736D6B78;4\x1B\\");
+
+        assert_eq!(
+            terminal.take_responses(),
+            b"\x1BP1+r436F=323536\x1B\\\x1BP0+r736D6B78\x1B\\\x1BP0+r\x1B\\"
+        );
+    }
+
+    #[test]
+    fn window_size_reports() {
+        let mut terminal = Terminal::new(80, 24);
+        terminal.feed(b"\x1B[18t");
+        assert_eq!(terminal.take_responses(), b"\x1B[8;24;80t");
+
+        terminal.set_pixel_size(640, 480);
+        terminal.resize(100, 30);
+        terminal.feed(b"\x1B[14t\x1B[18t");
+        assert_eq!(terminal.take_responses(), b"\x1B[4;480;640t\x1B[8;30;100t");
+    }
+
+    #[test]
+    fn single_shift_applies_to_next_character() {
+        let mut terminal = Terminal::new(10, 1);
+        terminal.feed(b"\x1B*0q\x1BNqq\x1BOq");
+
+        assert_eq!(terminal.buffer().to_string(), "q─qq");
+    }
+
+    #[test]
+    fn dec_special_graphics() {
+        let mut terminal = Terminal::new(10, 2);
+        terminal.feed(b"\x1B(0lqqk\r\nx  x\x1B(B lqqk");
+
+        assert_eq!(terminal.buffer().to_string(), "┌──┐\n│  │ lqqk");
+    }
+
+    #[test]
+    fn shift_out_selects_g1() {
+        let mut terminal = Terminal::new(10, 1);
+        terminal.feed(b"\x1B)0q\x0Eq\x1B(0\x0Fq\x0E\x1B)Bq\x0Fq");
+
+        assert_eq!(terminal.buffer().to_string(), "q──q─");
+    }
+
+    #[test]
+    fn resize_clamps_cursor() {
+        let mut terminal = Terminal::new(10, 5);
+        terminal.feed(b"\x1B[5;10H");
+
+        assert_eq!(terminal.resize(4, 3), (4, 3));
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 3, y: 2 });
+        assert_eq!(terminal.resize(0, 0), (1, 1));
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 0, y: 0 });
+    }
+
+    #[test]
+    fn resize_resets_scroll_region() {
+        let mut terminal = Terminal::new(10, 5);
+        terminal.feed(b"\x1B[2;4r");
+        assert_eq!(terminal.buffer().scroll_region(), 1..4);
+
+        terminal.resize(10, 3);
+        assert_eq!(terminal.buffer().scroll_region(), 0..3);
+    }
+
+    #[test]
+    fn resize_mode() {
+        let mut terminal = Terminal::new(4, 3);
+        terminal.feed(b"abcdef");
+        terminal.resize(8, 3);
+        assert_eq!(terminal.buffer().to_string(), "abcdef\n\n");
+
+        let mut terminal = Terminal::new(4, 3);
+        terminal.set_resize_mode(ResizeMode::Clip);
+        terminal.feed(b"abcdef");
+        terminal.resize(8, 3);
+        assert_eq!(terminal.buffer().to_string(), "abcd\nef\n");
+    }
+
+    #[test]
+    fn reset_to_initial_state() {
+        let mut terminal = Terminal::new(4, 3);
+        terminal.feed(b"\x1B[?25l\x1B[2;3r\x1B[1m\x1B(0ab\x1Bc");
+
+        assert_eq!(terminal.buffer(), &Buffer::new(4, 3));
+        assert_eq!(terminal.modes(), &Modes::default());
+
+        terminal.feed(b"q");
+        let cell = terminal.buffer().cell(0, 0).unwrap();
+        assert_eq!(cell, Cell::new('q', CellAttributes::default()));
+    }
+
+    #[test]
+    fn utf8_split_across_feeds() {
+        let mut terminal = Terminal::new(6, 1);
+        let text = "é日".as_bytes();
+        terminal.feed(&text[..3]);
+        terminal.feed(&text[3..]);
+
+        assert_eq!(terminal.buffer().to_string(), "é日");
+        assert_eq!(terminal.buffer().cursor(), Cursor { x: 3, y: 0 });
+    }
+
+    #[test]
+    fn reset_after_clusters() {
+        let mut terminal = Terminal::new(4, 3);
+        terminal.feed("e\u{301}\u{302}".as_bytes());
+        assert!(terminal.buffer().cell(0, 0).unwrap().is_cluster());
+
+        terminal.feed(b"\x1Bc");
+        assert_eq!(terminal.buffer(), &Buffer::new(4, 3));
+    }
+}
@@ -0,0 +1,138 @@
+use std::ops::RangeInclusive;
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::cell::Buffer;
+
+const SCHEMES: [&str; 3] = ["http://", "https://", "file://"];
+
+/// Scans the grid for URLs, joining rows continued by autowrap. Each match is returned with the
+/// inclusive `(column, row)` range of cells it covers.
+pub fn find_urls(buffer: &Buffer) -> Vec<(RangeInclusive<(usize, usize)>, String)> {
+    let mut urls = Vec::new();
+    let mut chars = Vec::new();
+    for y in 0..buffer.height() {
+        push_row(buffer, y, &mut chars);
+        let continues = buffer.line(y + 1).is_some_and(|line| line.is_wrapped());
+        if !continues {
+            scan(&chars, &mut urls);
+            chars.clear();
+        }
+    }
+    urls
+}
+
+/// Appends the characters of row `y` with their positions, skipping wide character placeholders.
+fn push_row(buffer: &Buffer, y: usize, chars: &mut Vec<(char, (usize, usize))>) {
+    let Some(line) = buffer.line(y) else {
+        return;
+    };
+
+    let mut x = 0;
+    while x < buffer.width() {
+        let ch = line.get(x as u16).ch;
+        chars.push((ch, (x, y)));
+        x += ch.width().unwrap_or(1).max(1);
+    }
+}
+
+fn scan(
+    chars: &[(char, (usize, usize))],
+    urls: &mut Vec<(RangeInclusive<(usize, usize)>, String)>,
+) {
+    let mut i = 0;
+    while i < chars.len() {
+        let at_boundary = i == 0 || !chars[i - 1].0.is_alphanumeric();
+        let Some(scheme) = SCHEMES
+            .iter()
+            .find(|scheme| at_boundary && starts_with(&chars[i..], scheme))
+        else {
+            i += 1;
+            continue;
+        };
+
+        let body = i + scheme.len();
+        let mut end = body;
+        while end < chars.len() && is_url_char(chars[end].0) {
+            end += 1;
+        }
+        end = trim_end(&chars[i..end]) + i;
+
+        if end > body {
+            let url = chars[i..end].iter().map(|(ch, _)| ch).collect();
+            urls.push((chars[i].1..=chars[end - 1].1, url));
+        }
+        i = end.max(i + 1);
+    }
+}
+
+fn starts_with(chars: &[(char, (usize, usize))], prefix: &str) -> bool {
+    chars.len() >= prefix.len()
+        && prefix
+            .chars()
+            .zip(chars)
+            .all(|(expected, (ch, _))| ch.eq_ignore_ascii_case(&expected))
+}
+
+fn is_url_char(ch: char) -> bool {
+    !ch.is_whitespace()
+        && !ch.is_control()
+        && !matches!(ch, '<' | '>' | '"' | '`' | '{' | '}' | '|' | '\\' | '^')
+}
+
+/// Length of `url` without trailing punctuation that more likely belongs to the surrounding text,
+/// such as a full stop or the closing parenthesis around a URL.
+fn trim_end(url: &[(char, (usize, usize))]) -> usize {
+    let count =
+        |len: usize, target: char| url[..len].iter().filter(|(ch, _)| *ch == target).count();
+
+    let mut len = url.len();
+    while let Some(&(last, _)) = url[..len].last() {
+        let trim = match last {
+            '.' | ',' | ';' | ':' | '!' | '?' | '\'' => true,
+            ')' => count(len, '(') < count(len, ')'),
+            ']' => count(len, '[') < count(len, ']'),
+            _ => false,
+        };
+        if !trim {
+            break;
+        }
+        len -= 1;
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cell::CellAttributes;
+
+    fn urls_in(text: &str) -> Vec<String> {
+        let mut buffer = Buffer::new(80, 1);
+        buffer.write_str(0, 0, text, CellAttributes::default());
+        find_urls(&buffer).into_iter().map(|(_, url)| url).collect()
+    }
+
+    #[test]
+    fn trailing_punctuation() {
+        assert_eq!(urls_in("see https://example.com."), ["https://example.com"]);
+        assert_eq!(
+            urls_in("(docs at https://example.com/a_(b)), ok"),
+            ["https://example.com/a_(b)"]
+        );
+    }
+
+    #[test]
+    fn scheme_needs_boundary_and_body() {
+        assert!(urls_in("xhttp://example.com").is_empty());
+        assert!(urls_in("http:// nothing").is_empty());
+    }
+
+    #[test]
+    fn multiple_urls() {
+        assert_eq!(
+            urls_in("http://a.org file:///tmp/x HTTPS://B.org"),
+            ["http://a.org", "file:///tmp/x", "HTTPS://B.org"]
+        );
+    }
+}
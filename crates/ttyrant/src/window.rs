@@ -1,14 +1,225 @@
-use std::sync::Arc;
+use std::{fmt, sync::Arc};
 
 use glyphon::{FontSystem, SwashCache, TextAtlas, TextRenderer, Viewport};
 use winit::window::Window;
 
 use wgpu::{
-    CompositeAlphaMode, Device, DeviceDescriptor, Instance, InstanceDescriptor, MultisampleState,
-    PresentMode, Queue, RequestAdapterOptions, Surface, SurfaceConfiguration, TextureFormat,
-    TextureUsages,
+    CompositeAlphaMode, CreateSurfaceError, Device, DeviceDescriptor, Instance, InstanceDescriptor,
+    MultisampleState, PresentMode, Queue, RequestAdapterOptions, RequestDeviceError, Surface,
+    SurfaceConfiguration, SurfaceError, TextureFormat, TextureUsages,
 };
 
+const FONT_SIZE: f32 = 30.0;
+/// Monospace font used unless another one is configured.
+pub const FONT_FAMILY: &str = "JetBrains Mono NL";
+const DEFAULT_LINE_SPACING: f32 = 1.4;
+/// Advance of a monospace glyph relative to the font size.
+const CELL_WIDTH_RATIO: f32 = 0.6;
+
+/// Size of the cells text is laid out in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CellMetrics {
+    pub font_size: f32,
+    /// Height of a row relative to the font size.
+    pub line_spacing: f32,
+}
+
+impl Default for CellMetrics {
+    fn default() -> Self {
+        Self {
+            font_size: FONT_SIZE,
+            line_spacing: DEFAULT_LINE_SPACING,
+        }
+    }
+}
+
+impl CellMetrics {
+    pub fn line_height(&self) -> f32 {
+        self.font_size * self.line_spacing
+    }
+
+    pub fn cell_width(&self) -> f32 {
+        self.font_size * CELL_WIDTH_RATIO
+    }
+
+    /// The metrics in physical pixels on a display with `scale_factor`, the font size being
+    /// given in logical pixels.
+    pub fn scaled(&self, scale_factor: f64) -> Self {
+        Self {
+            font_size: (self.font_size as f64 * scale_factor) as f32,
+            ..*self
+        }
+    }
+
+    pub fn metrics(&self) -> glyphon::Metrics {
+        glyphon::Metrics::new(self.font_size, self.line_height())
+    }
+
+    /// Size in pixels of a surface fitting exactly `cols` x `rows` cells.
+    pub fn surface_size(&self, cols: usize, rows: usize) -> (u32, u32) {
+        let width = (cols as f32 * self.cell_width()).ceil() as u32;
+        let height = (rows as f32 * self.line_height()).ceil() as u32;
+        (width, height)
+    }
+
+    /// Number of columns and rows of text fitting into a surface of `width` x `height` pixels,
+    /// at least one of each.
+    pub fn grid_size(&self, width: u32, height: u32) -> (usize, usize) {
+        let cols = width as f32 / self.cell_width();
+        let rows = height as f32 / self.line_height();
+        ((cols as usize).max(1), (rows as usize).max(1))
+    }
+}
+
+/// How the window surface is set up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SurfaceOptions {
+    /// Opacity of the background from 0 (transparent) to 1, text stays opaque. Only takes effect
+    /// if the platform supports compositing the window with alpha.
+    pub opacity: f32,
+    /// Requested presentation mode. `Fifo` waits for vertical sync, `Mailbox` and `Immediate`
+    /// lower latency at the cost of more frames drawn or tearing.
+    pub present_mode: PresentMode,
+}
+
+impl Default for SurfaceOptions {
+    fn default() -> Self {
+        Self {
+            opacity: 1.0,
+            present_mode: PresentMode::Fifo,
+        }
+    }
+}
+
+impl SurfaceOptions {
+    /// The requested present mode if the surface supports it, otherwise `Fifo`, which every
+    /// surface does.
+    pub fn select_present_mode(&self, supported: &[PresentMode]) -> PresentMode {
+        if supported.contains(&self.present_mode) {
+            self.present_mode
+        } else {
+            PresentMode::Fifo
+        }
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.opacity < 1.0
+    }
+
+    /// Alpha mode to configure among those `supported` by the surface: opaque unless the
+    /// background is transparent, otherwise premultiplied alpha if possible.
+    pub fn alpha_mode(&self, supported: &[CompositeAlphaMode]) -> CompositeAlphaMode {
+        if !self.is_transparent() {
+            return CompositeAlphaMode::Opaque;
+        }
+        [
+            CompositeAlphaMode::PreMultiplied,
+            CompositeAlphaMode::PostMultiplied,
+        ]
+        .into_iter()
+        .find(|mode| supported.contains(mode))
+        .unwrap_or(CompositeAlphaMode::Opaque)
+    }
+}
+
+/// Color to clear the surface with for a `background` given in sRGB, brightened by `flash` for
+/// the visual bell, with `opacity` applied as `alpha_mode` expects it.
+pub fn clear_color(
+    background: (u8, u8, u8),
+    flash: f64,
+    opacity: f32,
+    alpha_mode: CompositeAlphaMode,
+) -> wgpu::Color {
+    let alpha = match alpha_mode {
+        CompositeAlphaMode::PreMultiplied | CompositeAlphaMode::PostMultiplied => {
+            opacity.clamp(0.0, 1.0) as f64
+        }
+        _ => 1.0,
+    };
+    let scale = match alpha_mode {
+        CompositeAlphaMode::PreMultiplied => alpha,
+        _ => 1.0,
+    };
+    // The surface format is sRGB, so the clear color is given in linear space.
+    let channel = |value: u8| {
+        let value = value as f64 / 255.0;
+        let linear = if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        };
+        (linear + flash).min(1.0) * scale
+    };
+    wgpu::Color {
+        r: channel(background.0),
+        g: channel(background.1),
+        b: channel(background.2),
+        a: alpha,
+    }
+}
+
+/// Why the window couldn't be set up for rendering.
+#[derive(Debug)]
+pub enum WindowError {
+    CreateSurface(CreateSurfaceError),
+    /// Neither a hardware nor a fallback adapter compatible with the surface was found.
+    NoAdapter,
+    RequestDevice(RequestDeviceError),
+}
+
+impl fmt::Display for WindowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CreateSurface(err) => write!(f, "create surface: {err}"),
+            Self::NoAdapter => write!(f, "no suitable graphics adapter found"),
+            Self::RequestDevice(err) => write!(f, "request device: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for WindowError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::CreateSurface(err) => Some(err),
+            Self::NoAdapter => None,
+            Self::RequestDevice(err) => Some(err),
+        }
+    }
+}
+
+impl From<CreateSurfaceError> for WindowError {
+    fn from(err: CreateSurfaceError) -> Self {
+        Self::CreateSurface(err)
+    }
+}
+
+impl From<RequestDeviceError> for WindowError {
+    fn from(err: RequestDeviceError) -> Self {
+        Self::RequestDevice(err)
+    }
+}
+
+/// What to do when the next frame can't be acquired from the surface.
+#[derive(Debug, PartialEq)]
+pub enum SurfaceRecovery {
+    /// The surface no longer matches the window, configure it again and redraw.
+    Reconfigure,
+    /// Transient, drop this frame.
+    Skip,
+    /// Not recoverable.
+    Exit,
+}
+
+impl SurfaceRecovery {
+    pub fn for_error(error: &SurfaceError) -> Self {
+        match error {
+            SurfaceError::Lost | SurfaceError::Outdated => Self::Reconfigure,
+            SurfaceError::Timeout => Self::Skip,
+            SurfaceError::OutOfMemory => Self::Exit,
+        }
+    }
+}
+
 pub struct WindowState {
     pub device: Device,
     pub queue: Queue,
@@ -21,6 +232,10 @@ pub struct WindowState {
     pub atlas: TextAtlas,
     pub text_renderer: TextRenderer,
     pub text_buffer: glyphon::Buffer,
+    /// Cell size in logical pixels, as configured.
+    pub cell_metrics: CellMetrics,
+    /// Physical pixels per logical pixel of the display the window is on.
+    pub scale_factor: f64,
 
     // Make sure that the winit window is last in the struct so that
     // it is dropped after the wgpu surface is dropped, otherwise the
@@ -29,41 +244,53 @@ pub struct WindowState {
 }
 
 impl WindowState {
-    pub async fn new(window: Arc<Window>) -> Self {
+    pub async fn new(
+        window: Arc<Window>,
+        font_family: &str,
+        cell_metrics: CellMetrics,
+        surface_options: SurfaceOptions,
+    ) -> Result<Self, WindowError> {
         let physical_size = window.inner_size();
         let scale_factor = window.scale_factor();
 
         let instance = Instance::new(InstanceDescriptor::default());
-        let adapter = instance
-            .request_adapter(&RequestAdapterOptions::default())
-            .await
-            .expect("request adapter");
+        let surface = instance.create_surface(window.clone())?;
+
+        // Fall back to a software adapter on machines without a usable GPU.
+        let mut options = RequestAdapterOptions {
+            compatible_surface: Some(&surface),
+            ..Default::default()
+        };
+        let adapter = match instance.request_adapter(&options).await {
+            Some(adapter) => adapter,
+            None => {
+                options.force_fallback_adapter = true;
+                instance
+                    .request_adapter(&options)
+                    .await
+                    .ok_or(WindowError::NoAdapter)?
+            }
+        };
         let (device, queue) = adapter
             .request_device(&DeviceDescriptor::default(), None)
-            .await
-            .expect("request device");
-
-        let surface = instance
-            .create_surface(window.clone())
-            .expect("create surface");
+            .await?;
 
         let swapchain_format = TextureFormat::Bgra8UnormSrgb;
+        let capabilities = surface.get_capabilities(&adapter);
         let surface_config = SurfaceConfiguration {
             usage: TextureUsages::RENDER_ATTACHMENT,
             format: swapchain_format,
             width: physical_size.width,
             height: physical_size.height,
-            present_mode: PresentMode::Fifo,
-            alpha_mode: CompositeAlphaMode::Opaque,
+            present_mode: surface_options.select_present_mode(&capabilities.present_modes),
+            alpha_mode: surface_options.alpha_mode(&capabilities.alpha_modes),
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
         };
         surface.configure(&device, &surface_config);
 
         let mut font_system = FontSystem::new();
-        font_system
-            .db_mut()
-            .set_monospace_family("JetBrains Mono NL");
+        font_system.db_mut().set_monospace_family(font_family);
 
         let swash_cache = SwashCache::new();
         let cache = glyphon::Cache::new(&device);
@@ -71,20 +298,19 @@ impl WindowState {
         let mut atlas = TextAtlas::new(&device, &queue, &cache, swapchain_format);
         let text_renderer =
             TextRenderer::new(&mut atlas, &device, MultisampleState::default(), None);
-        let mut text_buffer =
-            glyphon::Buffer::new(&mut font_system, glyphon::Metrics::new(30.0, 42.0));
-
-        let physical_width = (physical_size.width as f64 * scale_factor) as f32;
-        let physical_height = (physical_size.height as f64 * scale_factor) as f32;
-
+        // The inner size is already in physical pixels, only the font needs scaling.
+        let mut text_buffer = glyphon::Buffer::new(
+            &mut font_system,
+            cell_metrics.scaled(scale_factor).metrics(),
+        );
         text_buffer.set_size(
             &mut font_system,
-            Some(physical_width),
-            Some(physical_height),
+            Some(physical_size.width as f32),
+            Some(physical_size.height as f32),
         );
         text_buffer.shape_until_scroll(&mut font_system, false);
 
-        Self {
+        Ok(Self {
             device,
             queue,
             surface,
@@ -95,7 +321,201 @@ impl WindowState {
             atlas,
             text_renderer,
             text_buffer,
+            cell_metrics,
+            scale_factor,
             window,
+        })
+    }
+
+    /// Cell size in the physical pixels text is laid out and the surface is sized in.
+    pub fn physical_metrics(&self) -> CellMetrics {
+        self.cell_metrics.scaled(self.scale_factor)
+    }
+
+    /// Lays out text for a display with `scale_factor`, after the window moved to another one.
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+        let metrics = self.physical_metrics().metrics();
+        self.text_buffer.set_metrics(&mut self.font_system, metrics);
+    }
+
+    /// Fits the surface and the text layout to a window of `width` x `height` physical pixels.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.surface_config.width = width;
+        self.surface_config.height = height;
+        self.configure_surface();
+        self.text_buffer.set_size(
+            &mut self.font_system,
+            Some(width as f32),
+            Some(height as f32),
+        );
+    }
+
+    /// Applies `surface_config` to the surface. Skipped while the window has no area, e.g. when
+    /// minimized, as a zero sized surface can't be configured.
+    pub fn configure_surface(&self) {
+        if self.surface_config.width == 0 || self.surface_config.height == 0 {
+            return;
         }
+        self.surface.configure(&self.device, &self.surface_config);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn grid_size_fits_whole_cells() {
+        let cell_metrics = CellMetrics::default();
+        assert_eq!(cell_metrics.grid_size(180, 420), (10, 10));
+        assert_eq!(cell_metrics.grid_size(179, 419), (9, 9));
+        assert_eq!(cell_metrics.grid_size(0, 0), (1, 1));
+    }
+
+    #[test]
+    fn line_spacing_changes_rows() {
+        let cell_metrics = CellMetrics {
+            font_size: 20.0,
+            line_spacing: 1.0,
+        };
+        assert_eq!(cell_metrics.metrics(), glyphon::Metrics::new(20.0, 20.0));
+        assert_eq!(cell_metrics.grid_size(120, 400), (10, 20));
+
+        let loose = CellMetrics {
+            line_spacing: 2.0,
+            ..cell_metrics
+        };
+        assert_eq!(loose.metrics(), glyphon::Metrics::new(20.0, 40.0));
+        assert_eq!(loose.grid_size(120, 400), (10, 10));
+        assert_eq!(loose.surface_size(10, 10), (120, 400));
+    }
+
+    #[test]
+    fn scaled_metrics_are_physical() {
+        let cell_metrics = CellMetrics {
+            font_size: 20.0,
+            line_spacing: 1.0,
+        };
+        let hidpi = cell_metrics.scaled(2.0);
+        assert_eq!(hidpi.metrics(), glyphon::Metrics::new(40.0, 40.0));
+        assert_eq!(cell_metrics.surface_size(10, 5), (120, 100));
+        assert_eq!(hidpi.surface_size(10, 5), (240, 200));
+        // The same physical surface holds fewer, larger cells.
+        assert_eq!(hidpi.grid_size(240, 200), (10, 5));
+        assert_eq!(cell_metrics.grid_size(240, 200), (20, 10));
+
+        assert_eq!(cell_metrics.scaled(1.5).surface_size(10, 5), (180, 150));
+        assert_eq!(cell_metrics.scaled(1.0), cell_metrics);
+    }
+
+    #[test]
+    fn scale_factor_change_refits_grid() {
+        let cell_metrics = CellMetrics::default();
+        let grid = cell_metrics.grid_size(800, 600);
+
+        // winit suggests keeping the logical size, which keeps the grid.
+        assert_eq!(cell_metrics.scaled(2.0).grid_size(1600, 1200), grid);
+        // A window keeping its physical size loses half its columns and rows.
+        assert_eq!(
+            cell_metrics.scaled(2.0).grid_size(800, 600),
+            (grid.0 / 2, grid.1 / 2)
+        );
+        assert_eq!(cell_metrics.scaled(0.5).grid_size(800, 600), (88, 28));
+    }
+
+    #[test]
+    fn present_mode_falls_back_to_fifo() {
+        let supported = [PresentMode::Fifo, PresentMode::Mailbox];
+        let options = |present_mode| SurfaceOptions {
+            present_mode,
+            ..SurfaceOptions::default()
+        };
+
+        assert_eq!(
+            SurfaceOptions::default().select_present_mode(&supported),
+            PresentMode::Fifo
+        );
+        assert_eq!(
+            options(PresentMode::Mailbox).select_present_mode(&supported),
+            PresentMode::Mailbox
+        );
+        assert_eq!(
+            options(PresentMode::Immediate).select_present_mode(&supported),
+            PresentMode::Fifo
+        );
+    }
+
+    #[test]
+    fn transparent_background() {
+        let opaque = SurfaceOptions::default();
+        let translucent = SurfaceOptions {
+            opacity: 0.5,
+            ..SurfaceOptions::default()
+        };
+        let supported = [
+            CompositeAlphaMode::Opaque,
+            CompositeAlphaMode::PreMultiplied,
+        ];
+
+        assert_eq!(opaque.alpha_mode(&supported), CompositeAlphaMode::Opaque);
+        assert_eq!(
+            translucent.alpha_mode(&supported),
+            CompositeAlphaMode::PreMultiplied
+        );
+        assert_eq!(
+            translucent.alpha_mode(&[CompositeAlphaMode::Opaque]),
+            CompositeAlphaMode::Opaque
+        );
+
+        let color = |flash, opacity, mode| {
+            let color = clear_color((0, 0, 0), flash, opacity, mode);
+            (color.r, color.a)
+        };
+        assert_eq!(color(0.5, 1.0, CompositeAlphaMode::Opaque), (0.5, 1.0));
+        assert_eq!(color(0.5, 0.5, CompositeAlphaMode::Opaque), (0.5, 1.0));
+        assert_eq!(
+            color(0.5, 0.5, CompositeAlphaMode::PreMultiplied),
+            (0.25, 0.5)
+        );
+        assert_eq!(
+            color(0.5, 0.5, CompositeAlphaMode::PostMultiplied),
+            (0.5, 0.5)
+        );
+
+        let white = clear_color((255, 255, 255), 0.5, 1.0, CompositeAlphaMode::Opaque);
+        assert_eq!((white.r, white.g, white.b), (1.0, 1.0, 1.0));
+        let gray = clear_color((128, 128, 128), 0.0, 1.0, CompositeAlphaMode::Opaque);
+        assert!((gray.r - 0.2158).abs() < 1e-4, "{}", gray.r);
+    }
+
+    #[test]
+    fn window_error_messages() {
+        assert_eq!(
+            WindowError::NoAdapter.to_string(),
+            "no suitable graphics adapter found"
+        );
+        assert!(WindowError::NoAdapter.source().is_none());
+    }
+
+    #[test]
+    fn surface_error_recovery() {
+        assert_eq!(
+            SurfaceRecovery::for_error(&SurfaceError::Lost),
+            SurfaceRecovery::Reconfigure
+        );
+        assert_eq!(
+            SurfaceRecovery::for_error(&SurfaceError::Outdated),
+            SurfaceRecovery::Reconfigure
+        );
+        assert_eq!(
+            SurfaceRecovery::for_error(&SurfaceError::Timeout),
+            SurfaceRecovery::Skip
+        );
+        assert_eq!(
+            SurfaceRecovery::for_error(&SurfaceError::OutOfMemory),
+            SurfaceRecovery::Exit
+        );
     }
 }